@@ -0,0 +1,65 @@
+//! A stand-in for the CAN peripheral of a microcontroller HAL, e.g. `stm32f4xx-hal` or `esp-hal`.
+//! Replace it with the peripheral of your HAL, configured with a baudrate of 250 kbit/s.
+
+use embedded_can::{nb::Can, ErrorKind, Frame, Id};
+
+pub struct MockFrame {
+    id: Id,
+    dlc: usize,
+    data: [u8; 8],
+}
+
+impl Frame for MockFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        let mut frame = Self {
+            id: id.into(),
+            dlc: data.len(),
+            data: [0; 8],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<Self> {
+        None
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.id, Id::Extended(_))
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn dlc(&self) -> usize {
+        self.dlc
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data[..self.dlc]
+    }
+}
+
+/// A peripheral on an empty bus, frames are sent but nothing is received.
+pub struct MockCan;
+
+impl Can for MockCan {
+    type Frame = MockFrame;
+    type Error = ErrorKind;
+
+    fn transmit(&mut self, _frame: &MockFrame) -> nb::Result<Option<MockFrame>, ErrorKind> {
+        Ok(None)
+    }
+
+    fn receive(&mut self) -> nb::Result<MockFrame, ErrorKind> {
+        Err(nb::Error::WouldBlock)
+    }
+}
@@ -2,7 +2,7 @@
 #![no_std]
 
 extern crate alloc;
-use alloc::{string::ToString, vec};
+use alloc::{boxed::Box, string::ToString, vec};
 
 use embassy_executor::Spawner;
 use embassy_sync::{
@@ -11,6 +11,10 @@ use embassy_sync::{
 };
 use embassy_time::Instant;
 
+#[path = "../common/mock_hal.rs"]
+mod mock_hal;
+
+use open_isobus::drivers::EmbeddedCanDriver;
 use open_isobus::iso_11783_6::objects::*;
 use open_isobus::iso_11783_6::EventType;
 use open_isobus::iso_11783_6::ObjectPool;
@@ -131,8 +135,9 @@ async fn working_set_task(sender: DynamicSender<'static, bool>) {
         macro_refs: vec![],
     }));
 
-    // Create a new working set instance.
-    let mut ws = WorkingSet::new(op);
+    // Create a new working set instance on the CAN peripheral of the HAL.
+    let can = mock_hal::MockCan;
+    let mut ws = WorkingSet::with_driver(op, Box::new(EmbeddedCanDriver::new(can)));
     let startup_time = Instant::now();
 
     let mut is_active = false;
@@ -3,9 +3,13 @@
 // #![no_main]
 use std::time::Instant;
 
+#[path = "../common/mock_hal.rs"]
+mod mock_hal;
+
 extern crate alloc;
-use alloc::{string::ToString, vec};
+use alloc::{boxed::Box, string::ToString, vec};
 
+use open_isobus::drivers::EmbeddedCanDriver;
 use open_isobus::iso_11783_6::objects::*;
 use open_isobus::iso_11783_6::EventType;
 use open_isobus::iso_11783_6::ObjectPool;
@@ -103,8 +107,9 @@ fn main() {
         macro_refs: vec![],
     }));
 
-    // Create a new working set instance.
-    let mut ws = WorkingSet::new(op);
+    // Create a new working set instance on the CAN peripheral of the HAL.
+    let can = mock_hal::MockCan;
+    let mut ws = WorkingSet::with_driver(op, Box::new(EmbeddedCanDriver::new(can)));
     let startup_time = Instant::now();
 
     let mut is_active = false;
@@ -0,0 +1,183 @@
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+
+use crate::{
+    iso_11783_3::{PDU, PGN},
+    iso_11783_5::Name,
+};
+
+/// The queue of a listener, the received PDUs are taken with `Isobus::receive`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Listener(usize);
+
+/// Called with each received PDU of the PGNs it is registered for.
+pub type PduCallback = Box<dyn FnMut(&PDU)>;
+
+enum Sink {
+    /// The received PDUs and the number of PDUs dropped because the queue was full.
+    Queue(VecDeque<PDU>, usize),
+    Callback(PduCallback),
+}
+
+struct Route {
+    pgns: Vec<PGN>,
+    /// Only PDUs from the control function with this NAME.
+    source: Option<Name>,
+    sink: Sink,
+}
+
+/// Routes the received PDUs by PGN to the queues and callbacks of the components sharing an `Isobus`.
+/// A PDU is passed to every route of its PGN, in the order the routes were added.
+#[derive(Default)]
+pub struct Dispatcher {
+    routes: Vec<Route>,
+}
+
+impl Dispatcher {
+    /// The number of PDUs a listener holds, the oldest are dropped when it is not drained.
+    pub const LISTENER_CAPACITY: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the PDUs of the PGNs, optionally only those sent by the NAME.
+    pub fn listen(&mut self, pgns: &[PGN], source: Option<Name>) -> Listener {
+        self.routes.push(Route {
+            pgns: pgns.to_vec(),
+            source,
+            sink: Sink::Queue(VecDeque::new(), 0),
+        });
+        Listener(self.routes.len() - 1)
+    }
+
+    /// Call the callback with the PDUs of the PGNs, optionally only those sent by the NAME.
+    pub fn on_receive(&mut self, pgns: &[PGN], source: Option<Name>, callback: PduCallback) {
+        self.routes.push(Route {
+            pgns: pgns.to_vec(),
+            source,
+            sink: Sink::Callback(callback),
+        });
+    }
+
+    /// The next PDU queued for the listener.
+    pub fn receive(&mut self, listener: Listener) -> Option<PDU> {
+        match &mut self.routes.get_mut(listener.0)?.sink {
+            Sink::Queue(queue, _) => queue.pop_front(),
+            Sink::Callback(_) => None,
+        }
+    }
+
+    /// The number of PDUs dropped because the queue of the listener was full.
+    pub fn dropped(&self, listener: Listener) -> usize {
+        match self.routes.get(listener.0).map(|r| &r.sink) {
+            Some(Sink::Queue(_, dropped)) => *dropped,
+            _ => 0,
+        }
+    }
+
+    /// Pass the PDU to the routes of its PGN, `source` is the NAME of the sender when it is known.
+    /// Returns false when no route took the PDU.
+    pub fn dispatch(&mut self, pdu: &PDU, source: Option<Name>) -> bool {
+        let pgn = pdu.pgn();
+        let mut dispatched = false;
+        for route in self.routes.iter_mut() {
+            if !route.pgns.contains(&pgn) {
+                continue;
+            }
+            if route.source.is_some() && route.source != source {
+                continue;
+            }
+
+            match &mut route.sink {
+                Sink::Queue(queue, dropped) => {
+                    if queue.len() == Self::LISTENER_CAPACITY {
+                        queue.pop_front();
+                        *dropped += 1;
+                    }
+                    queue.push_back(pdu.clone());
+                }
+                Sink::Callback(callback) => callback(pdu),
+            }
+            dispatched = true;
+        }
+        dispatched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, rc::Rc};
+    use core::cell::Cell;
+
+    use crate::{
+        iso_11783_3::{PDU, PGN},
+        iso_11783_5::Name,
+        isobus::IsobusAddress,
+    };
+
+    use super::Dispatcher;
+
+    #[test]
+    fn routes_pdus_by_pgn_and_source() {
+        let mut dispatcher = Dispatcher::new();
+        let vt = Name::from(0xA000_0000_0000_0026);
+        let tc = Name::from(0xA000_0000_0000_00F7);
+        let vt_to_ecu = PGN::new(0xE600);
+        let process_data = PGN::new(0xCB00);
+
+        let all = dispatcher.listen(&[vt_to_ecu, process_data], None);
+        let from_vt = dispatcher.listen(&[vt_to_ecu], Some(vt));
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        dispatcher.on_receive(
+            &[process_data],
+            Some(tc),
+            Box::new(move |_| counter.set(counter.get() + 1)),
+        );
+
+        let pdu = |pgn: PGN| PDU::with_pgn(7, pgn, IsobusAddress(128), IsobusAddress(38), [0; 8]);
+        dispatcher.dispatch(&pdu(vt_to_ecu), Some(vt));
+        dispatcher.dispatch(&pdu(process_data), Some(tc));
+        dispatcher.dispatch(&pdu(process_data), None);
+        dispatcher.dispatch(&pdu(PGN::new(0xFECA)), Some(vt));
+
+        assert_eq!(dispatcher.receive(all).map(|p| p.pgn()), Some(vt_to_ecu));
+        assert_eq!(dispatcher.receive(all).map(|p| p.pgn()), Some(process_data));
+        assert_eq!(dispatcher.receive(all).map(|p| p.pgn()), Some(process_data));
+        assert!(dispatcher.receive(all).is_none());
+        assert_eq!(
+            dispatcher.receive(from_vt).map(|p| p.pgn()),
+            Some(vt_to_ecu)
+        );
+        assert!(dispatcher.receive(from_vt).is_none());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn full_listeners_drop_the_oldest_pdus() {
+        let mut dispatcher = Dispatcher::new();
+        let pgn = PGN::new(0xE600);
+        let listener = dispatcher.listen(&[pgn], None);
+
+        for i in 0..Dispatcher::LISTENER_CAPACITY + 2 {
+            let pdu = PDU::with_pgn(7, pgn, IsobusAddress(128), IsobusAddress(38), [i as u8; 8]);
+            assert!(dispatcher.dispatch(&pdu, None));
+        }
+        let unrouted = PDU::with_pgn(
+            7,
+            PGN::new(0xFECA),
+            IsobusAddress(128),
+            IsobusAddress(38),
+            [0; 8],
+        );
+        assert!(!dispatcher.dispatch(&unrouted, None));
+
+        assert_eq!(dispatcher.dropped(listener), 2);
+        assert_eq!(
+            dispatcher.receive(listener).map(|p| p.data::<1>()),
+            Some([2])
+        );
+        let remaining = core::iter::from_fn(|| dispatcher.receive(listener)).count();
+        assert_eq!(remaining, Dispatcher::LISTENER_CAPACITY - 1);
+    }
+}
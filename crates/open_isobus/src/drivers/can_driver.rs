@@ -1,4 +1,4 @@
-use alloc::{string::String, vec::Vec};
+use alloc::string::String;
 use core::fmt::Display;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -9,7 +9,7 @@ pub enum Baudrate {
     Baud125K,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum CanError {
     Uninitialised,
     NoDriver,
@@ -19,33 +19,78 @@ pub enum CanError {
     Crc,
     Form,
     Acknowledge,
+    ErrorPassive,
+    BusOff,
     Other(String),
 }
 
+/// The error state of the CAN controller.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BusState {
+    /// The driver is not open.
+    Closed,
+    /// Normal operation, the controller takes part in the bus communication.
+    ErrorActive,
+    /// The controller has seen too many errors, it still communicates but can no longer signal errors.
+    ErrorPassive,
+    /// The controller has disconnected itself from the bus, no frames are sent or received.
+    BusOff,
+}
+
+/// An acceptance filter for extended frames, a frame is accepted when `frame_id & mask == id & mask`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CanFilter {
+    pub id: u32,
+    pub mask: u32,
+}
+
+impl CanFilter {
+    pub fn new(id: u32, mask: u32) -> Self {
+        Self { id, mask }
+    }
+
+    pub fn matches(&self, id: Id) -> bool {
+        match id {
+            Id::Standard(_) => false,
+            Id::Extended(id) => id.as_raw() & self.mask == self.id & self.mask,
+        }
+    }
+}
+
 pub trait CanDriverTrait {
     fn init(&mut self);
-    fn open(&mut self, baudrate: Option<Baudrate>);
+    fn open(&mut self, baudrate: Option<Baudrate>) -> Result<(), CanError>;
     fn close(&mut self);
-    fn read(&mut self) -> Option<CanFrame>;
-    fn write(&mut self, frame: CanFrame);
+    /// Read the next received frame, returns `WouldBlock` when no frame is available.
+    fn read(&mut self) -> nb::Result<CanFrame, CanError>;
+    /// Write a frame, returns `WouldBlock` when the transmit buffer is full.
+    fn write(&mut self, frame: CanFrame) -> nb::Result<(), CanError>;
+    fn state(&self) -> BusState;
+
+    /// Only receive extended frames matching any of the filters, an empty list accepts all frames.
+    /// Drivers without hardware filters accept all frames, the `DataLinkLayer` also filters in software.
+    fn set_filters(&mut self, _filters: &[CanFilter]) -> Result<(), CanError> {
+        Ok(())
+    }
+
+    /// Called by the `DataLinkLayer` with the current time in ms, before reading or writing frames.
+    fn set_time(&mut self, _time: u64) {}
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct CanFrame {
     id: Id,
     dlc: usize,
-    data: Vec<u8>, // TODO; A can frame will always have <= 8 data bytes!
+    data: [u8; 8],
 }
 
 impl CanFrame {
+    /// Data longer than 8 bytes is truncated.
     pub fn new(id: impl Into<Id>, data: &[u8]) -> Self {
         let id = id.try_into().unwrap_or(Id::Extended(ExtendedId::MAX));
         let dlc = usize::min(data.len(), 8);
-        let mut temp_data: Vec<u8> = Vec::new();
-
-        if let Some(d) = data.get(0..dlc) {
-            temp_data.extend_from_slice(d);
-        }
+        let mut temp_data = [0u8; 8];
+        temp_data[..dlc].copy_from_slice(&data[..dlc]);
 
         Self {
             id,
@@ -70,7 +115,7 @@ impl CanFrame {
     }
 
     pub fn data(&self) -> &[u8] {
-        &self.data
+        &self.data[..self.dlc]
     }
 }
 
@@ -81,7 +126,7 @@ impl Display for CanFrame {
             "0x{:08X}, {}, {:02X?}",
             self.id().as_raw(),
             self.dlc(),
-            &self.data
+            self.data()
         )
     }
 }
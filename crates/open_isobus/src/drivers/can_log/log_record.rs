@@ -0,0 +1,247 @@
+use alloc::format;
+use core::fmt::Write;
+
+use crate::drivers::can_driver::{CanFrame, ExtendedId, Id, StandardId};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LogFormat {
+    /// The format of `candump -l`, e.g. `(1436509052.249713) can0 18EAFF80#00EE00`.
+    Candump,
+    /// The Vector ASC format, e.g. `0.249713 1 18EAFF80x Rx d 3 00 EE 00`.
+    Asc,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+/// A single frame in a CAN log.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LogRecord {
+    /// Timestamp in µs.
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub frame: CanFrame,
+}
+
+impl LogRecord {
+    pub fn new(timestamp: u64, direction: Direction, frame: CanFrame) -> Self {
+        Self {
+            timestamp,
+            direction,
+            frame,
+        }
+    }
+
+    /// Parse a single line of the given format.
+    /// Returns `None` for headers, comments, events and remote or CAN FD frames.
+    pub fn parse(format: LogFormat, line: &str) -> Option<LogRecord> {
+        match format {
+            LogFormat::Candump => Self::parse_candump(line),
+            LogFormat::Asc => Self::parse_asc(line),
+        }
+    }
+
+    /// Write the record as a single line of the given format.
+    /// The `channel` is the interface name for candump, and the channel number for ASC.
+    pub fn write(&self, format: LogFormat, channel: &str, w: &mut impl Write) -> core::fmt::Result {
+        let seconds = self.timestamp / 1_000_000;
+        let micros = self.timestamp % 1_000_000;
+
+        match format {
+            LogFormat::Candump => {
+                write!(w, "({seconds:010}.{micros:06}) {channel} ")?;
+                match self.frame.id() {
+                    Id::Standard(id) => write!(w, "{:03X}#", id.as_raw())?,
+                    Id::Extended(id) => write!(w, "{:08X}#", id.as_raw())?,
+                }
+                for b in self.frame.data() {
+                    write!(w, "{b:02X}")?;
+                }
+            }
+            LogFormat::Asc => {
+                write!(w, "{seconds:>4}.{micros:06} {channel:<2} ")?;
+                match self.frame.id() {
+                    Id::Standard(id) => write!(w, "{:<15X}", id.as_raw())?,
+                    Id::Extended(id) => write!(w, "{:<15}", format!("{:X}x", id.as_raw()))?,
+                }
+                let direction = match self.direction {
+                    Direction::Rx => "Rx",
+                    Direction::Tx => "Tx",
+                };
+                write!(w, " {direction}   d {}", self.frame.dlc())?;
+                for b in self.frame.data() {
+                    write!(w, " {b:02X}")?;
+                }
+            }
+        }
+        writeln!(w)
+    }
+
+    fn parse_candump(line: &str) -> Option<LogRecord> {
+        let mut parts = line.split_whitespace();
+
+        let timestamp = parts.next()?.strip_prefix('(')?.strip_suffix(')')?;
+        let _interface = parts.next()?;
+        let (id, data) = parts.next()?.split_once('#')?;
+
+        // Remote frames and CAN FD frames are not used by ISOBUS.
+        if data.starts_with('R') || data.starts_with('#') || data.len() % 2 != 0 {
+            return None;
+        }
+
+        let raw = u32::from_str_radix(id, 16).ok()?;
+        let id = if id.len() > 3 {
+            Id::Extended(ExtendedId::new(raw)?)
+        } else {
+            Id::Standard(StandardId::new(raw)?)
+        };
+
+        let mut bytes = [0u8; 8];
+        let len = data.len() / 2;
+        if len > 8 {
+            return None;
+        }
+        for (i, b) in bytes.iter_mut().take(len).enumerate() {
+            *b = u8::from_str_radix(data.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+
+        Some(LogRecord::new(
+            parse_timestamp(timestamp)?,
+            Direction::Rx,
+            CanFrame::new(id, &bytes[..len]),
+        ))
+    }
+
+    fn parse_asc(line: &str) -> Option<LogRecord> {
+        let mut parts = line.split_whitespace();
+
+        let timestamp = parse_timestamp(parts.next()?)?;
+        let _channel = parts.next()?.parse::<u8>().ok()?;
+
+        let id = parts.next()?;
+        let id = match id.strip_suffix('x') {
+            Some(id) => Id::Extended(ExtendedId::new(u32::from_str_radix(id, 16).ok()?)?),
+            None => Id::Standard(StandardId::new(u32::from_str_radix(id, 16).ok()?)?),
+        };
+
+        let direction = match parts.next()? {
+            "Rx" => Direction::Rx,
+            "Tx" => Direction::Tx,
+            _ => return None,
+        };
+
+        // Remote frames are marked with `r` instead of `d`.
+        if parts.next()? != "d" {
+            return None;
+        }
+
+        let dlc = parts.next()?.parse::<usize>().ok()?;
+        if dlc > 8 {
+            return None;
+        }
+        let mut bytes = [0u8; 8];
+        for b in bytes.iter_mut().take(dlc) {
+            *b = u8::from_str_radix(parts.next()?, 16).ok()?;
+        }
+
+        Some(LogRecord::new(
+            timestamp,
+            direction,
+            CanFrame::new(id, &bytes[..dlc]),
+        ))
+    }
+}
+
+/// Parse a timestamp in seconds with an optional fraction, into µs.
+fn parse_timestamp(value: &str) -> Option<u64> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let seconds: u64 = seconds.parse().ok()?;
+
+    let mut micros: u64 = 0;
+    for i in 0..6 {
+        let digit = match fraction.as_bytes().get(i) {
+            Some(d) if d.is_ascii_digit() => (d - b'0') as u64,
+            Some(_) => return None,
+            None => 0,
+        };
+        micros = micros * 10 + digit;
+    }
+
+    Some(seconds * 1_000_000 + micros)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use crate::drivers::can_driver::CanFrame;
+
+    use super::{Direction, LogFormat, LogRecord};
+
+    #[test]
+    fn parse_candump() {
+        assert_eq!(
+            LogRecord::parse(
+                LogFormat::Candump,
+                "(1436509052.249713) can0 18EAFF80#00EE00"
+            ),
+            Some(LogRecord::new(
+                1_436_509_052_249_713,
+                Direction::Rx,
+                CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00])
+            ))
+        );
+        assert_eq!(
+            LogRecord::parse(LogFormat::Candump, "(1436509052.249713) can0 123#R"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_asc() {
+        assert_eq!(
+            LogRecord::parse(
+                LogFormat::Asc,
+                "   1.250000 1  18EAFF80x       Tx   d 3 00 EE 00"
+            ),
+            Some(LogRecord::new(
+                1_250_000,
+                Direction::Tx,
+                CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00])
+            ))
+        );
+        assert_eq!(
+            LogRecord::parse(LogFormat::Asc, "base hex  timestamps absolute"),
+            None
+        );
+    }
+
+    #[test]
+    fn write_and_parse() {
+        let records = [
+            LogRecord::new(
+                12_345_678,
+                Direction::Rx,
+                CanFrame::new(0x0CFE6CEE, &[1, 2, 3, 4, 5, 6, 7, 8]),
+            ),
+            LogRecord::new(12_345_679, Direction::Tx, CanFrame::new(0x123, &[])),
+        ];
+
+        for format in [LogFormat::Candump, LogFormat::Asc] {
+            for record in &records {
+                let mut line = String::new();
+                record.write(format, "1", &mut line).unwrap();
+
+                let mut parsed = LogRecord::parse(format, &line).unwrap();
+                if format == LogFormat::Candump {
+                    // Candump does not log the direction.
+                    parsed.direction = record.direction;
+                }
+                assert_eq!(&parsed, record);
+            }
+        }
+    }
+}
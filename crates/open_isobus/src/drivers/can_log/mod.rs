@@ -0,0 +1,10 @@
+mod log_record;
+pub use log_record::{Direction, LogFormat, LogRecord};
+
+mod replay_can_driver;
+pub use replay_can_driver::ReplayCanDriver;
+
+mod recording_can_driver;
+#[cfg(feature = "std")]
+pub use recording_can_driver::IoWriter;
+pub use recording_can_driver::RecordingCanDriver;
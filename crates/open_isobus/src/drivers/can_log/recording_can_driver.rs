@@ -0,0 +1,171 @@
+use {
+    super::{Direction, LogFormat, LogRecord},
+    crate::drivers::{
+        can_driver::{Baudrate, BusState, CanError, CanFilter, CanFrame},
+        CanDriverTrait,
+    },
+    alloc::{
+        boxed::Box,
+        string::{String, ToString},
+    },
+    core::fmt::Write,
+};
+
+/// Wraps a driver and writes all read and written frames to a CAN log.
+/// Timestamps are taken from the `process(time)` clock.
+pub struct RecordingCanDriver<W: Write> {
+    driver: Box<dyn CanDriverTrait>,
+    writer: W,
+    format: LogFormat,
+    channel: String,
+    time: u64,
+}
+
+impl<W: Write> RecordingCanDriver<W> {
+    /// The `channel` is the interface name for candump, e.g. `can0`, and the channel number for ASC.
+    pub fn new(
+        driver: Box<dyn CanDriverTrait>,
+        writer: W,
+        format: LogFormat,
+        channel: &str,
+    ) -> Self {
+        let mut recorder = Self {
+            driver,
+            writer,
+            format,
+            channel: channel.to_string(),
+            time: 0,
+        };
+
+        if format == LogFormat::Asc {
+            // There is no wall clock available, the timestamps are relative to the start of the clock.
+            let header = "date Thu Jan  1 00:00:00.000 am 1970\n\
+                          base hex  timestamps absolute\n\
+                          no internal events logged\n";
+            if let Err(e) = recorder.writer.write_str(header) {
+                log::error!("Unable to write CAN log: \"{e:?}\"");
+            }
+        }
+
+        recorder
+    }
+
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    fn record(&mut self, direction: Direction, frame: &CanFrame) {
+        let record = LogRecord::new(self.time * 1000, direction, *frame);
+        if let Err(e) = record.write(self.format, &self.channel, &mut self.writer) {
+            log::error!("Unable to write CAN log: \"{e:?}\"");
+        }
+    }
+}
+
+impl<W: Write> CanDriverTrait for RecordingCanDriver<W> {
+    fn init(&mut self) {
+        self.driver.init();
+    }
+
+    fn open(&mut self, baudrate: Option<Baudrate>) -> Result<(), CanError> {
+        self.driver.open(baudrate)
+    }
+
+    fn close(&mut self) {
+        self.driver.close();
+    }
+
+    fn read(&mut self) -> nb::Result<CanFrame, CanError> {
+        let frame = self.driver.read()?;
+        self.record(Direction::Rx, &frame);
+        Ok(frame)
+    }
+
+    fn write(&mut self, frame: CanFrame) -> nb::Result<(), CanError> {
+        // Only record frames that were accepted by the driver.
+        self.driver.write(frame)?;
+        self.record(Direction::Tx, &frame);
+        Ok(())
+    }
+
+    fn state(&self) -> BusState {
+        self.driver.state()
+    }
+
+    fn set_time(&mut self, time: u64) {
+        self.time = time;
+        self.driver.set_time(time);
+    }
+
+    fn set_filters(&mut self, filters: &[CanFilter]) -> Result<(), CanError> {
+        self.driver.set_filters(filters)
+    }
+}
+
+/// Adapts a `std::io::Write`, like a `File`, to be used as the writer of a `RecordingCanDriver`.
+#[cfg(feature = "std")]
+pub struct IoWriter<W: std::io::Write>(pub W);
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.write_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, string::String, vec::Vec};
+
+    use crate::drivers::{
+        can_driver::CanFrame,
+        can_log::{Direction, LogFormat, LogRecord},
+        CanDriverTrait, VirtualCanBus,
+    };
+
+    use super::RecordingCanDriver;
+
+    #[test]
+    fn records_read_and_written_frames() {
+        let bus = VirtualCanBus::new();
+        let mut peer = bus.connect();
+        let mut recorder =
+            RecordingCanDriver::new(Box::new(bus.connect()), String::new(), LogFormat::Asc, "1");
+        peer.open(None).unwrap();
+        recorder.open(None).unwrap();
+
+        recorder.set_time(1250);
+        recorder
+            .write(CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00]))
+            .unwrap();
+        peer.write(CanFrame::new(0x0CFE6CEE, &[1, 2, 3, 4, 5, 6, 7, 8]))
+            .unwrap();
+        recorder.set_time(2500);
+        assert!(recorder.read().is_ok());
+
+        let log = recorder.into_writer();
+        let records: Vec<LogRecord> = log
+            .lines()
+            .filter_map(|line| LogRecord::parse(LogFormat::Asc, line))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                LogRecord::new(
+                    1_250_000,
+                    Direction::Tx,
+                    CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00])
+                ),
+                LogRecord::new(
+                    2_500_000,
+                    Direction::Rx,
+                    CanFrame::new(0x0CFE6CEE, &[1, 2, 3, 4, 5, 6, 7, 8])
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use {
+    super::{LogFormat, LogRecord},
+    crate::drivers::{
+        can_driver::{Baudrate, BusState, CanError, CanFrame},
+        CanDriverTrait,
+    },
+    alloc::collections::VecDeque,
+};
+
+/// Replays a CAN log, honouring the original timestamps relative to the `process(time)` clock.
+/// The first frame of the log is available at the first read after opening the driver.
+/// Written frames are discarded.
+pub struct ReplayCanDriver {
+    records: VecDeque<LogRecord>,
+    /// The log timestamp in µs and the clock time in ms at which the replay started.
+    start: Option<(u64, u64)>,
+    time: u64,
+    open: bool,
+}
+
+impl ReplayCanDriver {
+    pub fn new(records: impl IntoIterator<Item = LogRecord>) -> Self {
+        Self {
+            records: records.into_iter().collect(),
+            start: None,
+            time: 0,
+            open: false,
+        }
+    }
+
+    /// Replay a log of the given format, lines that are not a CAN frame are skipped.
+    pub fn from_log(format: LogFormat, log: &str) -> Self {
+        Self::new(log.lines().filter_map(|l| LogRecord::parse(format, l)))
+    }
+
+    /// Returns `true` when all frames have been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+impl CanDriverTrait for ReplayCanDriver {
+    fn init(&mut self) {}
+
+    fn open(&mut self, _baudrate: Option<Baudrate>) -> Result<(), CanError> {
+        self.open = true;
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+        self.start = None;
+    }
+
+    fn read(&mut self) -> nb::Result<CanFrame, CanError> {
+        if !self.open {
+            return Err(nb::Error::Other(CanError::Uninitialised));
+        }
+
+        let record = self.records.front().ok_or(nb::Error::WouldBlock)?;
+        let (log_start, time_start) = *self.start.get_or_insert((record.timestamp, self.time));
+
+        let elapsed = self.time.saturating_sub(time_start) * 1000;
+        if record.timestamp.saturating_sub(log_start) > elapsed {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.records
+            .pop_front()
+            .map(|r| r.frame)
+            .ok_or(nb::Error::WouldBlock)
+    }
+
+    fn write(&mut self, _frame: CanFrame) -> nb::Result<(), CanError> {
+        if !self.open {
+            return Err(nb::Error::Other(CanError::Uninitialised));
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> BusState {
+        if self.open {
+            BusState::ErrorActive
+        } else {
+            BusState::Closed
+        }
+    }
+
+    fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drivers::{can_driver::CanFrame, can_log::LogFormat, CanDriverTrait};
+
+    use super::ReplayCanDriver;
+
+    #[test]
+    fn replay_honours_timestamps() {
+        let log = "(1436509052.200000) can0 18EAFF80#00EE00\n\
+                   (1436509052.300000) can0 18EEFF80#0102030405060708\n";
+        let mut driver = ReplayCanDriver::from_log(LogFormat::Candump, log);
+        driver.open(None).unwrap();
+
+        driver.set_time(5000);
+        assert_eq!(
+            driver.read(),
+            Ok(CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00]))
+        );
+        assert_eq!(driver.read(), Err(nb::Error::WouldBlock));
+
+        driver.set_time(5099);
+        assert_eq!(driver.read(), Err(nb::Error::WouldBlock));
+
+        driver.set_time(5100);
+        assert_eq!(
+            driver.read(),
+            Ok(CanFrame::new(0x18EEFF80, &[1, 2, 3, 4, 5, 6, 7, 8]))
+        );
+        assert!(driver.is_finished());
+    }
+}
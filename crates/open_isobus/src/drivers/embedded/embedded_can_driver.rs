@@ -0,0 +1,369 @@
+use {
+    crate::drivers::{
+        can_driver::{
+            Baudrate, BusState, CanError, CanFilter, CanFrame, ExtendedId, Id, StandardId,
+        },
+        CanDriverTrait,
+    },
+    alloc::{collections::VecDeque, format},
+    embedded_can::{nb::Can, ErrorKind, Frame},
+};
+
+/// Applies acceptance filters to the mailboxes of a peripheral.
+pub type FilterFn<C> = fn(&mut C, &[CanFilter]) -> Result<(), CanError>;
+
+/// A driver for any CAN peripheral implementing [`embedded_can::nb::Can`],
+/// e.g. from a STM32 or ESP32 HAL.
+///
+/// The peripheral, including its baudrate, is configured by the HAL before it is given to the driver.
+/// The HAL does not report the error state of the controller, so the bus state is only open or closed.
+/// `embedded_can` has no acceptance filters, use [`EmbeddedCanDriver::filter_fn`] to configure the mailboxes.
+pub struct EmbeddedCanDriver<C: Can> {
+    can: C,
+    open: bool,
+    filter_fn: Option<FilterFn<C>>,
+    /// Pending frames replaced by a higher priority frame, these are transmitted again first.
+    replaced: VecDeque<C::Frame>,
+}
+
+impl<C: Can> EmbeddedCanDriver<C> {
+    pub fn new(can: C) -> Self {
+        Self {
+            can,
+            open: false,
+            filter_fn: None,
+            replaced: VecDeque::new(),
+        }
+    }
+
+    /// Apply the acceptance filters of the stack to the peripheral with a HAL specific function.
+    pub fn filter_fn(mut self, f: FilterFn<C>) -> Self {
+        self.filter_fn = Some(f);
+        self
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.can
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.can
+    }
+
+    /// Release the peripheral.
+    pub fn free(self) -> C {
+        self.can
+    }
+
+    fn transmit(&mut self, frame: &C::Frame) -> nb::Result<(), CanError> {
+        match self.can.transmit(frame) {
+            Ok(Some(replaced)) => {
+                self.replaced.push_back(replaced);
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+            Err(nb::Error::Other(e)) => Err(nb::Error::Other(to_can_error(e))),
+        }
+    }
+
+    fn transmit_replaced(&mut self) {
+        for _ in 0..self.replaced.len() {
+            let frame = match self.replaced.pop_front() {
+                Some(frame) => frame,
+                None => return,
+            };
+
+            if self.transmit(&frame).is_err() {
+                self.replaced.push_front(frame);
+                return;
+            }
+        }
+    }
+}
+
+impl<C: Can> CanDriverTrait for EmbeddedCanDriver<C> {
+    fn init(&mut self) {}
+
+    fn open(&mut self, baudrate: Option<Baudrate>) -> Result<(), CanError> {
+        if let Some(baudrate) = baudrate {
+            log::warn!("The baudrate is configured by the HAL, ignoring {baudrate:?}");
+        }
+        self.open = true;
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+        self.replaced.clear();
+    }
+
+    fn read(&mut self) -> nb::Result<CanFrame, CanError> {
+        if !self.open {
+            return Err(nb::Error::Other(CanError::Uninitialised));
+        }
+
+        self.transmit_replaced();
+
+        loop {
+            let frame = self.can.receive().map_err(|e| e.map(to_can_error))?;
+
+            // Remote frames are not used by ISOBUS.
+            if frame.is_data_frame() {
+                return Ok(CanFrame::new(Id::from(frame.id()), frame.data()));
+            }
+        }
+    }
+
+    fn write(&mut self, frame: CanFrame) -> nb::Result<(), CanError> {
+        #[cfg(feature = "log_can_write")]
+        log::debug!("send: {}", &frame);
+
+        if !self.open {
+            return Err(nb::Error::Other(CanError::Uninitialised));
+        }
+
+        let frame = C::Frame::new(embedded_can::Id::from(frame.id()), frame.data()).ok_or(
+            nb::Error::Other(CanError::Other(format!("Invalid frame: {frame}"))),
+        )?;
+
+        self.transmit_replaced();
+        self.transmit(&frame)
+    }
+
+    fn state(&self) -> BusState {
+        if self.open {
+            BusState::ErrorActive
+        } else {
+            BusState::Closed
+        }
+    }
+
+    fn set_filters(&mut self, filters: &[CanFilter]) -> Result<(), CanError> {
+        match self.filter_fn {
+            Some(f) => f(&mut self.can, filters),
+            None => Ok(()),
+        }
+    }
+}
+
+fn to_can_error(e: impl embedded_can::Error) -> CanError {
+    match e.kind() {
+        ErrorKind::Overrun => CanError::Overrun,
+        ErrorKind::Bit => CanError::Bit,
+        ErrorKind::Stuff => CanError::Stuff,
+        ErrorKind::Crc => CanError::Crc,
+        ErrorKind::Form => CanError::Form,
+        ErrorKind::Acknowledge => CanError::Acknowledge,
+        _ => CanError::Other(format!("{e:?}")),
+    }
+}
+
+impl From<embedded_can::Id> for Id {
+    fn from(id: embedded_can::Id) -> Self {
+        match id {
+            embedded_can::Id::Standard(id) => {
+                Id::Standard(StandardId::new(id.as_raw() as u32).unwrap_or(StandardId::MAX))
+            }
+            embedded_can::Id::Extended(id) => {
+                Id::Extended(ExtendedId::new(id.as_raw()).unwrap_or(ExtendedId::MAX))
+            }
+        }
+    }
+}
+
+impl From<Id> for embedded_can::Id {
+    fn from(id: Id) -> Self {
+        match id {
+            Id::Standard(id) => embedded_can::Id::Standard(
+                embedded_can::StandardId::new(id.as_raw() as u16)
+                    .unwrap_or(embedded_can::StandardId::MAX),
+            ),
+            Id::Extended(id) => embedded_can::Id::Extended(
+                embedded_can::ExtendedId::new(id.as_raw()).unwrap_or(embedded_can::ExtendedId::MAX),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+
+    use embedded_can::{nb::Can, ErrorKind, ExtendedId, Frame, Id};
+
+    use crate::{
+        drivers::{can_driver::CanFrame, CanDriverTrait},
+        iso_11783_5::Name,
+        Isobus, IsobusAddress,
+    };
+
+    use super::EmbeddedCanDriver;
+
+    #[derive(Debug, PartialEq, Clone)]
+    struct MockFrame {
+        id: Id,
+        remote: bool,
+        dlc: usize,
+        data: [u8; 8],
+    }
+
+    impl Frame for MockFrame {
+        fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+            let mut frame = Self::new_remote(id, data.len())?;
+            frame.remote = false;
+            frame.data[..data.len()].copy_from_slice(data);
+            Some(frame)
+        }
+
+        fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+            if dlc > 8 {
+                return None;
+            }
+            Some(Self {
+                id: id.into(),
+                remote: true,
+                dlc,
+                data: [0; 8],
+            })
+        }
+
+        fn is_extended(&self) -> bool {
+            matches!(self.id, Id::Extended(_))
+        }
+
+        fn is_remote_frame(&self) -> bool {
+            self.remote
+        }
+
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn dlc(&self) -> usize {
+            self.dlc
+        }
+
+        fn data(&self) -> &[u8] {
+            if self.remote {
+                &[]
+            } else {
+                &self.data[..self.dlc]
+            }
+        }
+    }
+
+    /// A peripheral with a single transmit mailbox, like the bxCAN of a STM32 with two mailboxes in use.
+    #[derive(Default)]
+    struct MockCan {
+        rx: VecDeque<MockFrame>,
+        mailbox: Option<MockFrame>,
+        sent: Vec<MockFrame>,
+        /// Send the frame in the mailbox on the next transmit.
+        auto_send: bool,
+    }
+
+    impl MockCan {
+        fn send_mailbox(&mut self) {
+            if let Some(frame) = self.mailbox.take() {
+                self.sent.push(frame);
+            }
+        }
+    }
+
+    impl Can for MockCan {
+        type Frame = MockFrame;
+        type Error = ErrorKind;
+
+        fn transmit(&mut self, frame: &MockFrame) -> nb::Result<Option<MockFrame>, ErrorKind> {
+            if self.auto_send {
+                self.send_mailbox();
+            }
+
+            match &self.mailbox {
+                None => {
+                    self.mailbox = Some(frame.clone());
+                    Ok(None)
+                }
+                Some(pending) if frame.id < pending.id => Ok(self.mailbox.replace(frame.clone())),
+                Some(_) => Err(nb::Error::WouldBlock),
+            }
+        }
+
+        fn receive(&mut self) -> nb::Result<MockFrame, ErrorKind> {
+            self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    fn extended(id: u32, data: &[u8]) -> MockFrame {
+        MockFrame::new(ExtendedId::new(id).unwrap(), data).unwrap()
+    }
+
+    #[test]
+    fn frames_are_converted() {
+        let mut driver = EmbeddedCanDriver::new(MockCan::default());
+        driver.open(None).unwrap();
+
+        driver
+            .inner_mut()
+            .rx
+            .push_back(MockFrame::new_remote(ExtendedId::MAX, 0).unwrap());
+        driver
+            .inner_mut()
+            .rx
+            .push_back(extended(0x18EAFF80, &[0x00, 0xEE, 0x00]));
+        assert_eq!(
+            driver.read(),
+            Ok(CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00]))
+        );
+        assert_eq!(driver.read(), Err(nb::Error::WouldBlock));
+
+        driver.write(CanFrame::new(0x18EEFF80, &[1, 2, 3])).unwrap();
+        assert_eq!(
+            driver.free().mailbox,
+            Some(extended(0x18EEFF80, &[1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn replaced_frames_are_sent_again() {
+        let mut driver = EmbeddedCanDriver::new(MockCan::default());
+        driver.open(None).unwrap();
+
+        driver.write(CanFrame::new(0x1CEB2680, &[1])).unwrap();
+        // The higher priority frame replaces the pending frame in the mailbox.
+        driver.write(CanFrame::new(0x0CEB2680, &[2])).unwrap();
+        assert_eq!(
+            driver.write(CanFrame::new(0x1CEB2680, &[3])),
+            Err(nb::Error::WouldBlock)
+        );
+
+        driver.inner_mut().send_mailbox();
+        assert_eq!(driver.read(), Err(nb::Error::WouldBlock));
+        driver.inner_mut().send_mailbox();
+
+        assert_eq!(
+            driver.free().sent,
+            [extended(0x0CEB2680, &[2]), extended(0x1CEB2680, &[1])]
+        );
+    }
+
+    #[test]
+    fn isobus_claims_address() {
+        let can = MockCan {
+            auto_send: true,
+            ..Default::default()
+        };
+        let mut isobus = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0001))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(EmbeddedCanDriver::new(can)))
+            .build();
+
+        for time in (0..1000).step_by(10) {
+            isobus.process(time);
+        }
+
+        assert_eq!(isobus.claimed_address(), IsobusAddress(128));
+    }
+}
@@ -0,0 +1,2 @@
+mod embedded_can_driver;
+pub use embedded_can_driver::EmbeddedCanDriver;
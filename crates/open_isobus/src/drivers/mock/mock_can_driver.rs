@@ -1,9 +1,6 @@
-
-use {
-    crate::drivers::{
-        can_driver::{Baudrate, CanFrame},
-        CanDriverTrait,
-    },
+use crate::drivers::{
+    can_driver::{Baudrate, BusState, CanError, CanFrame},
+    CanDriverTrait,
 };
 
 pub struct MockCanDriver {}
@@ -17,11 +14,21 @@ impl MockCanDriver {
 impl CanDriverTrait for MockCanDriver {
     fn init(&mut self) {}
 
-    fn open(&mut self, _baudrate: Option<Baudrate>) {}
+    fn open(&mut self, _baudrate: Option<Baudrate>) -> Result<(), CanError> {
+        Ok(())
+    }
 
     fn close(&mut self) {}
 
-    fn read(&mut self) -> Option<CanFrame> { None }
+    fn read(&mut self) -> nb::Result<CanFrame, CanError> {
+        Err(nb::Error::WouldBlock)
+    }
+
+    fn write(&mut self, _frame: CanFrame) -> nb::Result<(), CanError> {
+        Ok(())
+    }
 
-    fn write(&mut self, _frame: CanFrame) {}
+    fn state(&self) -> BusState {
+        BusState::ErrorActive
+    }
 }
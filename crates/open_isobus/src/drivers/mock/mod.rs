@@ -1,3 +1,2 @@
-
 mod mock_can_driver;
-pub use mock_can_driver::MockCanDriver as CanDriver;
+pub use mock_can_driver::MockCanDriver;
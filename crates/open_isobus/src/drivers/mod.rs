@@ -1,22 +1,42 @@
 pub mod can_driver;
-pub use can_driver::CanDriverTrait;
+pub use can_driver::{BusState, CanDriverTrait, CanError, CanFilter};
 
 #[cfg(feature = "peak_can_driver")]
 mod peak;
 #[cfg(feature = "peak_can_driver")]
-pub use peak::CanDriver;
-
-#[cfg(any(feature = "mock_can_driver"))]
-mod mock;
-#[cfg(any(feature = "mock_can_driver"))]
-pub use mock::CanDriver;
+pub use peak::PeakCanDriver;
 
 #[cfg(all(target_family = "unix", feature = "socket_can_driver"))]
 mod socket;
 #[cfg(all(target_family = "unix", feature = "socket_can_driver"))]
-pub use socket::CanDriver;
+pub use socket::SocketCanDriver;
+
+#[cfg(feature = "embedded_can_driver")]
+mod embedded;
+#[cfg(feature = "embedded_can_driver")]
+pub use embedded::EmbeddedCanDriver;
+
+mod mock;
+pub use mock::MockCanDriver;
+
+mod virtual_bus;
+pub use virtual_bus::{VirtualCanBus, VirtualCanDriver};
 
-// #[cfg_attr(feature = "peak_can_driver", path = "peak")]
-// #[cfg_attr(all(target_family = "unix", feature = "socket_can_driver"), path = "socket")]
-// #[cfg_attr(feature = "mock_can_driver", path = "mock")]
-// pub use CanDriver;
+pub mod can_log;
+pub use can_log::{RecordingCanDriver, ReplayCanDriver};
+
+// The driver used by the `IsobusBuilder` when no driver is given.
+// When multiple driver features are enabled, hardware drivers take precedence over the mock driver.
+#[cfg(feature = "peak_can_driver")]
+pub type CanDriver = PeakCanDriver;
+#[cfg(all(
+    not(feature = "peak_can_driver"),
+    target_family = "unix",
+    feature = "socket_can_driver"
+))]
+pub type CanDriver = SocketCanDriver;
+#[cfg(not(any(
+    feature = "peak_can_driver",
+    all(target_family = "unix", feature = "socket_can_driver")
+)))]
+pub type CanDriver = MockCanDriver;
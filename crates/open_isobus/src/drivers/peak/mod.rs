@@ -1,4 +1,4 @@
 pub mod can_error;
 
 mod peak_can_driver;
-pub use peak_can_driver::PeakCanDriver as CanDriver;
+pub use peak_can_driver::PeakCanDriver;
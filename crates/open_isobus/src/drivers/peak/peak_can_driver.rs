@@ -1,6 +1,6 @@
 use {
     crate::drivers::{
-        can_driver::{Baudrate, CanError, CanFrame, ExtendedId, Id, StandardId},
+        can_driver::{Baudrate, BusState, CanError, CanFrame, ExtendedId, Id, StandardId},
         CanDriverTrait,
     },
     alloc::string::ToString,
@@ -14,6 +14,7 @@ use {
 pub struct PeakCanDriver {
     socket: Option<UsbCanSocket>,
     baudrate: Option<Baudrate>,
+    state: BusState,
 }
 
 impl PeakCanDriver {
@@ -21,14 +22,21 @@ impl PeakCanDriver {
         Self {
             socket: None,
             baudrate: None,
+            state: BusState::Closed,
         }
     }
 
-    fn socket(&self) -> Option<&UsbCanSocket> {
-        if self.socket.is_none() {
-            self.log_can_error("Peak CAN driver not open", None);
+    fn handle_error(&mut self, e: PcanError) -> nb::Error<CanError> {
+        match e {
+            PcanError::QrcvEmpty | PcanError::XmtFull | PcanError::QxmtFull => {
+                return nb::Error::WouldBlock
+            }
+            PcanError::BusOff => self.state = BusState::BusOff,
+            PcanError::BusPassive => self.state = BusState::ErrorPassive,
+            PcanError::BusLight | PcanError::BusHeavy => self.state = BusState::ErrorActive,
+            _ => {}
         }
-        self.socket.as_ref()
+        nb::Error::Other(e.into())
     }
 
     fn get_attached_channels(&self) {
@@ -44,67 +52,56 @@ impl PeakCanDriver {
             }
         }
     }
-
-    fn log_can_error(&self, message: &str, error: Option<PcanError>) {
-        match error {
-            Some(e) => log::error!("{message}: \"{e:?}\""),
-            None => log::error!("{message}"),
-        }
-    }
 }
 
 impl CanDriverTrait for PeakCanDriver {
     fn init(&mut self) {}
 
-    fn open(&mut self, baudrate: Option<Baudrate>) {
+    fn open(&mut self, baudrate: Option<Baudrate>) -> Result<(), CanError> {
         if self.socket.is_some() && self.baudrate == baudrate {
-            return;
+            return Ok(());
         }
 
         let baudrate = baudrate.unwrap_or(Baudrate::Baud250K);
         self.baudrate = Some(baudrate);
 
-        self.socket = match UsbCanSocket::open(UsbBus::USB1, baudrate.into()) {
-            Ok(socket) => Some(socket),
-            Err(e) => {
-                self.log_can_error("Unable to open Peak CAN driver", Some(e));
-                None
-            }
-        };
+        let socket = UsbCanSocket::open(UsbBus::USB1, baudrate.into())?;
+        self.socket = Some(socket);
+        self.state = BusState::ErrorActive;
+        Ok(())
     }
 
     fn close(&mut self) {
         self.socket = None;
+        self.state = BusState::Closed;
     }
 
-    fn read(&mut self) -> Option<CanFrame> {
-        let socket = match self.socket() {
-            Some(socket) => socket,
-            None => return None,
+    fn read(&mut self) -> nb::Result<CanFrame, CanError> {
+        let result = match &self.socket {
+            Some(socket) => socket.recv(),
+            None => return Err(nb::Error::Other(CanError::Uninitialised)),
         };
 
-        match socket.recv() {
-            Ok((f, _t)) => Some(f.into()),
-            Err(PcanError::QrcvEmpty) => None,
-            Err(e) => {
-                self.log_can_error("Unable to read CAN frame", Some(e));
-                None
-            }
+        match result {
+            Ok((f, _t)) => Ok(f.into()),
+            Err(e) => Err(self.handle_error(e)),
         }
     }
 
-    fn write(&mut self, frame: CanFrame) {
+    fn write(&mut self, frame: CanFrame) -> nb::Result<(), CanError> {
         #[cfg(feature = "log_can_write")]
         log::debug!("send: {}", &frame);
 
-        let socket = match self.socket() {
-            Some(socket) => socket,
-            None => return,
+        let result = match &self.socket {
+            Some(socket) => socket.send(frame.into()),
+            None => return Err(nb::Error::Other(CanError::Uninitialised)),
         };
 
-        if let Err(e) = socket.send(frame.into()) {
-            self.log_can_error("Unable to write CAN frame", Some(e));
-        }
+        result.map_err(|e| self.handle_error(e))
+    }
+
+    fn state(&self) -> BusState {
+        self.state
     }
 }
 
@@ -139,8 +136,8 @@ impl From<PcanError> for CanError {
             PcanError::Overrun => CanError::Overrun,
             PcanError::BusLight => CanError::Other("PcanError::BusLight".to_string()),
             PcanError::BusHeavy => CanError::Other("PcanError::BusHeavy".to_string()),
-            PcanError::BusPassive => CanError::Other("PcanError::BusPassive".to_string()),
-            PcanError::BusOff => CanError::Other("PcanError::BusOff".to_string()),
+            PcanError::BusPassive => CanError::ErrorPassive,
+            PcanError::BusOff => CanError::BusOff,
             PcanError::AnyBusErr => CanError::Other("PcanError::AnyBusErr".to_string()),
             PcanError::QrcvEmpty => CanError::Other("PcanError::QrcvEmpty".to_string()),
             PcanError::QOverrun => CanError::Overrun,
@@ -1,2 +1,2 @@
 mod socket_can_driver;
-pub use socket_can_driver::SocketCanDriver as CanDriver;
+pub use socket_can_driver::SocketCanDriver;
@@ -1,11 +1,18 @@
 use {
     crate::drivers::{
-        can_driver::{Baudrate, CanError, CanFrame, ExtendedId, Id, StandardId},
+        can_driver::{
+            Baudrate, BusState, CanError, CanFilter, CanFrame, ExtendedId, Id, StandardId,
+        },
         CanDriverTrait,
     },
-    alloc::string::ToString,
+    alloc::{
+        format,
+        string::{String, ToString},
+        vec::Vec,
+    },
     socketcan::{
         CANError,
+        CANFilter,
         // CANFrame,
         CANSocket,
     }, // pcan_basic::{
@@ -15,82 +22,175 @@ use {
        // },
 };
 
+// Linux error codes and error frame flags, see `linux/can/error.h`.
+const ENODEV: i32 = 19;
+const ENETDOWN: i32 = 100;
+const ENOBUFS: i32 = 105;
+const CAN_ERR_CRTL: u32 = 0x0004;
+const CAN_ERR_BUSOFF: u32 = 0x0040;
+const CAN_ERR_RESTARTED: u32 = 0x0100;
+const CAN_ERR_CRTL_RX_PASSIVE: u8 = 0x10;
+const CAN_ERR_CRTL_TX_PASSIVE: u8 = 0x20;
+const CAN_ERR_CRTL_ACTIVE: u8 = 0x40;
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+
 pub struct SocketCanDriver {
+    interface: String,
     socket: Option<CANSocket>,
     baudrate: Option<Baudrate>,
+    state: BusState,
+    filters: Vec<CanFilter>,
 }
 
 impl SocketCanDriver {
-    pub fn new(_bus_id: u8) -> Self {
+    /// Use the interface `can<bus_id>`, e.g. `can0` or `can1`.
+    pub fn new(bus_id: u8) -> Self {
+        Self::with_interface(&format!("can{bus_id}"))
+    }
+
+    /// Use the interface with the given name, e.g. `vcan0`.
+    pub fn with_interface(interface: &str) -> Self {
         Self {
+            interface: interface.to_string(),
             socket: None,
             baudrate: None,
+            state: BusState::Closed,
+            filters: Vec::new(),
+        }
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    fn handle_error_frame(&mut self, frame: &socketcan::CANFrame) -> CanError {
+        let class = frame.err();
+        let controller = frame.data().get(1).copied().unwrap_or_default();
+
+        if class & CAN_ERR_BUSOFF != 0 {
+            self.state = BusState::BusOff;
+        } else if class & CAN_ERR_RESTARTED != 0 {
+            self.state = BusState::ErrorActive;
+        } else if class & CAN_ERR_CRTL != 0 {
+            if controller & (CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE) != 0 {
+                self.state = BusState::ErrorPassive;
+            } else if controller & CAN_ERR_CRTL_ACTIVE != 0 {
+                self.state = BusState::ErrorActive;
+            }
+        }
+
+        match frame.error() {
+            Ok(e) => e.into(),
+            Err(e) => CanError::Other(format!("{e:?}")),
+        }
+    }
+
+    fn apply_filters(&self) -> Result<(), CanError> {
+        let socket = match &self.socket {
+            Some(socket) => socket,
+            None => return Ok(()),
+        };
+
+        if self.filters.is_empty() {
+            return socket
+                .filter_accept_all()
+                .map_err(|e| CanError::Other(format!("{e:?}")));
+        }
+
+        // Only accept extended frames, standard frames are not used by ISO 11783.
+        let mut filters = Vec::with_capacity(self.filters.len());
+        for f in &self.filters {
+            filters.push(
+                CANFilter::new(f.id | CAN_EFF_FLAG, f.mask | CAN_EFF_FLAG)
+                    .map_err(|e| CanError::Other(format!("{e:?}")))?,
+            );
         }
+        socket
+            .set_filter(&filters)
+            .map_err(|e| CanError::Other(format!("{e:?}")))
     }
 
-    fn socket(&self) -> Option<&CANSocket> {
-        if self.socket.is_none() {
-            log::error!("Socket CAN driver not open");
+    fn handle_io_error(&mut self, e: std::io::Error) -> nb::Error<CanError> {
+        if e.kind() == std::io::ErrorKind::WouldBlock || e.raw_os_error() == Some(ENOBUFS) {
+            return nb::Error::WouldBlock;
+        }
+
+        // The interface is gone, it has to be opened again.
+        if matches!(e.raw_os_error(), Some(ENODEV) | Some(ENETDOWN)) {
+            self.close();
         }
-        self.socket.as_ref()
+
+        nb::Error::Other(CanError::Other(format!("{e:?}")))
     }
 }
 #[cfg(feature = "socket_can_driver")]
 impl CanDriverTrait for SocketCanDriver {
     fn init(&mut self) {}
 
-    fn open(&mut self, baudrate: Option<Baudrate>) {
+    fn open(&mut self, baudrate: Option<Baudrate>) -> Result<(), CanError> {
         if self.socket.is_some() && self.baudrate == baudrate {
-            return;
+            return Ok(());
         }
 
         let baudrate = baudrate.unwrap_or(Baudrate::Baud250K);
         self.baudrate = Some(baudrate);
 
-        self.socket = match CANSocket::open("can0") {
-            Ok(socket) => {
-                socket.set_nonblocking(true).unwrap();
-                Some(socket)
-            }
-            Err(e) => {
-                log::error!("Unable to open Socket CAN driver: \"{e:?}\"");
-                None
-            }
-        };
+        let socket = CANSocket::open(&self.interface).map_err(|e| {
+            CanError::Other(format!(
+                "Unable to open interface \"{}\": {e:?}",
+                self.interface
+            ))
+        })?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|e| CanError::Other(format!("{e:?}")))?;
+        // Receive error frames to keep track of the bus state.
+        socket
+            .error_filter_accept_all()
+            .map_err(|e| CanError::Other(format!("{e:?}")))?;
+
+        self.socket = Some(socket);
+        self.state = BusState::ErrorActive;
+        self.apply_filters()
     }
 
     fn close(&mut self) {
         self.socket = None;
+        self.state = BusState::Closed;
     }
 
-    fn read(&mut self) -> Option<CanFrame> {
-        let socket = match self.socket() {
-            Some(socket) => socket,
-            None => return None,
+    fn read(&mut self) -> nb::Result<CanFrame, CanError> {
+        let result = match &self.socket {
+            Some(socket) => socket.read_frame(),
+            None => return Err(nb::Error::Other(CanError::Uninitialised)),
         };
 
-        match socket.read_frame() {
-            Ok(f) => Some(f.into()),
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => None,
-            Err(e) => {
-                log::error!("Unable to read CAN frame: \"{e:?}\"");
-                None
-            }
+        match result {
+            Ok(f) if f.is_error() => Err(nb::Error::Other(self.handle_error_frame(&f))),
+            Ok(f) => Ok(f.into()),
+            Err(e) => Err(self.handle_io_error(e)),
         }
     }
 
-    fn write(&mut self, frame: CanFrame) {
+    fn write(&mut self, frame: CanFrame) -> nb::Result<(), CanError> {
         #[cfg(feature = "log_can_write")]
         log::debug!("send: {}", &frame);
 
-        let socket = match self.socket() {
-            Some(socket) => socket,
-            None => return,
+        let result = match &self.socket {
+            Some(socket) => socket.write_frame(&(frame.into())),
+            None => return Err(nb::Error::Other(CanError::Uninitialised)),
         };
 
-        if let Err(e) = socket.write_frame(&(frame.into())) {
-            log::error!("Unable to write CAN frame: \"{e:?}\"");
-        }
+        result.map_err(|e| self.handle_io_error(e))
+    }
+
+    fn state(&self) -> BusState {
+        self.state
+    }
+
+    fn set_filters(&mut self, filters: &[CanFilter]) -> Result<(), CanError> {
+        self.filters = filters.to_vec();
+        self.apply_filters()
     }
 }
 
@@ -134,7 +234,7 @@ impl From<CANError> for CanError {
             } => CanError::Other("CANError::ProtocolViolation".to_string()),
             CANError::TransceiverError => CanError::Other("CANError::TransceiverError".to_string()),
             CANError::NoAck => CanError::Other("CANError::NoAck".to_string()),
-            CANError::BusOff => CanError::Other("CANError::BusOff".to_string()),
+            CANError::BusOff => CanError::BusOff,
             CANError::BusError => CanError::Other("CANError::BusError".to_string()),
             CANError::Restarted => CanError::Other("CANError::Restarted".to_string()),
             CANError::Unknown(_) => CanError::Other("CANError::Unknown".to_string()),
@@ -0,0 +1,2 @@
+mod virtual_can_driver;
+pub use virtual_can_driver::{VirtualCanBus, VirtualCanDriver};
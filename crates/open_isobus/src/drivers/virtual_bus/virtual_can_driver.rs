@@ -0,0 +1,388 @@
+use {
+    crate::drivers::{
+        can_driver::{Baudrate, BusState, CanError, CanFilter, CanFrame, Id},
+        CanDriverTrait,
+    },
+    alloc::{collections::VecDeque, rc::Rc, vec::Vec},
+    core::cell::RefCell,
+};
+
+/// An in-memory CAN bus, shared by all drivers connected to it.
+///
+/// Every frame written by one node is delivered to all other open nodes. When several nodes
+/// have frames waiting, the frame with the highest CAN priority (lowest identifier) wins the
+/// arbitration and is delivered first. Frames of a single node keep their transmit order.
+#[derive(Clone, Default)]
+pub struct VirtualCanBus {
+    bus: Rc<RefCell<Bus>>,
+}
+
+impl VirtualCanBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect a new node to the bus.
+    pub fn connect(&self) -> VirtualCanDriver {
+        let mut bus = self.bus.borrow_mut();
+        bus.nodes.push(Node::default());
+
+        VirtualCanDriver {
+            bus: self.clone(),
+            node: bus.nodes.len() - 1,
+        }
+    }
+
+    /// Deliver all frames waiting to be transmitted, in arbitration order.
+    pub fn flush(&self) {
+        self.bus.borrow_mut().arbitrate();
+    }
+
+    /// Put a node in bus-off, as if its controller saw too many errors.
+    /// The node no longer sends or receives frames until it is reopened.
+    pub fn bus_off(&self, node: usize) {
+        let mut bus = self.bus.borrow_mut();
+        bus.arbitrate();
+        let node = &mut bus.nodes[node];
+        if node.state != BusState::Closed {
+            node.state = BusState::BusOff;
+            node.tx.clear();
+            node.rx.clear();
+        }
+    }
+
+    /// Remove a node from the bus, as if its interface disappeared.
+    /// The node is closed and can not be opened until it is plugged in again.
+    pub fn unplug(&self, node: usize) {
+        let mut bus = self.bus.borrow_mut();
+        bus.arbitrate();
+        let node = &mut bus.nodes[node];
+        node.state = BusState::Closed;
+        node.plugged = false;
+        node.tx.clear();
+        node.rx.clear();
+    }
+
+    /// The acceptance filters set by a node.
+    pub fn filters(&self, node: usize) -> Vec<CanFilter> {
+        self.bus.borrow().nodes[node].filters.clone()
+    }
+
+    /// Plug a node back in after [`VirtualCanBus::unplug`].
+    pub fn plug(&self, node: usize) {
+        self.bus.borrow_mut().nodes[node].plugged = true;
+    }
+}
+
+#[derive(Default)]
+struct Bus {
+    nodes: Vec<Node>,
+}
+
+impl Bus {
+    fn arbitrate(&mut self) {
+        loop {
+            let winner = self
+                .nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, n)| n.tx.front().map(|f| (arbitration_key(f.id()), i)))
+                .min();
+
+            let node = match winner {
+                Some((_, node)) => node,
+                None => return,
+            };
+
+            if let Some(frame) = self.nodes[node].tx.pop_front() {
+                for (i, n) in self.nodes.iter_mut().enumerate() {
+                    if i != node && n.is_online() && n.accepts(&frame) {
+                        n.rx.push_back(frame);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Node {
+    state: BusState,
+    plugged: bool,
+    filters: Vec<CanFilter>,
+    tx: VecDeque<CanFrame>,
+    rx: VecDeque<CanFrame>,
+}
+
+impl Node {
+    fn is_online(&self) -> bool {
+        matches!(self.state, BusState::ErrorActive | BusState::ErrorPassive)
+    }
+
+    fn accepts(&self, frame: &CanFrame) -> bool {
+        self.filters.is_empty() || self.filters.iter().any(|f| f.matches(frame.id()))
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            state: BusState::Closed,
+            plugged: true,
+            filters: Vec::new(),
+            tx: VecDeque::new(),
+            rx: VecDeque::new(),
+        }
+    }
+}
+
+/// The bits of a frame in the order they are put on the wire during arbitration.
+/// A standard frame wins from an extended frame with the same base identifier.
+fn arbitration_key(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() << 20,
+        Id::Extended(id) => id.standard_id().as_raw() << 20 | 0b11 << 18 | (id.as_raw() & 0x3FFFF),
+    }
+}
+
+/// A node on a [`VirtualCanBus`].
+pub struct VirtualCanDriver {
+    bus: VirtualCanBus,
+    node: usize,
+}
+
+impl VirtualCanDriver {
+    /// The bus this node is connected to.
+    pub fn bus(&self) -> &VirtualCanBus {
+        &self.bus
+    }
+
+    /// The index of this node on the bus, used to inject faults.
+    pub fn node(&self) -> usize {
+        self.node
+    }
+}
+
+impl CanDriverTrait for VirtualCanDriver {
+    fn init(&mut self) {}
+
+    fn open(&mut self, _baudrate: Option<Baudrate>) -> Result<(), CanError> {
+        let mut bus = self.bus.bus.borrow_mut();
+        if !bus.nodes[self.node].plugged {
+            return Err(CanError::NoDriver);
+        }
+
+        // Frames sent before opening are not received.
+        bus.arbitrate();
+        bus.nodes[self.node].state = BusState::ErrorActive;
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        let mut bus = self.bus.bus.borrow_mut();
+        let node = &mut bus.nodes[self.node];
+        node.state = BusState::Closed;
+        node.tx.clear();
+        node.rx.clear();
+    }
+
+    fn read(&mut self) -> nb::Result<CanFrame, CanError> {
+        let mut bus = self.bus.bus.borrow_mut();
+        match bus.nodes[self.node].state {
+            BusState::Closed => return Err(nb::Error::Other(CanError::Uninitialised)),
+            BusState::BusOff => return Err(nb::Error::WouldBlock),
+            BusState::ErrorActive | BusState::ErrorPassive => {}
+        }
+
+        bus.arbitrate();
+        bus.nodes[self.node]
+            .rx
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+
+    fn write(&mut self, frame: CanFrame) -> nb::Result<(), CanError> {
+        #[cfg(feature = "log_can_write")]
+        log::debug!("send: {}", &frame);
+
+        let mut bus = self.bus.bus.borrow_mut();
+        let node = &mut bus.nodes[self.node];
+        match node.state {
+            BusState::Closed => return Err(nb::Error::Other(CanError::Uninitialised)),
+            BusState::BusOff => return Err(nb::Error::Other(CanError::BusOff)),
+            BusState::ErrorActive | BusState::ErrorPassive => {}
+        }
+
+        node.tx.push_back(frame);
+        Ok(())
+    }
+
+    fn state(&self) -> BusState {
+        self.bus.bus.borrow().nodes[self.node].state
+    }
+
+    fn set_filters(&mut self, filters: &[CanFilter]) -> Result<(), CanError> {
+        let mut bus = self.bus.bus.borrow_mut();
+        // Frames already on the bus were received with the old filters.
+        bus.arbitrate();
+        bus.nodes[self.node].filters = filters.to_vec();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+
+    use crate::{
+        drivers::{can_driver::CanFrame, CanDriverTrait, CanError},
+        iso_11783_3::DataLinkLayer,
+        iso_11783_5::{Name, NetworkManager},
+        Isobus, IsobusAddress,
+    };
+
+    use super::VirtualCanBus;
+
+    #[test]
+    fn frames_are_delivered_to_all_other_nodes() {
+        let bus = VirtualCanBus::new();
+        let mut a = bus.connect();
+        let mut b = bus.connect();
+        let mut c = bus.connect();
+        a.open(None).unwrap();
+        b.open(None).unwrap();
+        c.open(None).unwrap();
+
+        a.write(CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00]))
+            .unwrap();
+
+        assert_eq!(a.read(), Err(nb::Error::WouldBlock));
+        assert_eq!(b.read(), Ok(CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00])));
+        assert_eq!(c.read(), Ok(CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00])));
+    }
+
+    #[test]
+    fn closed_nodes_do_not_receive() {
+        let bus = VirtualCanBus::new();
+        let mut a = bus.connect();
+        let mut b = bus.connect();
+        a.open(None).unwrap();
+
+        a.write(CanFrame::new(0x18EAFF80, &[])).unwrap();
+
+        assert_eq!(b.read(), Err(nb::Error::Other(CanError::Uninitialised)));
+        b.open(None).unwrap();
+        assert_eq!(b.read(), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn arbitration_follows_can_priority() {
+        let bus = VirtualCanBus::new();
+        let mut a = bus.connect();
+        let mut b = bus.connect();
+        let mut probe = bus.connect();
+        a.open(None).unwrap();
+        b.open(None).unwrap();
+        probe.open(None).unwrap();
+
+        // Node a queues a low priority frame followed by a high priority frame,
+        // the order of a single node is kept.
+        a.write(CanFrame::new(0x1CEB2680, &[1])).unwrap();
+        a.write(CanFrame::new(0x0CEB2680, &[2])).unwrap();
+        // Node b competes with a higher priority frame than the head of node a.
+        b.write(CanFrame::new(0x18EEFF81, &[3])).unwrap();
+        // A standard frame wins from an extended frame with the same base identifier.
+        b.write(CanFrame::new(0x63A, &[4])).unwrap();
+
+        let received: Vec<u8> = core::iter::from_fn(|| probe.read().ok())
+            .map(|f| f.data()[0])
+            .collect();
+
+        assert_eq!(received, [3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn heads_of_three_nodes_are_arbitrated() {
+        let bus = VirtualCanBus::new();
+        let mut a = bus.connect();
+        let mut b = bus.connect();
+        let mut c = bus.connect();
+        let mut probe = bus.connect();
+        a.open(None).unwrap();
+        b.open(None).unwrap();
+        c.open(None).unwrap();
+        probe.open(None).unwrap();
+
+        a.write(CanFrame::new(0x1CEB2680, &[1])).unwrap();
+        a.write(CanFrame::new(0x0CEB2680, &[2])).unwrap();
+        b.write(CanFrame::new(0x18E8FF81, &[3])).unwrap();
+        // The standard frame of node c wins from the extended frames at the heads of a and b.
+        c.write(CanFrame::new(0x63A, &[4])).unwrap();
+
+        let received: Vec<u8> = core::iter::from_fn(|| probe.read().ok())
+            .map(|f| f.data()[0])
+            .collect();
+
+        assert_eq!(received, [4, 3, 1, 2]);
+    }
+
+    #[test]
+    fn nodes_claim_unique_addresses() {
+        let bus = VirtualCanBus::new();
+
+        let mut dll_a = DataLinkLayer::new(alloc::boxed::Box::new(bus.connect()));
+        let mut nm_a = NetworkManager::new(Name::from(0xA000_0000_0000_0001));
+        let mut dll_b = DataLinkLayer::new(alloc::boxed::Box::new(bus.connect()));
+        let mut nm_b = NetworkManager::new(Name::from(0xA000_0000_0000_0002));
+
+        for time in (0..2000).step_by(10) {
+            let pdus = dll_a.process(&nm_a, time);
+            if !nm_a.is_connected() {
+                let _ = nm_a.connect(&mut dll_a, Some(IsobusAddress(128)), time);
+            }
+            nm_a.process(&pdus, &mut dll_a, time);
+            dll_a.transmit(time);
+
+            // Node b powers up after node a claimed its address.
+            if time < 1000 {
+                continue;
+            }
+            let pdus = dll_b.process(&nm_b, time);
+            if !nm_b.is_connected() {
+                let _ = nm_b.connect(&mut dll_b, Some(IsobusAddress(128)), time);
+            }
+            nm_b.process(&pdus, &mut dll_b, time);
+            dll_b.transmit(time);
+        }
+
+        assert_eq!(nm_a.claimed_address(), IsobusAddress(128));
+        assert_eq!(nm_b.claimed_address(), IsobusAddress(129));
+    }
+
+    #[test]
+    fn injected_drivers_claim_unique_addresses() {
+        let bus = VirtualCanBus::new();
+
+        let mut a = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0001))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(bus.connect()))
+            .build();
+        let mut b = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0002))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(bus.connect()))
+            .build();
+
+        for time in (0..2000).step_by(10) {
+            a.process(time);
+
+            // Node b powers up after node a claimed its address.
+            if time >= 1000 {
+                b.process(time);
+            }
+        }
+
+        assert_eq!(a.claimed_address(), IsobusAddress(128));
+        assert_eq!(b.claimed_address(), IsobusAddress(129));
+    }
+}
@@ -1,13 +1,38 @@
 use alloc::{boxed::Box, vec::Vec};
 
-use crate::{drivers::CanDriverTrait, iso_11783_5::NetworkManager, isobus::CanFrame};
+use crate::{
+    drivers::{BusState, CanDriverTrait, CanError, CanFilter},
+    iso_11783_5::NetworkManager,
+    isobus::{CanFrame, IsobusAddress},
+};
 
-use super::{ExtendedTransportProtocolManager, TransportProtocolManager, PDU};
+use super::{
+    ExtendedTransportProtocolManager, FastPacketManager, TransmitQueue, TransportConfig,
+    TransportEvent, TransportProtocolManager, PDU, PGN,
+};
 
 pub struct DataLinkLayer {
     can_driver: Box<dyn CanDriverTrait>,
     tp_manager: TransportProtocolManager,
     etp_manager: ExtendedTransportProtocolManager,
+    fast_packet_manager: FastPacketManager,
+    transmit_queue: TransmitQueue,
+
+    pgns: Vec<PGN>,
+    filters: Vec<CanFilter>,
+    filter_address: IsobusAddress,
+    filters_changed: bool,
+}
+
+/// The destination specific (PDU1) frames sent to the address, PDU format below 240.
+/// A single mask cannot express `PF < 0xF0`, the PDU formats are split by their leading bits:
+/// `0xxx_xxxx`, `10xx_xxxx`, `110x_xxxx` and `1110_xxxx`.
+fn pdu1_filters(address: IsobusAddress) -> impl Iterator<Item = CanFilter> {
+    [(0x00, 0x80), (0x80, 0xC0), (0xC0, 0xE0), (0xE0, 0xF0)]
+        .into_iter()
+        .map(move |(pf, mask)| {
+            CanFilter::new(pf << 16 | (address.0 as u32) << 8, mask << 16 | 0x00_FF00)
+        })
 }
 
 impl DataLinkLayer {
@@ -15,25 +40,128 @@ impl DataLinkLayer {
 
     pub fn new(mut can_driver: Box<dyn CanDriverTrait>) -> Self {
         can_driver.init();
-        can_driver.open(None);
+        if let Err(e) = can_driver.open(None) {
+            log::error!("Unable to open CAN driver: \"{e:?}\"");
+        }
 
         DataLinkLayer {
             can_driver,
             tp_manager: TransportProtocolManager::new(),
             etp_manager: ExtendedTransportProtocolManager::new(),
+            fast_packet_manager: FastPacketManager::new(),
+            transmit_queue: TransmitQueue::new(),
+
+            pgns: Vec::new(),
+            filters: Vec::new(),
+            filter_address: IsobusAddress::NULL,
+            filters_changed: true,
+        }
+    }
+
+    /// Receive the broadcast (PDU2) PGN. Until a PGN is registered all broadcasts are received.
+    /// Destination specific (PDU1) PGNs are received when sent to the claimed or global address,
+    /// registering them has no effect on the filters.
+    pub fn register_pgn(&mut self, pgn: PGN) {
+        if !self.pgns.contains(&pgn) {
+            self.pgns.push(pgn);
+            self.filters_changed = true;
+        }
+    }
+
+    /// Send and receive the (NMEA 2000) PGN with Fast Packet instead of single frames, TP or ETP.
+    /// The PGN is registered for the acceptance filters too.
+    pub fn register_fast_packet_pgn(&mut self, pgn: PGN) {
+        self.fast_packet_manager.register_pgn(pgn);
+        self.register_pgn(pgn);
+    }
+
+    /// The acceptance filters for the claimed address and the registered PGNs.
+    pub fn filters(&self) -> &[CanFilter] {
+        &self.filters
+    }
+
+    fn update_filters(&mut self, claimed_address: IsobusAddress) {
+        if !self.filters_changed && self.filter_address == claimed_address {
+            return;
         }
+        self.filter_address = claimed_address;
+        self.filters_changed = false;
+
+        self.filters.clear();
+        self.filters.extend(pdu1_filters(IsobusAddress::GLOBAL));
+        if claimed_address != IsobusAddress::NULL {
+            self.filters.extend(pdu1_filters(claimed_address));
+        }
+
+        if self.pgns.is_empty() {
+            self.filters.push(CanFilter::new(0x00F0_0000, 0x00F0_0000));
+        } else {
+            for pgn in self
+                .pgns
+                .iter()
+                .filter(|pgn| (pgn.as_u32() >> 8) & 0xFF >= 0xF0)
+            {
+                self.filters
+                    .push(CanFilter::new(pgn.as_u32() << 8, 0x03FF_FF00));
+            }
+        }
+
+        if let Err(e) = self.can_driver.set_filters(&self.filters) {
+            log::error!("Unable to set CAN filters: \"{e:?}\"");
+        }
+    }
+
+    /// Limit the number of frames written per call to `transmit`, and per ms.
+    pub fn set_transmit_budget(&mut self, max_frames_per_process: u16, max_frames_per_ms: u16) {
+        self.transmit_queue
+            .set_budget(max_frames_per_process, max_frames_per_ms);
+    }
+
+    /// Limit the number of concurrent TP and ETP sessions, each.
+    pub fn set_max_transport_sessions(&mut self, max_sessions: usize) {
+        self.tp_manager.set_max_sessions(max_sessions);
+        self.etp_manager.set_max_sessions(max_sessions);
+    }
+
+    /// The timing and flow control of TP and ETP.
+    pub fn set_transport_config(&mut self, config: TransportConfig) {
+        self.tp_manager.set_config(config);
+        self.etp_manager.set_config(config);
     }
 
+    /// Limit the size of messages received with ETP.
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.etp_manager.set_max_message_size(max_message_size);
+    }
+
+    /// Queue the started, progress, completed and aborted events of the TP and ETP sessions.
+    pub fn subscribe_transport_events(&mut self) {
+        self.tp_manager.subscribe_events();
+        self.etp_manager.subscribe_events();
+    }
+
+    pub fn next_transport_event(&mut self) -> Option<TransportEvent> {
+        self.tp_manager
+            .next_event()
+            .or_else(|| self.etp_manager.next_event())
+    }
+
+    /// Queue a PDU, it is written to the driver by `transmit`.
     pub fn send(&mut self, pdu: PDU, time: u64) {
+        if self.fast_packet_manager.is_registered(pdu.pgn()) {
+            self.fast_packet_manager.send(&mut self.transmit_queue, pdu);
+            return;
+        }
+
         match pdu.data_len() {
             0..=8 => {
-                self.can_driver.write(pdu.into());
+                self.transmit_queue.push(pdu);
             }
             9..=1785 => {
-                self.tp_manager.send(&mut self.can_driver, pdu, time);
+                self.tp_manager.send(&mut self.transmit_queue, pdu, time);
             }
             1786..=117_440_505 => {
-                self.etp_manager.send(&mut self.can_driver, pdu, time);
+                self.etp_manager.send(&mut self.transmit_queue, pdu, time);
             }
             _ => {
                 log::error!("Can message to long; > 117.440.505 bytes!");
@@ -44,25 +172,17 @@ impl DataLinkLayer {
     pub fn process(&mut self, network_manager: &NetworkManager, time: u64) -> Vec<PDU> {
         let mut pdus: Vec<PDU> = Vec::new();
 
+        self.can_driver.set_time(time);
+        self.update_filters(network_manager.claimed_address());
+
         for _ in 0..DataLinkLayer::MAX_FRAMES_IN_PER_PROCESS {
             let frame: CanFrame = match self.can_driver.read() {
-                Some(value) => value,
-                None => {
-                    if let Some(pdu) = self.tp_manager.process(
-                        &mut self.can_driver,
-                        network_manager.claimed_address(),
-                        None,
-                        time,
-                    ) {
-                        pdus.push(pdu);
-                    }
-                    if let Some(pdu) = self.etp_manager.process(
-                        &mut self.can_driver,
-                        network_manager.claimed_address(),
-                        None,
-                        time,
-                    ) {
-                        pdus.push(pdu);
+                Ok(value) => value,
+                Err(e) => {
+                    // A closed driver is reported by the bus state.
+                    match e {
+                        nb::Error::Other(CanError::Uninitialised) | nb::Error::WouldBlock => {}
+                        nb::Error::Other(e) => log::error!("Unable to read CAN frame: \"{e:?}\""),
                     }
                     break;
                 }
@@ -71,21 +191,20 @@ impl DataLinkLayer {
             #[cfg(feature = "log_all_can_read")]
             log::debug!("read: {}", &frame);
 
-            let pdu: PDU = (&frame).into();
-
-            // Only listen to global messages and messages ment for us.
-            if !pdu.is_address_global()
-                && !pdu.is_address_specific(network_manager.claimed_address())
-            {
+            // Only listen to global messages, messages ment for us and registered PGNs.
+            // Drivers without hardware filters pass all frames.
+            if !self.filters.iter().any(|f| f.matches(frame.id())) {
                 continue;
             }
 
+            let pdu: PDU = (&frame).into();
+
             #[cfg(feature = "log_can_read")]
             log::debug!("read: {}", &frame);
 
             if pdu.is_tp_connection_management() || pdu.is_tp_data_transfer() {
                 if let Some(pdu) = self.tp_manager.process(
-                    &mut self.can_driver,
+                    &mut self.transmit_queue,
                     network_manager.claimed_address(),
                     Some(pdu),
                     time,
@@ -95,7 +214,7 @@ impl DataLinkLayer {
                 continue;
             } else if pdu.is_etp_connection_management() || pdu.is_etp_data_transfer() {
                 if let Some(pdu) = self.etp_manager.process(
-                    &mut self.can_driver,
+                    &mut self.transmit_queue,
                     network_manager.claimed_address(),
                     Some(pdu),
                     time,
@@ -103,45 +222,60 @@ impl DataLinkLayer {
                     pdus.push(pdu);
                 }
                 continue;
+            } else if self.fast_packet_manager.is_registered(pdu.pgn()) {
+                if let Some(pdu) = self.fast_packet_manager.process(Some(pdu), time) {
+                    pdus.push(pdu);
+                }
+                continue;
             }
 
             pdus.push(pdu);
+        }
 
-            //  IF PGN = REQUEST PGN AND THE DESTINATION IS SPECIFIC        ; specific request
-            //  THEN
-            //      IF DA = ASSIGNED ADDRESS (destination)
-            //      THEN
-            //          SAVE 4 BYTE ID AND 3 DATA BYTES IN REQUEST QUEUE
-            //  IF PGN = REQUEST PGN AND THE DESTINATION IS GLOBAL          ; global request
-            //  THEN
-            //      SAVE 4 BYTE ID AND 3 DATA BYTES IN REQUEST QUEUE
-            //
-            //  IF PF < 240
-            //  THEN
-            //      IF DA = GLOBAL                                          ; PDU1 Format (DA = global)
-            //      THEN
-            //          USE JUMP TABLE FOR PGN VALUES OF INTEREST AND
-            //          IF SA = ID OF SPECIAL INTEREST
-            //          THEN
-            //              SAVE 8 BYTES OF DATA IN DEDICATED BUFFER
-            //          ELSE
-            //              SAVE 12 BYTE MESSAGE (ID AND DATA) IN CIRCULAR QUEUE
-            //      ELSE DA = SPECIFIC                                      ; PDU1 Format (DA = specific)
-            //          USE JUMP TABLE FOR PGN VALUES OF INTEREST AND
-            //          IF SA = ID OF SPECIAL INTEREST VALUES
-            //          THEN
-            //              SAVE 8 BYTES OF DATA IN DEDICATED BUFFER
-            //          ELSE
-            //              SAVE 12 BYTE MESSAGE (ID AND DATA) IN CIRCULAR QUEUE
-            //  IF PF >= 240                                                ; PDU2 Format
-            //  THEN
-            //      USE JUMP TABLE FOR PGN VALUES OF INTEREST AND
-            //      IF SA = ID OF SPECIAL INTEREST
-            //      THEN
-            //          SAVE 8 BYTES OF DATA IN DEDICATED BUFFER
-            //      ELSE
-            //          SAVE 12 BYTE MESSAGE (ID AND DATA) IN CIRCULAR QUEUE
+        // Run the timeouts of the transport sessions, also while frames keep arriving.
+        if let Some(pdu) = self.tp_manager.process(
+            &mut self.transmit_queue,
+            network_manager.claimed_address(),
+            None,
+            time,
+        ) {
+            pdus.push(pdu);
+        }
+        if let Some(pdu) = self.etp_manager.process(
+            &mut self.transmit_queue,
+            network_manager.claimed_address(),
+            None,
+            time,
+        ) {
+            pdus.push(pdu);
         }
+        self.fast_packet_manager.process(None, time);
         pdus
     }
+
+    /// Write the queued frames to the driver, highest priority first, within the transmit budget.
+    pub fn transmit(&mut self, time: u64) {
+        self.can_driver.set_time(time);
+        self.transmit_queue.transmit(&mut self.can_driver, time);
+        self.tp_manager
+            .packets_written(&mut self.transmit_queue, time);
+        self.etp_manager
+            .packets_written(&mut self.transmit_queue, time);
+    }
+
+    pub fn bus_state(&self) -> BusState {
+        self.can_driver.state()
+    }
+
+    /// Close and open the CAN driver again, to recover from bus-off or a lost interface.
+    /// Transport sessions in progress are dropped.
+    pub fn reopen(&mut self) -> Result<(), CanError> {
+        self.can_driver.close();
+        self.transmit_queue.clear();
+        self.tp_manager.reset();
+        self.etp_manager.reset();
+        self.fast_packet_manager.reset();
+        self.filters_changed = true;
+        self.can_driver.open(None)
+    }
 }
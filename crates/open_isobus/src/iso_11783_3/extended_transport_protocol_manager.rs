@@ -1,70 +1,84 @@
-use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use alloc::{collections::VecDeque, vec::Vec};
 
-use crate::{drivers::CanDriverTrait, isobus::IsobusAddress};
+use crate::isobus::IsobusAddress;
 
-use super::{EtpAbortReasons, PDU, PGN};
+use super::{
+    transmit_queue::TransmitQueue,
+    transport_event::TransportEvents,
+    transport_session::{message_pgn, Direction, Session, State},
+    EtpAbortReasons, TransportConfig, TransportEvent, TransportProtocol, PDU, PGN,
+};
 
-// const ETP_TIMEOUT_T1: u64 = 750;
-// const ETP_TIMEOUT_T2: u64 = 1250;
-const ETP_TIMEOUT_T3: u64 = 1750;
-const ETP_TIMEOUT_T4: u64 = 1050;
-
-#[derive(Debug, PartialEq)]
-enum State {
-    Idle,
-    Receiving,
-    Sending,
-}
+/// The number of packets requested with a CTS.
+const ETP_PACKETS_PER_CTS: u8 = 16;
 
 pub struct ExtendedTransportProtocolManager {
+    sessions: Vec<Session>,
     backlog: VecDeque<PDU>,
-    pdu_to_send: Option<PDU>,
-    timeout_time: u64,
-    _receive_buffer: Vec<u8>,
-    receive_pgn: Option<PGN>,
-    _receive_nr_of_packets: u8,
+    max_sessions: usize,
+    max_message_size: usize,
+    config: TransportConfig,
+    events: TransportEvents,
 }
 
 impl ExtendedTransportProtocolManager {
+    pub const DEFAULT_MAX_SESSIONS: usize = 16;
+    /// The largest message received by default, 1 MiB.
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 0x10_0000;
+    /// The largest message that can be sent with ETP.
+    pub const MAX_MESSAGE_SIZE: usize = 117_440_505;
+
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn send(&mut self, can: &mut Box<dyn CanDriverTrait>, pdu: PDU, time: u64) {
-        // Start a new sending connection or
-        // if we are already in a connection, store the PDU in the backlog.
-        match self.state() {
-            State::Idle => {
-                self.open_sending_connection(can, pdu, time);
-            }
-            State::Sending | State::Receiving => {
-                self.backlog.push_back(pdu);
-            }
-        }
+    /// Limit the number of concurrent sessions, inbound and outbound.
+    /// Messages to send wait in the backlog, requests to send are aborted with `NoResources`.
+    pub fn set_max_sessions(&mut self, max_sessions: usize) {
+        self.max_sessions = usize::max(max_sessions, 1);
+    }
+
+    /// Limit the size of received messages, larger requests to send are aborted with `MessageToLarge`.
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = usize::min(max_message_size, Self::MAX_MESSAGE_SIZE);
+    }
+
+    pub fn set_config(&mut self, config: TransportConfig) {
+        self.config = config;
+    }
+
+    /// Queue the events of the sessions, to be taken with `next_event`.
+    pub fn subscribe_events(&mut self) {
+        self.events.subscribe();
+    }
+
+    pub fn next_event(&mut self) -> Option<TransportEvent> {
+        self.events.pop()
+    }
+
+    /// Drop all sessions and messages waiting to be sent.
+    pub fn reset(&mut self) {
+        self.sessions.clear();
+        self.backlog.clear();
+    }
+
+    pub fn send(&mut self, queue: &mut TransmitQueue, pdu: PDU, time: u64) {
+        self.backlog.push_back(pdu);
+        self.open_sessions(queue, time);
     }
 
+    // All pdu's to process are global or ment for us.
     pub fn process(
         &mut self,
-        can: &mut Box<dyn CanDriverTrait>,
+        queue: &mut TransmitQueue,
         claimed_address: IsobusAddress,
         pdu: Option<PDU>,
         time: u64,
     ) -> Option<PDU> {
-        // If connected and messages are not received on time, send a timeout message and change state.
-        if let Some(pdu_to_send) = &self.pdu_to_send {
-            if time > self.timeout_time && self.state() != State::Idle {
-                can.write(
-                    PDU::new_etp_connection_abort(
-                        EtpAbortReasons::Timeout,
-                        pdu_to_send.pgn(),
-                        pdu_to_send.destination_address(),
-                        claimed_address,
-                    )
-                    .into(),
-                );
-                self.close_connection();
-            }
-        }
+        self.packets_written(queue, time);
+        self.process_timeouts(queue, time);
+        self.resume_held_sessions(queue, time);
+        self.open_sessions(queue, time);
 
         // Statements after this need to process a PDU.
         let pdu = match pdu {
@@ -72,182 +86,677 @@ impl ExtendedTransportProtocolManager {
             None => return None,
         };
 
-        // Received a request to send meant for us.
-        if pdu.is_etp_request_to_send() && pdu.is_address_specific(claimed_address) {
-            // When idling, accept the request to send and send a clear to send message.
-            if self.state() == State::Idle {
-                //         let data: [u8; 8] = pdu.data::<8>();
-
-                //         let nr_of_bytes: u16 = u16::from_le_bytes([data[1], data[2]]);
-                //         self.receive_buffer = vec![0xFF; nr_of_bytes as usize];
-                //         self.receive_nr_of_packets = u8::min(data[3], data[4]);
-                //         let packet_pgn: PGN = PGN::from_le_bytes([data[5], data[6], data[7]]);
-
-                //         can.write(PDU::new_tp_clear_to_send(
-                //             self.receive_nr_of_packets,
-                //             1,
-                //             packet_pgn,
-                //             pdu.source_address(),
-                //             claimed_address,
-                //         ).into());
-
-                //         self.receive_pgn = Some(packet_pgn);
-                //         self.timeout_time = time + TP_TIMEOUT_T2;
-                //     } else {
-                //         // If we are already in a connection, abort the new connection.
-                //         can.write(PDU::new_tp_connection_abort(TpAbortReasons::AlreadyConnected, pdu.pgn(), pdu.source_address(), claimed_address).into());
+        // ETP is only used destination specific.
+        if !pdu.is_address_specific(claimed_address) {
+            return None;
+        }
+
+        if pdu.is_etp_data_transfer() {
+            return self.receive_data(queue, &pdu, time);
+        }
+
+        if pdu.is_etp_request_to_send() {
+            self.open_inbound_session(queue, &pdu, time);
+        }
+
+        if pdu.is_etp_data_packet_offset() {
+            self.receive_data_packet_offset(queue, &pdu, time);
+        }
+
+        if pdu.is_etp_clear_to_send() {
+            self.send_data(queue, &pdu, time);
+        }
+
+        // The sent message is reported with `TransportEvent::Completed`, it is not returned.
+        if pdu.is_etp_end_of_message_acknowledge() {
+            let index = self.position(&pdu, Direction::Outbound)?;
+            let session = self.sessions.swap_remove(index);
+            self.events.push(TransportEvent::Completed(session.info()));
+            self.open_sessions(queue, time);
+        }
+
+        if pdu.is_etp_connection_abort() {
+            self.close_aborted_session(&pdu);
+            self.open_sessions(queue, time);
+        }
+
+        None
+    }
+
+    /// Start T3 of the outbound sessions when the last data packet of the window is written.
+    pub fn packets_written(&mut self, queue: &mut TransmitQueue, time: u64) {
+        while let Some(pdu) = queue.take_written(PGN::ETP_DT) {
+            let session = self.sessions.iter_mut().find(|s| {
+                s.direction == Direction::Outbound
+                    && s.source_address == pdu.source_address()
+                    && s.destination_address == pdu.destination_address()
+            });
+            if let Some(session) = session {
+                session.timeout_time = time + self.config.etp_t3;
             }
         }
+    }
 
-        // Received a clear to send meant for us.
-        if pdu.is_etp_clear_to_send()
-            && pdu.is_address_specific(claimed_address)
-            && self.state() == State::Sending
-        {
-            let data: [u8; 8] = pdu.data::<8>();
-            let nr_of_packets = data[1];
-            let next_packet = u32::from_le_bytes([data[2], data[3], data[4], 0x00]);
+    /// The session the received PDU belongs to, matched by the addresses and for connection management by the PGN.
+    fn position(&self, pdu: &PDU, direction: Direction) -> Option<usize> {
+        let (source_address, destination_address) = match direction {
+            Direction::Inbound => (pdu.source_address(), pdu.destination_address()),
+            Direction::Outbound => (pdu.destination_address(), pdu.source_address()),
+        };
+        let pgn = match pdu.is_etp_connection_management() {
+            true => Some(message_pgn(pdu)),
+            false => None,
+        };
 
-            if nr_of_packets == 0 {
-                self.timeout_time = time + ETP_TIMEOUT_T4;
-            } else {
-                self.send_pdu_data(
-                    can,
-                    next_packet,
-                    nr_of_packets,
+        self.sessions.iter().position(|s| {
+            s.direction == direction
+                && s.source_address == source_address
+                && s.destination_address == destination_address
+                && pgn.is_none_or(|pgn| s.pgn == pgn)
+        })
+    }
+
+    /// Start sending the messages in the backlog. Messages to a peer with an outbound session wait,
+    /// so the messages to a peer keep their order.
+    fn open_sessions(&mut self, queue: &mut TransmitQueue, time: u64) {
+        let mut i = 0;
+        while i < self.backlog.len() && self.sessions.len() < self.max_sessions {
+            let pdu = &self.backlog[i];
+            let busy = self.sessions.iter().any(|s| {
+                s.direction == Direction::Outbound
+                    && s.source_address == pdu.source_address()
+                    && s.destination_address == pdu.destination_address()
+            });
+            if busy {
+                i += 1;
+                continue;
+            }
+
+            if let Some(pdu) = self.backlog.remove(i) {
+                let mut session = Session::new(
+                    TransportProtocol::Etp,
+                    Direction::Outbound,
                     pdu.source_address(),
-                    claimed_address,
+                    pdu.destination_address(),
+                    pdu.pgn(),
+                    pdu.data_len(),
+                    pdu.data_len().div_ceil(7) as u32,
                 );
-                self.timeout_time = time + ETP_TIMEOUT_T3;
+                queue.push(PDU::new_etp_request_to_send(
+                    session.nr_of_bytes as u32,
+                    session.pgn,
+                    session.destination_address,
+                    session.source_address,
+                ));
+
+                session.pdu = pdu;
+                session.state = State::WaitingForClearToSend;
+                session.timeout_time = time + self.config.etp_t3;
+                self.events.push(TransportEvent::Started(session.info()));
+                self.sessions.push(session);
             }
         }
+    }
 
-        // Received an end of message meant for us.
-        if pdu.is_etp_end_of_message_acknowledge() && pdu.is_address_specific(claimed_address) {
-            if self.state() == State::Sending {
-                let finished_pdu = self.pdu_to_send.take();
-                self.close_connection();
+    fn open_inbound_session(&mut self, queue: &mut TransmitQueue, pdu: &PDU, time: u64) {
+        let data: [u8; 8] = pdu.data::<8>();
+        let nr_of_bytes = u32::from_le_bytes([data[1], data[2], data[3], data[4]]) as usize;
+        let mut session = Session::new(
+            TransportProtocol::Etp,
+            Direction::Inbound,
+            pdu.source_address(),
+            pdu.destination_address(),
+            message_pgn(pdu),
+            nr_of_bytes,
+            nr_of_bytes.div_ceil(7) as u32,
+        );
+        session.max_packets_per_cts = ETP_PACKETS_PER_CTS;
+
+        let existing = self.sessions.iter().position(|s| {
+            s.direction == Direction::Inbound
+                && s.source_address == session.source_address
+                && s.destination_address == session.destination_address
+        });
+        if let Some(index) = existing {
+            // The data packets of both sessions can not be told apart.
+            if self.sessions[index].pgn != session.pgn {
+                session.abort(queue, &mut self.events, EtpAbortReasons::AlreadyConnected);
+                return;
+            }
 
-                // If we have PDUs in the backlog, start a new connection.
-                if let Some(pdu) = self.backlog.pop_front() {
-                    self.open_sending_connection(can, pdu, time);
-                }
+            // The sender started the same message again.
+            self.sessions.swap_remove(index);
+        }
 
-                return finished_pdu;
+        if self.sessions.len() >= self.max_sessions {
+            session.abort(queue, &mut self.events, EtpAbortReasons::NoResources);
+            return;
+        }
+
+        if nr_of_bytes > self.max_message_size {
+            log::error!(
+                "ETP message from {} refused, {nr_of_bytes} bytes is more than {}",
+                session.source_address,
+                self.max_message_size
+            );
+            session.abort(queue, &mut self.events, EtpAbortReasons::MessageToLarge);
+            return;
+        }
+
+        if nr_of_bytes < 1786 {
+            log::error!(
+                "Invalid ETP RTS from {}: {nr_of_bytes} bytes",
+                session.source_address
+            );
+            session.abort(queue, &mut self.events, EtpAbortReasons::Other);
+            return;
+        }
+
+        session.buffer = Vec::with_capacity(nr_of_bytes);
+        if self.receiving_sessions() < self.config.max_receiving_sessions {
+            session.clear_to_send(queue, time, &self.config);
+        } else {
+            session.hold(queue, time, &self.config);
+        }
+        self.events.push(TransportEvent::Started(session.info()));
+        self.sessions.push(session);
+    }
+
+    /// The number of inbound connections receiving data.
+    fn receiving_sessions(&self) -> usize {
+        self.sessions
+            .iter()
+            .filter(|s| s.direction == Direction::Inbound && s.state != State::Holding)
+            .count()
+    }
+
+    /// Request the data of held connections, when other connections are finished.
+    fn resume_held_sessions(&mut self, queue: &mut TransmitQueue, time: u64) {
+        let mut receiving = self.receiving_sessions();
+        for session in self.sessions.iter_mut() {
+            if receiving >= self.config.max_receiving_sessions {
+                return;
+            }
+
+            if session.state == State::Holding {
+                session.clear_to_send(queue, time, &self.config);
+                receiving += 1;
             }
         }
+    }
 
-        // Received an data transfer meant for us.
-        // if pdu.is_etp_data_transfer() && pdu.is_address_specific(claimed_address) {
-        //     if self.state() == State::Receiving {
-        //         let data: [u8; 8] = pdu.data::<8>();
-        //         let packet_nr = data[0];
+    /// If messages are not received on time, send a timeout message and close the session.
+    fn process_timeouts(&mut self, queue: &mut TransmitQueue, time: u64) {
+        let config = &self.config;
+        let events = &mut self.events;
+        self.sessions.retain_mut(|s| {
+            if s.state == State::Holding {
+                if time >= s.timeout_time {
+                    s.hold(queue, time, config);
+                }
+                return true;
+            }
 
-        //         for i in 0..7 {
-        //             let index = ((packet_nr - 1) * 7 + i) as usize;
-        //             if index >= self.receive_buffer.len() {
-        //                 break;
-        //             }
-        //             self.receive_buffer[index] = data[(i+1) as usize];
-        //         }
+            if time > s.timeout_time {
+                log::error!("ETP session with {} timed out", s.peer());
+                s.abort(queue, events, EtpAbortReasons::Timeout);
+                return false;
+            }
+            true
+        });
+    }
 
-        //         self.timeout_time = time + TP_TIMEOUT_T1;
+    fn receive_data_packet_offset(&mut self, queue: &mut TransmitQueue, pdu: &PDU, time: u64) {
+        let index = match self.position(pdu, Direction::Inbound) {
+            Some(index) => index,
+            None => return,
+        };
+        let session = &mut self.sessions[index];
+        let data: [u8; 8] = pdu.data::<8>();
+        let nr_of_packets = data[1];
+        let offset = u32::from_le_bytes([data[2], data[3], data[4], 0x00]);
+
+        let reason = if session.state != State::WaitingForDataPacketOffset {
+            Some(EtpAbortReasons::UnexpectedDpo)
+        } else if nr_of_packets == 0 || nr_of_packets > session.cts_packets {
+            Some(EtpAbortReasons::DpoPacketsGreaterThanCts)
+        } else if offset != session.next_packet - 1 {
+            Some(EtpAbortReasons::BadDpoOffset)
+        } else {
+            None
+        };
 
-        //         if self.receive_nr_of_packets == packet_nr {
-        //             if let Some(pgn) = self.receive_pgn {
-        //                 can.write(PDU::new_tp_end_of_message_acknowledge(self.receive_buffer.len() as u16, self.receive_nr_of_packets, pgn, pdu.source_address(), claimed_address).into());
-        //             }
-        //             self.close_connection();
-        //         }
-        //     }
-        // }
+        if let Some(reason) = reason {
+            log::error!(
+                "ETP from {} aborted, invalid DPO: \"{reason:?}\"",
+                session.source_address
+            );
+            session.abort(queue, &mut self.events, reason);
+            self.sessions.swap_remove(index);
+            return;
+        }
 
-        None
+        session.offset = offset;
+        session.dpo_packets = nr_of_packets;
+        session.state = State::ReceivingData;
+        session.retransmit_requested = false;
+        session.timeout_time = time + self.config.t1;
     }
 
-    // TODO: Cleanup connection creation
-    fn open_sending_connection(&mut self, can: &mut Box<dyn CanDriverTrait>, pdu: PDU, time: u64) {
-        let number_of_bytes = pdu.data_len() as u32;
+    fn receive_data(&mut self, queue: &mut TransmitQueue, pdu: &PDU, time: u64) -> Option<PDU> {
+        let index = self.position(pdu, Direction::Inbound)?;
+        let session = &mut self.sessions[index];
+        let data: [u8; 8] = pdu.data::<8>();
 
-        can.write(
-            PDU::new_etp_request_to_send(
-                number_of_bytes,
-                pdu.pgn(),
-                pdu.destination_address(),
-                pdu.source_address(),
-            )
-            .into(),
-        );
+        // The sequence number is relative to the offset of the DPO.
+        let sequence_number = data[0];
+        let expected = (session.next_packet - session.offset) as u8;
+
+        // The packets sent before the retransmission request are ignored.
+        if session.retransmit_requested && session.state != State::ReceivingData {
+            return None;
+        }
 
-        self.timeout_time = time + ETP_TIMEOUT_T3;
-        self.pdu_to_send = Some(pdu);
-    }
-
-    fn close_connection(&mut self) {
-        self.pdu_to_send = None;
-        self.timeout_time = u64::MAX;
-        // self.receive_buffer.clear();
-        // self.receive_pgn = None;
-        // self.receive_nr_of_packets = 0;
-    }
-
-    fn send_pdu_data(
-        &self,
-        can: &mut Box<dyn CanDriverTrait>,
-        next_packet: u32,
-        number_of_packets: u8,
-        da: IsobusAddress,
-        sa: IsobusAddress,
-    ) {
-        if let Some(pdu_to_send) = &self.pdu_to_send {
-            can.write(
-                PDU::new_etp_data_packet_offset(
-                    number_of_packets,
-                    next_packet - 1,
-                    pdu_to_send.pgn(),
-                    da,
-                    sa,
-                )
-                .into(),
+        // Request the missing packets again.
+        if session.state == State::ReceivingData
+            && sequence_number > expected
+            && session.retransmits < self.config.max_retransmits
+        {
+            log::warn!(
+                "ETP from {} is missing packet {}, requesting it again",
+                session.source_address,
+                session.next_packet,
             );
+            session.retransmits += 1;
+            session.retransmit_requested = true;
+            session.clear_to_send(queue, time, &self.config);
+            return None;
+        }
 
-            let chunks: Vec<&[u8]> = pdu_to_send.data_raw().chunks(7).collect();
-            for i in 0..number_of_packets {
-                can.write(
-                    PDU::new_etp_data_transfer(
-                        i + 1,
-                        chunks[(next_packet - 1 + i as u32) as usize],
-                        da,
-                        sa,
-                    )
-                    .into(),
-                );
+        let reason = if session.state != State::ReceivingData {
+            Some(EtpAbortReasons::UnexpectedDt)
+        } else if sequence_number < expected {
+            Some(EtpAbortReasons::DuplicateSequenceNumber)
+        } else if sequence_number > expected && self.config.max_retransmits > 0 {
+            Some(EtpAbortReasons::RetransmitLimitReached)
+        } else if sequence_number > expected {
+            Some(EtpAbortReasons::BadSequenceNumber)
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            log::error!(
+                "ETP from {} aborted, expected packet {expected} but received {sequence_number}",
+                session.source_address,
+            );
+            session.abort(queue, &mut self.events, reason);
+            self.sessions.swap_remove(index);
+            return None;
+        }
+
+        let len = usize::min(7, session.nr_of_bytes - session.buffer.len());
+        session.buffer.extend_from_slice(&data[1..=len]);
+        session.timeout_time = time + self.config.t1;
+
+        if session.next_packet < session.nr_of_packets {
+            session.next_packet += 1;
+            if sequence_number == session.dpo_packets {
+                session.progress(&mut self.events, session.next_packet - 1);
+                session.clear_to_send(queue, time, &self.config);
             }
+            return None;
         }
+
+        let session = self.sessions.swap_remove(index);
+        session.progress(&mut self.events, session.nr_of_packets);
+        self.events.push(TransportEvent::Completed(session.info()));
+        queue.push(PDU::new_etp_end_of_message_acknowledge(
+            session.nr_of_bytes as u32,
+            session.pgn,
+            session.source_address,
+            session.destination_address,
+        ));
+
+        // The priority is not part of the transfer, use the default priority.
+        Some(PDU::with_pgn(
+            6,
+            session.pgn,
+            session.destination_address,
+            session.source_address,
+            session.buffer,
+        ))
     }
 
-    fn state(&self) -> State {
-        if self.pdu_to_send.is_some() {
-            return State::Sending;
+    fn send_data(&mut self, queue: &mut TransmitQueue, pdu: &PDU, time: u64) {
+        let index = match self.position(pdu, Direction::Outbound) {
+            Some(index) => index,
+            None => return,
+        };
+        let session = &mut self.sessions[index];
+        let data: [u8; 8] = pdu.data::<8>();
+        let nr_of_packets = data[1];
+        let next_packet = u32::max(u32::from_le_bytes([data[2], data[3], data[4], 0x00]), 1);
+
+        // The receiver holds the connection open.
+        if nr_of_packets == 0 {
+            session.timeout_time = time + self.config.t4;
+            return;
         }
 
-        if self.receive_pgn.is_some() {
-            return State::Receiving;
+        let nr_of_packets = u32::min(
+            nr_of_packets as u32,
+            session.nr_of_packets.saturating_sub(next_packet - 1),
+        ) as u8;
+        queue.push(PDU::new_etp_data_packet_offset(
+            nr_of_packets,
+            next_packet - 1,
+            session.pgn,
+            session.destination_address,
+            session.source_address,
+        ));
+
+        let chunks = session
+            .pdu
+            .data_raw()
+            .chunks(7)
+            .skip(next_packet as usize - 1)
+            .take(nr_of_packets as usize);
+        for (i, chunk) in chunks.enumerate() {
+            let sequence_number = i as u8 + 1;
+            let pdu = PDU::new_etp_data_transfer(
+                sequence_number,
+                chunk,
+                session.destination_address,
+                session.source_address,
+            );
+            if sequence_number == nr_of_packets {
+                queue.push_reported(pdu);
+            } else {
+                queue.push(pdu);
+            }
         }
+        session.progress(&mut self.events, next_packet - 1 + nr_of_packets as u32);
+        // T3 starts when the last packet is written, see `packets_written`.
+        session.timeout_time = u64::MAX;
+    }
 
-        State::Idle
+    fn close_aborted_session(&mut self, pdu: &PDU) {
+        let reason = EtpAbortReasons::from(pdu.data::<2>()[1]);
+        let index = match self
+            .position(pdu, Direction::Outbound)
+            .or_else(|| self.position(pdu, Direction::Inbound))
+        {
+            Some(index) => index,
+            None => return,
+        };
+
+        let session = self.sessions.swap_remove(index);
+        log::error!(
+            "ETP session with {} aborted: \"{reason:?}\"",
+            session.peer()
+        );
+        session.aborted(&mut self.events, reason);
+
+        // Send the message again.
+        if session.direction == Direction::Outbound {
+            self.backlog.push_front(session.pdu);
+        }
     }
 }
 
 impl Default for ExtendedTransportProtocolManager {
     fn default() -> Self {
         Self {
+            sessions: Vec::new(),
             backlog: VecDeque::new(),
-            pdu_to_send: None,
-            timeout_time: u64::MAX,
-            _receive_buffer: Vec::new(),
-            receive_pgn: None,
-            _receive_nr_of_packets: 0,
+            max_sessions: Self::DEFAULT_MAX_SESSIONS,
+            max_message_size: Self::DEFAULT_MAX_MESSAGE_SIZE,
+            config: TransportConfig::default(),
+            events: TransportEvents::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::{
+        iso_11783_3::{EtpAbortReasons, TransmitQueue, TransportConfig, TransportEvent, PDU, PGN},
+        isobus::IsobusAddress,
+    };
+
+    use super::ExtendedTransportProtocolManager;
+
+    fn sent(queue: &mut TransmitQueue) -> Vec<PDU> {
+        core::iter::from_fn(|| queue.pop())
+            .map(|f| PDU::from(&f))
+            .collect()
+    }
+
+    #[test]
+    fn transfers_large_message() {
+        let sender = IsobusAddress(0x26);
+        let receiver = IsobusAddress(128);
+        let mut etp_sender = ExtendedTransportProtocolManager::new();
+        let mut etp_receiver = ExtendedTransportProtocolManager::new();
+        let mut queue_sender = TransmitQueue::new();
+        let mut queue_receiver = TransmitQueue::new();
+
+        let data: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let pdu = PDU::new_ecu_to_vt(receiver, sender, data.clone());
+        etp_sender.subscribe_events();
+        etp_sender.send(&mut queue_sender, pdu, 0);
+
+        let mut received = Vec::new();
+        for time in 0..100 {
+            for pdu in sent(&mut queue_sender) {
+                received.extend(etp_receiver.process(
+                    &mut queue_receiver,
+                    receiver,
+                    Some(pdu),
+                    time,
+                ));
+            }
+            for pdu in sent(&mut queue_receiver) {
+                let returned = etp_sender.process(&mut queue_sender, sender, Some(pdu), time);
+                assert!(returned.is_none());
+            }
         }
+
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].pgn(), PGN::new(0xE700));
+        assert_eq!(received[0].source_address(), sender);
+        assert_eq!(received[0].destination_address(), receiver);
+        assert_eq!(received[0].data_raw(), data.as_slice());
+        let events = core::iter::from_fn(|| etp_sender.next_event());
+        assert_eq!(
+            events
+                .filter(|e| matches!(e, TransportEvent::Completed(_)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn oversized_request_to_send_is_aborted() {
+        let mut etp = ExtendedTransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+        etp.set_max_message_size(2000);
+
+        let rts = |size: u32| {
+            Some(PDU::new_etp_request_to_send(
+                size,
+                PGN::new(0xE700),
+                address,
+                IsobusAddress(0x26),
+            ))
+        };
+
+        etp.process(&mut queue, address, rts(2001), 0);
+        let abort = &sent(&mut queue)[0];
+        assert!(abort.is_etp_connection_abort());
+        assert_eq!(
+            EtpAbortReasons::from(abort.data::<2>()[1]),
+            EtpAbortReasons::MessageToLarge
+        );
+
+        etp.process(&mut queue, address, rts(2000), 0);
+        let cts = &sent(&mut queue)[0];
+        assert!(cts.is_etp_clear_to_send());
+        assert_eq!(cts.data::<5>(), [21, 16, 1, 0, 0]);
+    }
+
+    #[test]
+    fn bad_offset_is_aborted() {
+        let mut etp = ExtendedTransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+        let peer = IsobusAddress(0x26);
+        let pgn = PGN::new(0xE700);
+
+        etp.process(
+            &mut queue,
+            address,
+            Some(PDU::new_etp_request_to_send(2000, pgn, address, peer)),
+            0,
+        );
+        sent(&mut queue);
+
+        let dpo = PDU::new_etp_data_packet_offset(16, 5, pgn, address, peer);
+        etp.process(&mut queue, address, Some(dpo), 10);
+        let abort = &sent(&mut queue)[0];
+        assert_eq!(
+            EtpAbortReasons::from(abort.data::<2>()[1]),
+            EtpAbortReasons::BadDpoOffset
+        );
+    }
+
+    fn data_packet_offset(nr_of_packets: u8, offset: u32, sa: u8) -> Option<PDU> {
+        Some(PDU::new_etp_data_packet_offset(
+            nr_of_packets,
+            offset,
+            PGN::new(0xE700),
+            IsobusAddress(128),
+            IsobusAddress(sa),
+        ))
+    }
+
+    fn data_transfer(sequence_number: u8, sa: u8) -> Option<PDU> {
+        Some(PDU::new_etp_data_transfer(
+            sequence_number,
+            &[sequence_number; 7],
+            IsobusAddress(128),
+            IsobusAddress(sa),
+        ))
+    }
+
+    #[test]
+    fn missing_packets_are_requested_again() {
+        let mut etp = ExtendedTransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+        etp.set_config(TransportConfig {
+            max_retransmits: 1,
+            ..Default::default()
+        });
+
+        let rts =
+            PDU::new_etp_request_to_send(2000, PGN::new(0xE700), address, IsobusAddress(0x26));
+        etp.process(&mut queue, address, Some(rts), 0);
+        sent(&mut queue);
+
+        // Packet 2 is lost, it is requested again and packet 3 is ignored.
+        etp.process(&mut queue, address, data_packet_offset(16, 0, 0x26), 10);
+        etp.process(&mut queue, address, data_transfer(1, 0x26), 10);
+        etp.process(&mut queue, address, data_transfer(3, 0x26), 10);
+        etp.process(&mut queue, address, data_transfer(4, 0x26), 10);
+        let cts = sent(&mut queue);
+        assert_eq!(cts.len(), 1);
+        assert!(cts[0].is_etp_clear_to_send());
+        assert_eq!(cts[0].data::<5>(), [21, 16, 2, 0, 0]);
+
+        // The sequence numbers restart at the offset of the new DPO.
+        etp.process(&mut queue, address, data_packet_offset(16, 1, 0x26), 20);
+        etp.process(&mut queue, address, data_transfer(1, 0x26), 20);
+        assert!(sent(&mut queue).is_empty());
+
+        // The retransmit limit is reached.
+        etp.process(&mut queue, address, data_transfer(3, 0x26), 30);
+        let abort = sent(&mut queue).pop().unwrap();
+        assert!(abort.is_etp_connection_abort());
+        assert_eq!(
+            EtpAbortReasons::from(abort.data::<2>()[1]),
+            EtpAbortReasons::RetransmitLimitReached
+        );
+    }
+
+    #[test]
+    fn connections_are_held_open_while_receiving() {
+        let mut etp = ExtendedTransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+        etp.set_config(TransportConfig {
+            max_receiving_sessions: 1,
+            ..Default::default()
+        });
+
+        // 1786 bytes are 256 packets, 16 windows of 16 packets.
+        let rts = |sa: u8| {
+            Some(PDU::new_etp_request_to_send(
+                1786,
+                PGN::new(0xE700),
+                address,
+                IsobusAddress(sa),
+            ))
+        };
+        etp.process(&mut queue, address, rts(0x10), 0);
+        etp.process(&mut queue, address, rts(0x20), 0);
+        let cts = sent(&mut queue);
+        assert_eq!(cts[0].data::<5>(), [21, 16, 1, 0, 0]);
+        assert_eq!(cts[1].data::<5>(), [21, 0, 1, 0, 0]);
+        assert_eq!(cts[1].destination_address(), IsobusAddress(0x20));
+
+        // The hold is repeated every Th, before T4 of the sender expires.
+        etp.process(&mut queue, address, data_packet_offset(16, 0, 0x10), 300);
+        etp.process(&mut queue, address, data_transfer(1, 0x10), 300);
+        etp.process(&mut queue, address, None, 500);
+        assert_eq!(sent(&mut queue)[0].data::<5>(), [21, 0, 1, 0, 0]);
+
+        // The held connection continues when the first is finished.
+        for sequence_number in 2..=16 {
+            etp.process(
+                &mut queue,
+                address,
+                data_transfer(sequence_number, 0x10),
+                600,
+            );
+        }
+        let mut received = None;
+        for window in 1..16 {
+            etp.process(
+                &mut queue,
+                address,
+                data_packet_offset(16, window * 16, 0x10),
+                700,
+            );
+            for sequence_number in 1..=16 {
+                received = received.or(etp.process(
+                    &mut queue,
+                    address,
+                    data_transfer(sequence_number, 0x10),
+                    700,
+                ));
+            }
+        }
+        assert_eq!(received.unwrap().data_raw().len(), 1786);
+        sent(&mut queue);
+
+        etp.process(&mut queue, address, None, 710);
+        let pdus = sent(&mut queue);
+        assert_eq!(pdus[0].data::<5>(), [21, 16, 1, 0, 0]);
+        assert_eq!(pdus[0].destination_address(), IsobusAddress(0x20));
     }
 }
@@ -0,0 +1,290 @@
+use alloc::vec::Vec;
+
+use crate::isobus::IsobusAddress;
+
+use super::{transmit_queue::TransmitQueue, PDU, PGN};
+
+/// A message being reassembled, the frames of a message share the sequence counter.
+struct Session {
+    source_address: IsobusAddress,
+    pgn: PGN,
+    sequence_counter: u8,
+    next_frame: u8,
+    nr_of_bytes: usize,
+    buffer: Vec<u8>,
+    timeout_time: u64,
+}
+
+/// NMEA 2000 Fast Packet, messages of up to 223 bytes in up to 32 frames of a single PGN.
+/// The first frame carries the size and 6 bytes of data, the next frames 7 bytes each.
+/// Byte 0 of each frame holds the sequence counter (bits 7-5) and the frame counter (bits 4-0).
+///
+/// Fast Packet is used for the registered PGNs only, other PGNs use TP or ETP.
+#[derive(Default)]
+pub struct FastPacketManager {
+    pgns: Vec<PGN>,
+    sessions: Vec<Session>,
+    /// The sequence counter of the last message sent, per PGN.
+    sequence_counters: Vec<(PGN, u8)>,
+}
+
+impl FastPacketManager {
+    pub const MAX_MESSAGE_SIZE: usize = 223;
+    /// The time between two frames of a message before the message is dropped.
+    pub const TIMEOUT: u64 = 750;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send and receive the PGN with Fast Packet.
+    pub fn register_pgn(&mut self, pgn: PGN) {
+        if !self.pgns.contains(&pgn) {
+            self.pgns.push(pgn);
+        }
+    }
+
+    pub fn is_registered(&self, pgn: PGN) -> bool {
+        self.pgns.contains(&pgn)
+    }
+
+    /// Drop all messages being reassembled.
+    pub fn reset(&mut self) {
+        self.sessions.clear();
+    }
+
+    pub fn send(&mut self, queue: &mut TransmitQueue, pdu: PDU) {
+        let data = pdu.data_raw();
+        if data.len() > FastPacketManager::MAX_MESSAGE_SIZE {
+            log::error!("Fast Packet message to long; {} > 223 bytes!", data.len());
+            return;
+        }
+
+        let pgn = pdu.pgn();
+        let sequence_counter = match self.sequence_counters.iter_mut().find(|(p, _)| *p == pgn) {
+            Some((_, counter)) => {
+                *counter = (*counter + 1) & 0b111;
+                *counter
+            }
+            None => {
+                self.sequence_counters.push((pgn, 0));
+                0
+            }
+        };
+
+        let (first, rest) = data.split_at(usize::min(data.len(), 6));
+        let chunks = core::iter::once(first).chain(rest.chunks(7));
+        for (frame_counter, chunk) in chunks.enumerate() {
+            let mut frame = [0xFF; 8];
+            frame[0] = sequence_counter << 5 | frame_counter as u8;
+            if frame_counter == 0 {
+                frame[1] = data.len() as u8;
+                frame[2..2 + chunk.len()].copy_from_slice(chunk);
+            } else {
+                frame[1..1 + chunk.len()].copy_from_slice(chunk);
+            }
+
+            queue.push(PDU::with_pgn(
+                pdu.priority(),
+                pgn,
+                pdu.destination_address(),
+                pdu.source_address(),
+                frame,
+            ));
+        }
+    }
+
+    /// Reassemble a frame of a registered PGN, the complete message is returned with the last frame.
+    pub fn process(&mut self, pdu: Option<PDU>, time: u64) -> Option<PDU> {
+        self.sessions.retain(|session| {
+            let expired = time > session.timeout_time;
+            if expired {
+                log::warn!(
+                    "Fast Packet message 0x{:05X} from {:?} timed out",
+                    session.pgn.as_u32(),
+                    session.source_address
+                );
+            }
+            !expired
+        });
+
+        let pdu = pdu?;
+        let data = pdu.data_raw();
+        if data.is_empty() {
+            return None;
+        }
+        let sequence_counter = data[0] >> 5;
+        let frame_counter = data[0] & 0b1_1111;
+        let position = self.sessions.iter().position(|s| {
+            s.source_address == pdu.source_address()
+                && s.pgn == pdu.pgn()
+                && s.sequence_counter == sequence_counter
+        });
+
+        if frame_counter == 0 {
+            let nr_of_bytes = *data.get(1)? as usize;
+            let mut buffer = Vec::with_capacity(nr_of_bytes);
+            buffer.extend(data.iter().skip(2).take(nr_of_bytes));
+
+            // A first frame with the same sequence counter restarts the message.
+            if let Some(index) = position {
+                self.sessions.swap_remove(index);
+            }
+            let session = Session {
+                source_address: pdu.source_address(),
+                pgn: pdu.pgn(),
+                sequence_counter,
+                next_frame: 1,
+                nr_of_bytes,
+                buffer,
+                timeout_time: time + FastPacketManager::TIMEOUT,
+            };
+            if session.buffer.len() >= nr_of_bytes {
+                return Some(session.into_pdu(&pdu));
+            }
+            self.sessions.push(session);
+            return None;
+        }
+
+        let index = position?;
+        let session = &mut self.sessions[index];
+        if frame_counter != session.next_frame {
+            log::warn!(
+                "Fast Packet message 0x{:05X} from {:?} lost frame {}",
+                session.pgn.as_u32(),
+                session.source_address,
+                session.next_frame
+            );
+            self.sessions.swap_remove(index);
+            return None;
+        }
+
+        let remaining = session.nr_of_bytes - session.buffer.len();
+        session
+            .buffer
+            .extend(data.iter().skip(1).take(usize::min(remaining, 7)));
+        session.next_frame += 1;
+        session.timeout_time = time + FastPacketManager::TIMEOUT;
+
+        if session.buffer.len() < session.nr_of_bytes {
+            return None;
+        }
+        Some(self.sessions.swap_remove(index).into_pdu(&pdu))
+    }
+}
+
+impl Session {
+    /// The reassembled message, with the identifier of the last frame.
+    fn into_pdu(self, frame: &PDU) -> PDU {
+        PDU::with_pgn(
+            frame.priority(),
+            self.pgn,
+            frame.destination_address(),
+            self.source_address,
+            self.buffer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::{
+        iso_11783_3::{TransmitQueue, PDU, PGN},
+        isobus::IsobusAddress,
+    };
+
+    use super::FastPacketManager;
+
+    const GNSS_POSITION_DATA: PGN = PGN::new(0x1F805);
+
+    fn frames(fast_packet: &mut FastPacketManager, sa: u8, data: &[u8]) -> Vec<PDU> {
+        let mut queue = TransmitQueue::new();
+        let pdu = PDU::with_pgn(
+            3,
+            GNSS_POSITION_DATA,
+            IsobusAddress::GLOBAL,
+            IsobusAddress(sa),
+            data,
+        );
+        fast_packet.send(&mut queue, pdu);
+        core::iter::from_fn(|| queue.pop())
+            .map(|f| PDU::from(&f))
+            .collect()
+    }
+
+    #[test]
+    fn splits_messages_into_frames() {
+        let mut fast_packet = FastPacketManager::new();
+        let data: Vec<u8> = (0..43).collect();
+
+        let pdus = frames(&mut fast_packet, 0x10, &data);
+        assert_eq!(pdus.len(), 7);
+        assert_eq!(pdus[0].data::<8>(), [0x00, 43, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(pdus[1].data::<8>(), [0x01, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(
+            pdus[6].data::<8>(),
+            [0x06, 41, 42, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+        assert!(pdus.iter().all(|pdu| pdu.pgn() == GNSS_POSITION_DATA));
+
+        // The sequence counter is incremented per message.
+        let pdus = frames(&mut fast_packet, 0x10, &data[..4]);
+        assert_eq!(pdus.len(), 1);
+        assert_eq!(pdus[0].data::<8>(), [0x20, 4, 0, 1, 2, 3, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn reassembles_interleaved_messages() {
+        let mut sender_a = FastPacketManager::new();
+        let mut sender_b = FastPacketManager::new();
+        let data_a: Vec<u8> = (0..43).collect();
+        let data_b: Vec<u8> = (100..120).collect();
+
+        // Both sources use sequence counter 0, the second message of A uses 1.
+        let a0 = frames(&mut sender_a, 0x10, &data_a);
+        let a1 = frames(&mut sender_a, 0x10, &data_b);
+        let b0 = frames(&mut sender_b, 0x20, &data_b);
+
+        let mut fast_packet = FastPacketManager::new();
+        let mut received = Vec::new();
+        let longest = a0.len();
+        for i in 0..longest {
+            for pdus in [&a0, &b0, &a1] {
+                if let Some(pdu) = pdus.get(i) {
+                    received.extend(fast_packet.process(Some(pdu.clone()), i as u64));
+                }
+            }
+        }
+
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0].source_address(), IsobusAddress(0x20));
+        assert_eq!(received[0].data_raw(), data_b.as_slice());
+        assert_eq!(received[1].source_address(), IsobusAddress(0x10));
+        assert_eq!(received[1].data_raw(), data_b.as_slice());
+        assert_eq!(received[2].source_address(), IsobusAddress(0x10));
+        assert_eq!(received[2].data_raw(), data_a.as_slice());
+        assert_eq!(received[2].pgn(), GNSS_POSITION_DATA);
+    }
+
+    #[test]
+    fn drops_incomplete_messages() {
+        let mut sender = FastPacketManager::new();
+        let data: Vec<u8> = (0..20).collect();
+        let pdus = frames(&mut sender, 0x10, &data);
+
+        // A lost frame drops the message.
+        let mut fast_packet = FastPacketManager::new();
+        assert!(fast_packet.process(Some(pdus[0].clone()), 0).is_none());
+        assert!(fast_packet.process(Some(pdus[2].clone()), 0).is_none());
+        assert!(fast_packet.process(Some(pdus[1].clone()), 0).is_none());
+        assert!(fast_packet.process(Some(pdus[2].clone()), 0).is_none());
+
+        // As does a timeout.
+        assert!(fast_packet.process(Some(pdus[0].clone()), 0).is_none());
+        assert!(fast_packet.process(Some(pdus[1].clone()), 100).is_none());
+        fast_packet.process(None, 1000);
+        assert!(fast_packet.process(Some(pdus[2].clone()), 1000).is_none());
+    }
+}
@@ -1,10 +1,14 @@
 pub mod data_link_layer;
 pub mod pdu;
 pub mod pgn;
+pub mod transmit_queue;
+pub mod transport_config;
 
 pub use data_link_layer::DataLinkLayer;
 pub use pdu::PDU;
 pub use pgn::PGN;
+pub use transmit_queue::TransmitQueue;
+pub use transport_config::TransportConfig;
 
 pub mod transport_protocol_manager;
 pub use transport_protocol_manager::TransportProtocolManager;
@@ -12,9 +16,24 @@ pub use transport_protocol_manager::TransportProtocolManager;
 pub mod extended_transport_protocol_manager;
 pub use extended_transport_protocol_manager::ExtendedTransportProtocolManager;
 
-use crate::isobus::IsobusAddress;
-use alloc::vec;
-use alloc::vec::Vec;
+pub mod fast_packet_manager;
+pub use fast_packet_manager::FastPacketManager;
+
+pub mod request_responder;
+pub use request_responder::{RequestResponder, RequestResponders, RequestResponse};
+
+pub mod transport_event;
+pub use transport_event::{
+    TransportAbortReason, TransportEvent, TransportProtocol, TransportSession,
+};
+
+mod transport_session;
+
+use crate::{
+    isobus::IsobusAddress,
+    isobus_message,
+    message::{BitField, IsobusMessage},
+};
 
 impl PGN {
     pub const TP_CM: PGN = PGN::new(0x00EC00);
@@ -50,13 +69,10 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = Vec::new();
-        vec.push(16);
-        vec.extend_from_slice(&number_of_bytes.to_le_bytes());
-        vec.push(number_of_packets);
-        vec.push(0x10);
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 236, da.into(), sa.into(), vec)
+        let [b0, b1] = number_of_bytes.to_le_bytes();
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [16, b0, b1, number_of_packets, 0x10, p0, p1, p2];
+        PDU::new(7, 0, 0, 236, da.into(), sa.into(), data)
     }
     pub fn is_tp_request_to_send(&self) -> bool {
         self.is_tp_connection_management() && self.data::<1>()[0] == 16
@@ -69,9 +85,18 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = vec![17, number_of_packets, next_packet_number, 0xFF, 0xFF];
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 236, da.into(), sa.into(), vec)
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [
+            17,
+            number_of_packets,
+            next_packet_number,
+            0xFF,
+            0xFF,
+            p0,
+            p1,
+            p2,
+        ];
+        PDU::new(7, 0, 0, 236, da.into(), sa.into(), data)
     }
     pub fn is_tp_clear_to_send(&self) -> bool {
         self.is_tp_connection_management() && self.data::<1>()[0] == 17
@@ -84,13 +109,10 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = Vec::new();
-        vec.push(19);
-        vec.extend_from_slice(&number_of_bytes.to_le_bytes());
-        vec.push(number_of_packets);
-        vec.push(0xFF);
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 235, da.into(), sa.into(), vec)
+        let [b0, b1] = number_of_bytes.to_le_bytes();
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [19, b0, b1, number_of_packets, 0xFF, p0, p1, p2];
+        PDU::new(7, 0, 0, 236, da.into(), sa.into(), data)
     }
     pub fn is_tp_end_of_message_acknowledge(&self) -> bool {
         self.is_tp_connection_management() && self.data::<1>()[0] == 19
@@ -102,13 +124,10 @@ impl PDU {
         message_pgn: PGN,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = Vec::new();
-        vec.push(32);
-        vec.extend_from_slice(&number_of_bytes.to_le_bytes());
-        vec.push(number_of_packets);
-        vec.push(0xFF);
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 236, IsobusAddress::GLOBAL.into(), sa.into(), vec)
+        let [b0, b1] = number_of_bytes.to_le_bytes();
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [32, b0, b1, number_of_packets, 0xFF, p0, p1, p2];
+        PDU::new(7, 0, 0, 236, IsobusAddress::GLOBAL.into(), sa.into(), data)
     }
     pub fn is_tp_broadcast_announce_message(&self) -> bool {
         self.is_tp_connection_management() && self.data::<1>()[0] == 32
@@ -120,9 +139,9 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = vec![255, reason.into(), 0xFF, 0xFF, 0xFF];
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 236, da.into(), sa.into(), vec)
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [255, reason.into(), 0xFF, 0xFF, 0xFF, p0, p1, p2];
+        PDU::new(7, 0, 0, 236, da.into(), sa.into(), data)
     }
     pub fn is_tp_connection_abort(&self) -> bool {
         self.is_tp_connection_management() && self.data::<1>()[0] == 255
@@ -134,16 +153,13 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = Vec::new();
-        vec.push(sequence_number);
-
-        let mut temp_data: [u8; 7] = [0xFF; 7];
-        for (i, v) in data.iter().enumerate() {
-            temp_data[i] = *v;
+        let mut temp_data: [u8; 8] = [0xFF; 8];
+        temp_data[0] = sequence_number;
+        for (i, v) in data.iter().take(7).enumerate() {
+            temp_data[i + 1] = *v;
         }
-        vec.append(&mut temp_data.to_vec());
 
-        PDU::new(7, 0, 0, 235, da.into(), sa.into(), vec)
+        PDU::new(7, 0, 0, 235, da.into(), sa.into(), temp_data)
     }
     pub fn is_tp_data_transfer(&self) -> bool {
         self.pgn().is_tp_dt()
@@ -159,10 +175,10 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = vec![20];
-        vec.extend_from_slice(&number_of_bytes.to_le_bytes());
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 200, da.into(), sa.into(), vec)
+        let [b0, b1, b2, b3] = number_of_bytes.to_le_bytes();
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [20, b0, b1, b2, b3, p0, p1, p2];
+        PDU::new(7, 0, 0, 200, da.into(), sa.into(), data)
     }
     pub fn is_etp_request_to_send(&self) -> bool {
         self.is_etp_connection_management() && self.data::<1>()[0] == 20
@@ -175,10 +191,10 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = vec![21, number_of_packets];
-        vec.extend_from_slice(&next_packet_number.to_le_bytes()[0..2]);
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 200, da.into(), sa.into(), vec)
+        let [n0, n1, n2, _] = next_packet_number.to_le_bytes();
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [21, number_of_packets, n0, n1, n2, p0, p1, p2];
+        PDU::new(7, 0, 0, 200, da.into(), sa.into(), data)
     }
     pub fn is_etp_clear_to_send(&self) -> bool {
         self.is_etp_connection_management() && self.data::<1>()[0] == 21
@@ -191,10 +207,10 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = vec![22, number_of_packets];
-        vec.extend_from_slice(&offset.to_le_bytes()[0..3]);
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 200, da.into(), sa.into(), vec)
+        let [o0, o1, o2, _] = offset.to_le_bytes();
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [22, number_of_packets, o0, o1, o2, p0, p1, p2];
+        PDU::new(7, 0, 0, 200, da.into(), sa.into(), data)
     }
     pub fn is_etp_data_packet_offset(&self) -> bool {
         self.is_etp_connection_management() && self.data::<1>()[0] == 22
@@ -206,10 +222,10 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = vec![23];
-        vec.extend_from_slice(&number_of_bytes.to_le_bytes());
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 200, da.into(), sa.into(), vec)
+        let [b0, b1, b2, b3] = number_of_bytes.to_le_bytes();
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [23, b0, b1, b2, b3, p0, p1, p2];
+        PDU::new(7, 0, 0, 200, da.into(), sa.into(), data)
     }
     pub fn is_etp_end_of_message_acknowledge(&self) -> bool {
         self.is_etp_connection_management() && self.data::<1>()[0] == 23
@@ -221,9 +237,9 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = vec![255, reason.into(), 0xFF, 0xFF, 0xFF];
-        vec.extend_from_slice(&message_pgn.as_bytes());
-        PDU::new(7, 0, 0, 200, da.into(), sa.into(), vec)
+        let [p0, p1, p2] = message_pgn.as_bytes();
+        let data = [255, reason.into(), 0xFF, 0xFF, 0xFF, p0, p1, p2];
+        PDU::new(7, 0, 0, 200, da.into(), sa.into(), data)
     }
     pub fn is_etp_connection_abort(&self) -> bool {
         self.is_etp_connection_management() && self.data::<1>()[0] == 255
@@ -235,23 +251,111 @@ impl PDU {
         da: IsobusAddress,
         sa: IsobusAddress,
     ) -> PDU {
-        let mut vec = Vec::new();
-        vec.push(sequence_number);
-
-        let mut temp_data: [u8; 7] = [0xFF; 7];
-        for (i, v) in data.iter().enumerate() {
-            temp_data[i] = *v;
+        let mut temp_data: [u8; 8] = [0xFF; 8];
+        temp_data[0] = sequence_number;
+        for (i, v) in data.iter().take(7).enumerate() {
+            temp_data[i + 1] = *v;
         }
-        vec.append(&mut temp_data.to_vec());
 
-        PDU::new(7, 0, 0, 199, da.into(), sa.into(), vec)
+        PDU::new(7, 0, 0, 199, da.into(), sa.into(), temp_data)
     }
     pub fn is_etp_data_transfer(&self) -> bool {
         self.pgn().is_etp_dt()
     }
+
+    /// The acknowledgement is sent to global, the address tells who sent the acknowledged message.
+    pub fn new_acknowledgement(acknowledgement: Acknowledgement, sa: IsobusAddress) -> PDU {
+        acknowledgement.to_pdu(IsobusAddress::GLOBAL, sa)
+    }
+    pub fn is_acknowledgement(&self) -> bool {
+        self.pgn().is_acknowledgement()
+    }
+    pub fn acknowledgement(&self) -> Option<Acknowledgement> {
+        Acknowledgement::from_pdu(self).ok()
+    }
+
+    pub fn new_ack(pgn: PGN, da: IsobusAddress, sa: IsobusAddress) -> PDU {
+        PDU::new_acknowledgement(Acknowledgement::new(AcknowledgementType::Ack, pgn, da), sa)
+    }
+    pub fn new_nack(pgn: PGN, da: IsobusAddress, sa: IsobusAddress) -> PDU {
+        PDU::new_acknowledgement(Acknowledgement::new(AcknowledgementType::Nack, pgn, da), sa)
+    }
+    pub fn new_access_denied(pgn: PGN, da: IsobusAddress, sa: IsobusAddress) -> PDU {
+        PDU::new_acknowledgement(
+            Acknowledgement::new(AcknowledgementType::AccessDenied, pgn, da),
+            sa,
+        )
+    }
+    pub fn new_cannot_respond(pgn: PGN, da: IsobusAddress, sa: IsobusAddress) -> PDU {
+        PDU::new_acknowledgement(
+            Acknowledgement::new(AcknowledgementType::CannotRespond, pgn, da),
+            sa,
+        )
+    }
+}
+
+isobus_message! {
+    /// The positive or negative answer to a request or command.
+    pub struct Acknowledgement {
+        pgn: PGN::ACKNOWLEDGEMENT,
+        priority: 6,
+        length: 8,
+        pub control: AcknowledgementType = [0, 8],
+        /// The group function value of a command, 0xFF when not applicable.
+        pub group_function: u8 = [8, 8],
+        /// The address of the node the acknowledgement is for.
+        pub address: IsobusAddress = [32, 8],
+        /// The PGN requested or commanded.
+        pub pgn: PGN = [40, 24],
+    }
+}
+
+impl Acknowledgement {
+    pub fn new(control: AcknowledgementType, pgn: PGN, address: IsobusAddress) -> Self {
+        Self {
+            control,
+            group_function: 0xFF,
+            address,
+            pgn,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AcknowledgementType {
+    Ack = 0,
+    Nack = 1,
+    AccessDenied = 2,
+    CannotRespond = 3,
+    // 4 to 255: Reserved
+    Reserved = 255,
+}
+impl From<u8> for AcknowledgementType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Ack,
+            1 => Self::Nack,
+            2 => Self::AccessDenied,
+            3 => Self::CannotRespond,
+            _ => Self::Reserved,
+        }
+    }
+}
+impl From<AcknowledgementType> for u8 {
+    fn from(value: AcknowledgementType) -> Self {
+        value as u8
+    }
+}
+impl BitField for AcknowledgementType {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+    fn from_bits(bits: u64) -> Self {
+        (bits as u8).into()
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TpAbortReasons {
     Reserved = 0,
     AlreadyConnected = 1,
@@ -302,7 +406,7 @@ impl From<TpAbortReasons> for u8 {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum EtpAbortReasons {
     Reserved = 0,
     AlreadyConnected = 1,
@@ -314,6 +418,12 @@ pub enum EtpAbortReasons {
     BadSequenceNumber = 7,
     DuplicateSequenceNumber = 8,
     MessageToLarge = 9,
+    UnexpectedDpo = 10,
+    UnexpectedDpoPgn = 11,
+    DpoPacketsGreaterThanCts = 12,
+    BadDpoOffset = 13,
+    UnexpectedCtsPgn = 15,
+    CtsPacketsExceedMessage = 16,
     Other = 250,
     // 251 to 255: According to ISO 11783-7 definitions
 }
@@ -330,6 +440,12 @@ impl From<u8> for EtpAbortReasons {
             7 => Self::BadSequenceNumber,
             8 => Self::DuplicateSequenceNumber,
             9 => Self::MessageToLarge,
+            10 => Self::UnexpectedDpo,
+            11 => Self::UnexpectedDpoPgn,
+            12 => Self::DpoPacketsGreaterThanCts,
+            13 => Self::BadDpoOffset,
+            15 => Self::UnexpectedCtsPgn,
+            16 => Self::CtsPacketsExceedMessage,
             250 => Self::Other,
             _ => Self::Other,
         }
@@ -348,6 +464,12 @@ impl From<EtpAbortReasons> for u8 {
             EtpAbortReasons::BadSequenceNumber => 7,
             EtpAbortReasons::DuplicateSequenceNumber => 8,
             EtpAbortReasons::MessageToLarge => 9,
+            EtpAbortReasons::UnexpectedDpo => 10,
+            EtpAbortReasons::UnexpectedDpoPgn => 11,
+            EtpAbortReasons::DpoPacketsGreaterThanCts => 12,
+            EtpAbortReasons::BadDpoOffset => 13,
+            EtpAbortReasons::UnexpectedCtsPgn => 15,
+            EtpAbortReasons::CtsPacketsExceedMessage => 16,
             EtpAbortReasons::Other => 250,
         }
     }
@@ -9,7 +9,68 @@ use super::PGN;
 
 pub struct PduPriority(u8);
 
-#[derive(Clone, Debug)]
+/// The data of a PDU. Data that fits in a single frame is stored inline,
+/// only messages sent with a transport protocol use the heap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Payload {
+    Inline { len: u8, bytes: [u8; 8] },
+    Heap(Vec<u8>),
+}
+
+impl Payload {
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            Payload::Inline { len, bytes } => &bytes[..*len as usize],
+            Payload::Heap(data) => data,
+        }
+    }
+
+    pub fn is_inline(&self) -> bool {
+        matches!(self, Payload::Inline { .. })
+    }
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Payload::Inline {
+            len: 0,
+            bytes: [0; 8],
+        }
+    }
+}
+
+impl From<&[u8]> for Payload {
+    fn from(data: &[u8]) -> Self {
+        if data.len() > 8 {
+            return Payload::Heap(data.to_vec());
+        }
+
+        let mut bytes = [0; 8];
+        bytes[..data.len()].copy_from_slice(data);
+        Payload::Inline {
+            len: data.len() as u8,
+            bytes,
+        }
+    }
+}
+
+impl<const LEN: usize> From<[u8; LEN]> for Payload {
+    fn from(data: [u8; LEN]) -> Self {
+        data.as_slice().into()
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(data: Vec<u8>) -> Self {
+        if data.len() > 8 {
+            Payload::Heap(data)
+        } else {
+            data.as_slice().into()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct PDU {
     priority: u8,
     extended_data_page: u8,
@@ -17,7 +78,7 @@ pub struct PDU {
     pdu_format: u8,
     pdu_specific: u8,
     source_address: u8,
-    data: Vec<u8>,
+    data: Payload,
 }
 
 impl PDU {
@@ -28,7 +89,7 @@ impl PDU {
         pdu_format: u8,
         pdu_specific: u8,
         source_address: u8,
-        data: Vec<u8>,
+        data: impl Into<Payload>,
     ) -> Self {
         Self {
             priority,
@@ -37,10 +98,31 @@ impl PDU {
             pdu_format,
             pdu_specific,
             source_address,
-            data,
+            data: data.into(),
         }
     }
 
+    /// Create a PDU for any PGN, the destination address is ignored for PDU2 PGNs.
+    pub fn with_pgn(
+        priority: u8,
+        pgn: PGN,
+        da: IsobusAddress,
+        sa: IsobusAddress,
+        data: impl Into<Payload>,
+    ) -> Self {
+        let [ps, pf, dp] = pgn.as_bytes();
+        let pdu_specific = if pf < 240 { da.0 } else { ps };
+        Self::new(
+            priority,
+            (dp >> 1) & 1,
+            dp & 1,
+            pf,
+            pdu_specific,
+            sa.0,
+            data,
+        )
+    }
+
     pub fn from_pgn(pgn: PGN, source_address: IsobusAddress, data: Vec<u8>) -> Option<Self> {
         match pgn {
             PGN::REQUEST => Some(Self::new_request(
@@ -77,21 +159,21 @@ impl PDU {
     pub fn source_address(&self) -> IsobusAddress {
         IsobusAddress(self.source_address)
     }
+    /// The first `LEN` bytes of the data, missing bytes are `0xFF`.
     pub fn data<const LEN: usize>(&self) -> [u8; LEN] {
         let mut data: [u8; LEN] = [0xFF; LEN];
-        // for i in 0..usize::min(data.len(), LEN) {
-        //     data[i] = self.data[i]; // TODO, should never throw a panic but we can do better...
-        // }
-
-        let len = data.len();
-        data[..usize::min(len, LEN)].copy_from_slice(&self.data[..usize::min(len, LEN)]);
+        let len = usize::min(self.data_len(), LEN);
+        data[..len].copy_from_slice(&self.data_raw()[..len]);
 
         data
     }
     pub fn data_len(&self) -> usize {
-        self.data.len()
+        self.data_raw().len()
     }
     pub fn data_raw(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+    pub fn payload(&self) -> &Payload {
         &self.data
     }
 
@@ -137,30 +219,23 @@ impl PDU {
     }
 
     pub fn new_request(da: IsobusAddress, sa: IsobusAddress, pgn: PGN) -> PDU {
-        PDU::new(6, 0, 0, 234, da.into(), sa.into(), pgn.as_bytes().to_vec())
+        PDU::new(6, 0, 0, 234, da.into(), sa.into(), pgn.as_bytes())
+    }
+    pub fn is_request(&self) -> bool {
+        self.pgn().is_request()
+    }
+    /// The PGN asked for with a request.
+    pub fn requested_pgn(&self) -> PGN {
+        PGN::from_le_bytes(self.data::<3>())
     }
     // pub fn new_bam(da: IsobusAddress, sa: IsobusAddress, pgn: PGN) -> PDU {
     //     PDU::new(6, 0, 0, 234, da.into(), sa.into(), pgn.as_bytes().to_vec())
     // }
 }
 
-impl Default for PDU {
-    fn default() -> Self {
-        Self {
-            priority: 0,
-            extended_data_page: 0,
-            data_page: 0,
-            pdu_format: 0,
-            pdu_specific: 0,
-            source_address: 0,
-            data: Vec::with_capacity(8),
-        }
-    }
-}
-
 impl From<PDU> for CanFrame {
     fn from(pdu: PDU) -> Self {
-        CanFrame::new(pdu.id(), &pdu.data)
+        CanFrame::new(pdu.id(), pdu.data_raw())
     }
 }
 
@@ -174,7 +249,83 @@ impl From<&CanFrame> for PDU {
             pdu_format: (id >> 16 & 0b11111111) as u8,
             pdu_specific: (id >> 8 & 0b11111111) as u8,
             source_address: (id & 0b11111111) as u8,
-            data: frame.data().to_vec(),
+            data: frame.data().into(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::isobus::{CanFrame, IsobusAddress};
+
+    use crate::iso_11783_3::AcknowledgementType;
+
+    use super::{Payload, PDU, PGN};
+
+    #[test]
+    fn single_frame_is_stored_inline() {
+        let frame = CanFrame::new(0x18EAFF80, &[0x00, 0xEE, 0x00]);
+        let pdu = PDU::from(&frame);
+
+        assert!(pdu.payload().is_inline());
+        assert_eq!(pdu.data_raw(), &[0x00, 0xEE, 0x00]);
+        assert_eq!(pdu.data::<4>(), [0x00, 0xEE, 0x00, 0xFF]);
+        assert_eq!(CanFrame::from(pdu), frame);
+    }
+
+    #[test]
+    fn multi_packet_data_uses_the_heap() {
+        let pdu = PDU::new_ecu_to_vt(IsobusAddress(38), IsobusAddress(128), vec![0x11; 9]);
+
+        assert_eq!(pdu.payload(), &Payload::Heap(vec![0x11; 9]));
+        assert_eq!(pdu.data_len(), 9);
+    }
+
+    #[test]
+    fn pdu_from_pgn() {
+        let pdu1 = PDU::with_pgn(
+            6,
+            PGN::new(0x01EA00),
+            IsobusAddress(38),
+            IsobusAddress(128),
+            [],
+        );
+        assert_eq!(pdu1.pgn(), PGN::new(0x01EA00));
+        assert_eq!(pdu1.destination_address(), IsobusAddress(38));
+        assert_eq!(pdu1.id().as_raw(), 0x19EA2680);
+
+        let pdu2 = PDU::with_pgn(
+            6,
+            PGN::new(0xFECA),
+            IsobusAddress(38),
+            IsobusAddress(128),
+            [],
+        );
+        assert_eq!(pdu2.pgn(), PGN::new(0xFECA));
+        assert!(pdu2.is_address_global());
+        assert_eq!(pdu2.id().as_raw(), 0x18FECA80);
+    }
+
+    #[test]
+    fn acknowledgement_round_trip() {
+        let pdu = PDU::new_cannot_respond(PGN::new(0xFEDA), IsobusAddress(38), IsobusAddress(128));
+        assert_eq!(pdu.id().as_raw(), 0x18E8FF80);
+        assert_eq!(pdu.data::<8>(), [3, 0xFF, 0xFF, 0xFF, 38, 0xDA, 0xFE, 0x00]);
+
+        let acknowledgement = pdu.acknowledgement().unwrap();
+        assert_eq!(acknowledgement.control, AcknowledgementType::CannotRespond);
+        assert_eq!(acknowledgement.address, IsobusAddress(38));
+        assert_eq!(acknowledgement.pgn, PGN::new(0xFEDA));
+        let pdu2 = PDU::new_acknowledgement(acknowledgement, IsobusAddress(128));
+        assert_eq!(pdu2.id(), pdu.id());
+        assert_eq!(pdu2.data_raw(), pdu.data_raw());
+
+        assert!(
+            PDU::new_request(IsobusAddress(38), IsobusAddress(128), PGN::new(0xFEDA))
+                .acknowledgement()
+                .is_none()
+        );
+    }
+}
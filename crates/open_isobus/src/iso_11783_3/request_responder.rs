@@ -0,0 +1,65 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::isobus::IsobusAddress;
+
+use super::{Acknowledgement, AcknowledgementType, PDU, PGN};
+
+/// The answer of a responder to a request.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RequestResponse {
+    /// The data of the requested PGN, sent with TP or BAM when larger than 8 bytes.
+    Data(Vec<u8>),
+    /// Answer a request sent to us with an acknowledgement, global requests are not answered.
+    Acknowledgement(AcknowledgementType),
+    /// Do not answer, e.g. when the application answers the request itself.
+    Ignore,
+}
+
+/// Answers the requests for a PGN, called with the received request.
+pub type RequestResponder = Box<dyn FnMut(&PDU) -> RequestResponse>;
+
+/// The responders of the requested PGNs.
+#[derive(Default)]
+pub struct RequestResponders {
+    responders: Vec<(PGN, RequestResponder)>,
+}
+
+impl RequestResponders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the requests for the PGN, replacing the previous responder of the PGN.
+    pub fn register(&mut self, pgn: PGN, responder: RequestResponder) {
+        match self.responders.iter_mut().find(|(p, _)| *p == pgn) {
+            Some((_, r)) => *r = responder,
+            None => self.responders.push((pgn, responder)),
+        }
+    }
+
+    pub fn contains(&self, pgn: PGN) -> bool {
+        self.responders.iter().any(|(p, _)| *p == pgn)
+    }
+
+    /// The response to a global or specific request, `None` without a responder for the PGN.
+    pub fn respond(&mut self, request: &PDU, claimed_address: IsobusAddress) -> Option<PDU> {
+        let pgn = request.requested_pgn();
+        let (_, responder) = self.responders.iter_mut().find(|(p, _)| *p == pgn)?;
+
+        // A global request is answered to global.
+        let da = match request.is_address_global() {
+            true => IsobusAddress::GLOBAL,
+            false => request.source_address(),
+        };
+        match responder(request) {
+            RequestResponse::Data(data) => Some(PDU::with_pgn(6, pgn, da, claimed_address, data)),
+            RequestResponse::Acknowledgement(control) if !request.is_address_global() => {
+                Some(PDU::new_acknowledgement(
+                    Acknowledgement::new(control, pgn, request.source_address()),
+                    claimed_address,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use alloc::{boxed::Box, collections::VecDeque};
+
+use crate::{drivers::CanDriverTrait, isobus::CanFrame};
+
+use super::{PDU, PGN};
+
+/// Frames waiting to be written to the driver, highest priority first.
+/// Frames with the same priority keep their order, so transport protocol packets stay in sequence.
+/// The TP and ETP data packets wait behind the other frames of their priority, so a CTS or an abort
+/// is not held up by a window of packets of another session.
+///
+/// The queue is drained with a budget of frames per call to [`TransmitQueue::transmit`] and frames per ms,
+/// so a large transfer can not starve other messages or flood the bus.
+pub struct TransmitQueue {
+    /// Two lanes per priority, the second one for the transport data packets.
+    /// The frames are marked to be reported once written.
+    queues: [VecDeque<(CanFrame, bool)>; 16],
+    written: VecDeque<CanFrame>,
+    max_frames_per_process: u16,
+    max_frames_per_ms: u16,
+    budget: u16,
+    last_time: Option<u64>,
+}
+
+impl TransmitQueue {
+    pub const DEFAULT_MAX_FRAMES_PER_PROCESS: u16 = 32;
+    /// About the maximum of a 250 kbit/s bus, which fits 1.8 frames per ms.
+    pub const DEFAULT_MAX_FRAMES_PER_MS: u16 = 2;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of frames written per call to `transmit`, and per ms.
+    /// Both limits are at least one frame.
+    pub fn set_budget(&mut self, max_frames_per_process: u16, max_frames_per_ms: u16) {
+        self.max_frames_per_process = u16::max(max_frames_per_process, 1);
+        self.max_frames_per_ms = u16::max(max_frames_per_ms, 1);
+    }
+
+    pub fn push(&mut self, pdu: PDU) {
+        self.queue(pdu, false);
+    }
+
+    /// Queue the PDU and report it with `take_written` once it is written,
+    /// e.g. to start a timeout when the last packet of a window is on the bus.
+    pub fn push_reported(&mut self, pdu: PDU) {
+        self.queue(pdu, true);
+    }
+
+    fn queue(&mut self, pdu: PDU, report: bool) {
+        let priority = (pdu.priority() & 0b111) as usize;
+        let data = pdu.is_tp_data_transfer() || pdu.is_etp_data_transfer();
+        self.queues[priority * 2 + data as usize].push_back((pdu.into(), report));
+    }
+
+    /// Take a reported frame of the PGN that is written, or dropped after a write error.
+    pub fn take_written(&mut self, pgn: PGN) -> Option<PDU> {
+        let index = self
+            .written
+            .iter()
+            .position(|f| PDU::from(f).pgn() == pgn)?;
+        self.written.remove(index).map(|f| PDU::from(&f))
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.is_empty())
+    }
+
+    /// Remove the next frame to transmit, e.g. to forward the frames without a driver.
+    pub fn pop(&mut self) -> Option<CanFrame> {
+        let (frame, report) = self.queues.iter_mut().find_map(|q| q.pop_front())?;
+        if report {
+            self.written.push_back(frame);
+        }
+        Some(frame)
+    }
+
+    pub fn clear(&mut self) {
+        self.queues.iter_mut().for_each(|q| q.clear());
+        self.written.clear();
+    }
+
+    /// Write queued frames to the driver, until the budget is used or the transmit buffer of the driver is full.
+    pub fn transmit(&mut self, can: &mut Box<dyn CanDriverTrait>, time: u64) {
+        let elapsed = match self.last_time {
+            Some(last_time) => time.saturating_sub(last_time),
+            None => u64::MAX,
+        };
+        self.last_time = Some(time);
+        self.budget = u64::min(
+            (self.budget as u64)
+                .saturating_add(elapsed.saturating_mul(self.max_frames_per_ms as u64)),
+            self.max_frames_per_process as u64,
+        ) as u16;
+
+        while self.budget > 0 {
+            let queue = match self.queues.iter_mut().find(|q| !q.is_empty()) {
+                Some(queue) => queue,
+                None => return,
+            };
+
+            let (frame, report) = match queue.front() {
+                Some(&front) => front,
+                None => return,
+            };
+
+            let written = match can.write(frame) {
+                Ok(()) => true,
+                // Retry the frame in the next call.
+                Err(nb::Error::WouldBlock) => return,
+                Err(nb::Error::Other(e)) => {
+                    log::error!("Unable to write CAN frame: \"{e:?}\"");
+                    false
+                }
+            };
+            queue.pop_front();
+            if report {
+                self.written.push_back(frame);
+            }
+            if !written {
+                return;
+            }
+            self.budget -= 1;
+        }
+    }
+}
+
+impl Default for TransmitQueue {
+    fn default() -> Self {
+        Self {
+            queues: Default::default(),
+            written: VecDeque::new(),
+            max_frames_per_process: Self::DEFAULT_MAX_FRAMES_PER_PROCESS,
+            max_frames_per_ms: Self::DEFAULT_MAX_FRAMES_PER_MS,
+            budget: 0,
+            last_time: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+
+    use crate::{
+        drivers::{CanDriverTrait, VirtualCanBus},
+        iso_11783_3::{PDU, PGN},
+        IsobusAddress,
+    };
+
+    use super::TransmitQueue;
+
+    fn received(probe: &mut dyn CanDriverTrait) -> Vec<u8> {
+        core::iter::from_fn(|| probe.read().ok())
+            .map(|f| f.data()[0])
+            .collect()
+    }
+
+    #[test]
+    fn higher_priority_frames_go_first() {
+        let bus = VirtualCanBus::new();
+        let mut can: Box<dyn CanDriverTrait> = Box::new(bus.connect());
+        let mut probe = bus.connect();
+        can.open(None).unwrap();
+        probe.open(None).unwrap();
+
+        let mut queue = TransmitQueue::new();
+        for i in 1..=3 {
+            queue.push(PDU::new_tp_data_transfer(
+                i,
+                &[],
+                IsobusAddress(38),
+                IsobusAddress(128),
+            ));
+        }
+        queue.push(PDU::new_address_claimed(0.into(), IsobusAddress(128)));
+
+        queue.transmit(&mut can, 0);
+        bus.flush();
+
+        // The address claim has priority 6, the data transfers 7.
+        assert_eq!(received(&mut probe), [0, 1, 2, 3]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn control_frames_go_before_data_of_the_same_priority() {
+        let bus = VirtualCanBus::new();
+        let mut can: Box<dyn CanDriverTrait> = Box::new(bus.connect());
+        let mut probe = bus.connect();
+        can.open(None).unwrap();
+        probe.open(None).unwrap();
+
+        let mut queue = TransmitQueue::new();
+        for i in 1..=2 {
+            queue.push(PDU::new_tp_data_transfer(
+                i,
+                &[],
+                IsobusAddress(38),
+                IsobusAddress(128),
+            ));
+        }
+        queue.push_reported(PDU::new_tp_data_transfer(
+            3,
+            &[],
+            IsobusAddress(38),
+            IsobusAddress(128),
+        ));
+        queue.push(PDU::new_tp_clear_to_send(
+            1,
+            1,
+            PGN::from_le_bytes([0, 0xE7, 0]),
+            IsobusAddress(128),
+            IsobusAddress(38),
+        ));
+        assert!(queue.take_written(PGN::TP_DT).is_none());
+
+        queue.transmit(&mut can, 0);
+        bus.flush();
+
+        // The CTS (control byte 17) and the data transfers both have priority 7.
+        assert_eq!(received(&mut probe), [17, 1, 2, 3]);
+        // Only the reported frame is handed back once written.
+        let written = queue.take_written(PGN::TP_DT).unwrap();
+        assert_eq!(written.data::<1>(), [3]);
+        assert!(queue.take_written(PGN::TP_DT).is_none());
+    }
+
+    #[test]
+    fn frames_are_limited_by_the_budget() {
+        let bus = VirtualCanBus::new();
+        let mut can: Box<dyn CanDriverTrait> = Box::new(bus.connect());
+        let mut probe = bus.connect();
+        can.open(None).unwrap();
+        probe.open(None).unwrap();
+
+        let mut queue = TransmitQueue::new();
+        queue.set_budget(4, 1);
+        for i in 1..=10 {
+            queue.push(PDU::new_tp_data_transfer(
+                i,
+                &[],
+                IsobusAddress(38),
+                IsobusAddress(128),
+            ));
+        }
+
+        queue.transmit(&mut can, 0);
+        assert_eq!(received(&mut probe), [1, 2, 3, 4]);
+
+        // Two ms later, only two frames are allowed.
+        queue.transmit(&mut can, 2);
+        assert_eq!(received(&mut probe), [5, 6]);
+
+        // The number of frames per process still applies after a long pause.
+        queue.transmit(&mut can, 1000);
+        assert_eq!(received(&mut probe), [7, 8, 9, 10]);
+    }
+}
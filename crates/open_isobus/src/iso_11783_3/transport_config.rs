@@ -0,0 +1,40 @@
+/// The timing and flow control of the transport protocols (TP and ETP), in ms.
+/// The defaults are the values of ISO 11783-3, test rigs can use shorter or longer timeouts.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TransportConfig {
+    /// The receiver waits at most `t1` for the next data packet.
+    pub t1: u64,
+    /// The receiver waits at most `t2` for the first data packet after a CTS.
+    pub t2: u64,
+    /// The TP sender waits at most `tp_t3` for a CTS or EoMA after the last data packet.
+    pub tp_t3: u64,
+    /// The ETP sender waits at most `etp_t3` for a CTS or EoMA after the last data packet.
+    pub etp_t3: u64,
+    /// The sender waits at most `t4` for the next CTS after a CTS holding the connection open.
+    pub t4: u64,
+    /// A receiver holding a connection open sends a CTS without packets every `th`.
+    pub th: u64,
+    /// The time between the data packets of a broadcast, 50 to 200 ms.
+    pub bam_packet_interval: u64,
+    /// The number of times missing packets are requested again, before the connection is aborted.
+    pub max_retransmits: u8,
+    /// The number of inbound connections receiving data at the same time,
+    /// other connections are held open until one is finished.
+    pub max_receiving_sessions: usize,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            t1: 750,
+            t2: 1250,
+            tp_t3: 1250,
+            etp_t3: 1750,
+            t4: 1050,
+            th: 500,
+            bam_packet_interval: 50,
+            max_retransmits: 2,
+            max_receiving_sessions: 4,
+        }
+    }
+}
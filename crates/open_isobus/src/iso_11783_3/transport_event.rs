@@ -0,0 +1,95 @@
+use alloc::collections::VecDeque;
+
+use crate::isobus::IsobusAddress;
+
+use super::{EtpAbortReasons, TpAbortReasons, PGN};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransportProtocol {
+    Tp,
+    Etp,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransportAbortReason {
+    Tp(TpAbortReasons),
+    Etp(EtpAbortReasons),
+}
+
+impl From<TpAbortReasons> for TransportAbortReason {
+    fn from(reason: TpAbortReasons) -> Self {
+        TransportAbortReason::Tp(reason)
+    }
+}
+
+impl From<EtpAbortReasons> for TransportAbortReason {
+    fn from(reason: EtpAbortReasons) -> Self {
+        TransportAbortReason::Etp(reason)
+    }
+}
+
+/// A TP or ETP session, the same for all events of the session.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TransportSession {
+    pub protocol: TransportProtocol,
+    pub pgn: PGN,
+    pub source_address: IsobusAddress,
+    /// `IsobusAddress::GLOBAL` for a broadcast (BAM).
+    pub destination_address: IsobusAddress,
+    /// The size of the message.
+    pub nr_of_bytes: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransportEvent {
+    /// A message is announced with a RTS or BAM, sent or received.
+    Started(TransportSession),
+    /// The bytes and packets queued for sending or received so far.
+    /// Connections report progress per CTS window, broadcasts per packet.
+    Progress {
+        session: TransportSession,
+        bytes: usize,
+        packets: u32,
+    },
+    Completed(TransportSession),
+    /// The session is aborted by us or the peer, or timed out.
+    /// A message aborted while sending is sent again, starting a new session.
+    Aborted {
+        session: TransportSession,
+        reason: TransportAbortReason,
+    },
+}
+
+impl TransportEvent {
+    pub fn session(&self) -> &TransportSession {
+        match self {
+            TransportEvent::Started(session)
+            | TransportEvent::Progress { session, .. }
+            | TransportEvent::Completed(session)
+            | TransportEvent::Aborted { session, .. } => session,
+        }
+    }
+}
+
+/// The events of a transport manager, only queued after subscribing.
+#[derive(Default)]
+pub(crate) struct TransportEvents {
+    subscribed: bool,
+    events: VecDeque<TransportEvent>,
+}
+
+impl TransportEvents {
+    pub fn subscribe(&mut self) {
+        self.subscribed = true;
+    }
+
+    pub fn push(&mut self, event: TransportEvent) {
+        if self.subscribed {
+            self.events.push_back(event);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<TransportEvent> {
+        self.events.pop_front()
+    }
+}
@@ -1,60 +1,71 @@
-use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+use alloc::{collections::VecDeque, vec::Vec};
 
-use crate::{drivers::CanDriverTrait, isobus::IsobusAddress};
+use crate::isobus::IsobusAddress;
 
-use super::{TpAbortReasons, PDU, PGN};
-
-const TP_TIMEOUT_T1: u64 = 750;
-const TP_TIMEOUT_T2: u64 = 1250;
-const TP_TIMEOUT_T3: u64 = 1750;
-const TP_TIMEOUT_T4: u64 = 1050;
-
-#[derive(Debug, PartialEq)]
-enum State {
-    Idle,
-    Sending,
-    Receiving,
-}
+use super::{
+    transmit_queue::TransmitQueue,
+    transport_event::TransportEvents,
+    transport_session::{message_pgn, Direction, Session, State},
+    TpAbortReasons, TransportConfig, TransportEvent, TransportProtocol, PDU, PGN,
+};
 
 pub struct TransportProtocolManager {
+    sessions: Vec<Session>,
     backlog: VecDeque<PDU>,
-    pdu_to_send: Option<PDU>,
-    timeout_time: u64,
-    receive_buffer: Vec<u8>,
-    receive_pgn: Option<PGN>,
-    receive_nr_of_packets: u8,
+    max_sessions: usize,
+    config: TransportConfig,
+    events: TransportEvents,
 }
 
 impl TransportProtocolManager {
+    pub const DEFAULT_MAX_SESSIONS: usize = 16;
+
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn send(&mut self, can: &mut Box<dyn CanDriverTrait>, pdu: PDU, time: u64) {
-        // Start a new sending connection or
-        // if we are already in a connection, store the PDU in the backlog.
-        match self.state() {
-            State::Idle => {
-                self.open_sending_connection(can, pdu, time);
-            }
-            State::Sending | State::Receiving => {
-                self.backlog.push_back(pdu);
-            }
-        }
+    /// Limit the number of concurrent sessions, inbound and outbound.
+    /// Messages to send wait in the backlog, requests to send are aborted with `NoResources`.
+    pub fn set_max_sessions(&mut self, max_sessions: usize) {
+        self.max_sessions = usize::max(max_sessions, 1);
+    }
+
+    pub fn set_config(&mut self, config: TransportConfig) {
+        self.config = config;
+    }
+
+    /// Queue the events of the sessions, to be taken with `next_event`.
+    pub fn subscribe_events(&mut self) {
+        self.events.subscribe();
+    }
+
+    pub fn next_event(&mut self) -> Option<TransportEvent> {
+        self.events.pop()
+    }
+
+    /// Drop all sessions and messages waiting to be sent.
+    pub fn reset(&mut self) {
+        self.sessions.clear();
+        self.backlog.clear();
+    }
+
+    pub fn send(&mut self, queue: &mut TransmitQueue, pdu: PDU, time: u64) {
+        self.backlog.push_back(pdu);
+        self.open_sessions(queue, time);
     }
 
     // All pdu's to process are global or ment for us.
     pub fn process(
         &mut self,
-        can: &mut Box<dyn CanDriverTrait>,
+        queue: &mut TransmitQueue,
         claimed_address: IsobusAddress,
         pdu: Option<PDU>,
         time: u64,
     ) -> Option<PDU> {
-        // If connected check if a timeout has occurred.
-        if self.is_connected() {
-            self.process_timeout(can, claimed_address, time);
-        }
+        self.packets_written(queue, time);
+        self.process_timeouts(queue, time);
+        self.resume_held_sessions(queue, time);
+        self.open_sessions(queue, time);
 
         // Statements after this need to process a PDU.
         // Return if no pdu was given
@@ -63,254 +74,903 @@ impl TransportProtocolManager {
             None => return None,
         };
 
-        // Received a request to send meant for us.
-        if pdu.is_tp_request_to_send() {
-            // When idling, accept the request to send and send a clear to send message.
-            if self.state() == State::Idle {
-                let data: [u8; 8] = pdu.data::<8>();
-
-                let nr_of_bytes: u16 = u16::from_le_bytes([data[1], data[2]]);
-                self.receive_buffer = vec![0xFF; nr_of_bytes as usize];
-                self.receive_nr_of_packets = u8::min(data[3], data[4]);
-                let packet_pgn: PGN = PGN::from_le_bytes([data[5], data[6], data[7]]);
-
-                can.write(
-                    PDU::new_tp_clear_to_send(
-                        self.receive_nr_of_packets,
-                        1,
-                        packet_pgn,
-                        pdu.source_address(),
-                        claimed_address,
-                    )
-                    .into(),
-                );
+        if pdu.is_tp_data_transfer() {
+            return self.receive_data(queue, &pdu, time);
+        }
 
-                self.receive_pgn = Some(packet_pgn);
-                self.timeout_time = time + TP_TIMEOUT_T2;
-            } else {
-                // If we are already in a connection, abort the new connection.
-                can.write(
-                    PDU::new_tp_connection_abort(
-                        TpAbortReasons::AlreadyConnected,
-                        pdu.pgn(),
-                        pdu.source_address(),
-                        claimed_address,
-                    )
-                    .into(),
-                );
-            }
+        if pdu.is_tp_broadcast_announce_message() && pdu.is_address_global() {
+            self.open_broadcast_session(&pdu, time);
         }
 
-        // Received a clear to send meant for us.
-        if pdu.is_tp_clear_to_send() && self.state() == State::Sending {
-            let data: [u8; 8] = pdu.data::<8>();
-            let nr_of_packets = data[1];
-            let next_packet = data[2];
+        // Connection management meant for us.
+        if !pdu.is_address_specific(claimed_address) {
+            return None;
+        }
 
-            if nr_of_packets == 0 {
-                self.timeout_time = time + TP_TIMEOUT_T4;
-            } else {
-                self.send_pdu_data(
-                    can,
-                    next_packet,
-                    nr_of_packets,
-                    pdu.source_address(),
-                    claimed_address,
-                );
-                self.timeout_time = time + TP_TIMEOUT_T3;
-            }
+        if pdu.is_tp_request_to_send() {
+            self.open_inbound_session(queue, &pdu, time);
         }
 
-        // Received an end of message meant for us.
-        if pdu.is_tp_end_of_message_acknowledge() && self.state() == State::Sending {
-            let finished_pdu = self.pdu_to_send.take();
-            self.close_connection();
+        if pdu.is_tp_clear_to_send() {
+            self.send_data(queue, &pdu, time);
+        }
 
-            // If we have PDUs in the backlog, start a new connection.
-            if let Some(pdu) = self.backlog.pop_front() {
-                self.open_sending_connection(can, pdu, time);
-            }
+        // The sent message is reported with `TransportEvent::Completed`, it is not returned.
+        if pdu.is_tp_end_of_message_acknowledge() {
+            let index = self.position(&pdu, Direction::Outbound)?;
+            let session = self.sessions.swap_remove(index);
+            self.events.push(TransportEvent::Completed(session.info()));
+            self.open_sessions(queue, time);
+        }
 
-            return finished_pdu;
+        if pdu.is_tp_connection_abort() {
+            self.close_aborted_session(&pdu);
+            self.open_sessions(queue, time);
         }
 
-        // Received an data transfer meant for us.
-        if pdu.is_tp_data_transfer() && self.state() == State::Receiving {
-            let data: [u8; 8] = pdu.data::<8>();
-            let packet_nr = data[0];
+        None
+    }
 
-            for i in 0..7 {
-                let index = ((packet_nr - 1) * 7 + i) as usize;
-                if index >= self.receive_buffer.len() {
-                    break;
-                }
-                self.receive_buffer[index] = data[(i + 1) as usize];
+    /// Start the timeout of the outbound sessions when their last queued data packet is written,
+    /// T3 for a connection and the packet interval for a broadcast.
+    pub fn packets_written(&mut self, queue: &mut TransmitQueue, time: u64) {
+        while let Some(pdu) = queue.take_written(PGN::TP_DT) {
+            let session = self.sessions.iter_mut().find(|s| {
+                s.direction == Direction::Outbound
+                    && s.source_address == pdu.source_address()
+                    && s.destination_address == destination(&pdu)
+            });
+            if let Some(session) = session {
+                session.timeout_time = match session.is_broadcast() {
+                    true => time + self.config.bam_packet_interval,
+                    false => time + self.config.tp_t3,
+                };
             }
+        }
+    }
 
-            self.timeout_time = time + TP_TIMEOUT_T1;
-
-            if self.receive_nr_of_packets == packet_nr {
-                let mut finished_pdu = None;
-
-                if let Some(pgn) = self.receive_pgn {
-                    can.write(
-                        PDU::new_tp_end_of_message_acknowledge(
-                            self.receive_buffer.len() as u16,
-                            self.receive_nr_of_packets,
-                            pgn,
-                            pdu.source_address(),
-                            claimed_address,
-                        )
-                        .into(),
-                    );
-
-                    if pgn.is_vt_to_ecu() {
-                        finished_pdu = Some(PDU::new_vt_to_ecu(
-                            pdu.source_address(),
-                            claimed_address,
-                            self.receive_buffer.clone(),
-                        ))
-                    }
-                }
+    /// The session the received PDU belongs to, matched by the addresses and for connection management by the PGN.
+    fn position(&self, pdu: &PDU, direction: Direction) -> Option<usize> {
+        let (source_address, destination_address) = match direction {
+            Direction::Inbound => (pdu.source_address(), destination(pdu)),
+            Direction::Outbound => (pdu.destination_address(), pdu.source_address()),
+        };
+        let pgn = match pdu.is_tp_connection_management() {
+            true => Some(message_pgn(pdu)),
+            false => None,
+        };
 
-                // log::info!("{:#?}", finished_pdu);
+        self.sessions.iter().position(|s| {
+            s.direction == direction
+                && s.source_address == source_address
+                && s.destination_address == destination_address
+                && pgn.is_none_or(|pgn| s.pgn == pgn)
+        })
+    }
 
-                self.close_connection();
+    /// Start sending the messages in the backlog. Messages to a peer with an outbound session wait,
+    /// so the messages to a peer keep their order.
+    fn open_sessions(&mut self, queue: &mut TransmitQueue, time: u64) {
+        let mut i = 0;
+        while i < self.backlog.len() && self.sessions.len() < self.max_sessions {
+            let pdu = &self.backlog[i];
+            let busy = self.sessions.iter().any(|s| {
+                s.direction == Direction::Outbound
+                    && s.source_address == pdu.source_address()
+                    && s.destination_address == destination(pdu)
+            });
+            if busy {
+                i += 1;
+                continue;
+            }
 
-                return finished_pdu;
+            if let Some(pdu) = self.backlog.remove(i) {
+                self.open_outbound_session(queue, pdu, time);
             }
         }
+    }
+
+    fn open_outbound_session(&mut self, queue: &mut TransmitQueue, pdu: PDU, time: u64) {
+        let nr_of_bytes = pdu.data_len();
+        let nr_of_packets = nr_of_bytes.div_ceil(7) as u8;
+        let mut session = Session::new(
+            TransportProtocol::Tp,
+            Direction::Outbound,
+            pdu.source_address(),
+            destination(&pdu),
+            pdu.pgn(),
+            nr_of_bytes,
+            nr_of_packets as u32,
+        );
+
+        if session.is_broadcast() {
+            queue.push(PDU::new_tp_broadcast_announce_message(
+                nr_of_bytes as u16,
+                nr_of_packets,
+                session.pgn,
+                session.source_address,
+            ));
+            session.state = State::Broadcasting;
+            session.timeout_time = time + self.config.bam_packet_interval;
+        } else {
+            queue.push(PDU::new_tp_request_to_send(
+                nr_of_bytes as u16,
+                nr_of_packets,
+                session.pgn,
+                session.destination_address,
+                session.source_address,
+            ));
+            session.state = State::WaitingForClearToSend;
+            session.timeout_time = time + self.config.tp_t3;
+        }
 
-        // Received a connection abort meant for us.
-        if pdu.is_tp_connection_abort() && self.state() == State::Sending {
-            if let Some(pdu) = self.pdu_to_send.take() {
-                self.close_connection();
-                self.send(can, pdu, time);
+        session.pdu = pdu;
+        self.events.push(TransportEvent::Started(session.info()));
+        self.sessions.push(session);
+    }
+
+    fn open_inbound_session(&mut self, queue: &mut TransmitQueue, pdu: &PDU, time: u64) {
+        let data: [u8; 8] = pdu.data::<8>();
+        let nr_of_bytes = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let mut session = Session::new(
+            TransportProtocol::Tp,
+            Direction::Inbound,
+            pdu.source_address(),
+            pdu.destination_address(),
+            message_pgn(pdu),
+            nr_of_bytes,
+            data[3] as u32,
+        );
+        session.max_packets_per_cts = u8::max(data[4], 1);
+
+        let existing = self.sessions.iter().position(|s| {
+            s.direction == Direction::Inbound
+                && s.source_address == session.source_address
+                && s.destination_address == session.destination_address
+        });
+        if let Some(index) = existing {
+            // The data packets of both sessions can not be told apart.
+            if self.sessions[index].pgn != session.pgn {
+                session.abort(queue, &mut self.events, TpAbortReasons::AlreadyConnected);
+                return;
             }
+
+            // The sender started the same message again.
+            self.sessions.swap_remove(index);
         }
 
-        None
+        if self.sessions.len() >= self.max_sessions {
+            session.abort(queue, &mut self.events, TpAbortReasons::NoResources);
+            return;
+        }
+
+        if !(9..=1785).contains(&nr_of_bytes)
+            || session.nr_of_packets as usize != nr_of_bytes.div_ceil(7)
+        {
+            log::error!(
+                "Invalid RTS from {}: {nr_of_bytes} bytes in {} packets",
+                session.source_address,
+                session.nr_of_packets
+            );
+            session.abort(queue, &mut self.events, TpAbortReasons::Other);
+            return;
+        }
+
+        session.buffer = Vec::with_capacity(nr_of_bytes);
+        if self.receiving_sessions() < self.config.max_receiving_sessions {
+            session.clear_to_send(queue, time, &self.config);
+        } else {
+            session.hold(queue, time, &self.config);
+        }
+        self.events.push(TransportEvent::Started(session.info()));
+        self.sessions.push(session);
     }
 
-    fn process_timeout(
-        &mut self,
-        can: &mut Box<dyn CanDriverTrait>,
-        claimed_address: IsobusAddress,
-        time: u64,
-    ) {
-        if let Some(pdu_to_send) = &self.pdu_to_send {
-            if time > self.timeout_time && self.state() != State::Idle {
-                can.write(
-                    PDU::new_tp_connection_abort(
-                        TpAbortReasons::Timeout,
-                        pdu_to_send.pgn(),
-                        pdu_to_send.destination_address(),
-                        claimed_address,
-                    )
-                    .into(),
-                );
-                self.close_connection();
+    /// The number of inbound connections receiving data.
+    fn receiving_sessions(&self) -> usize {
+        self.sessions
+            .iter()
+            .filter(|s| {
+                s.direction == Direction::Inbound
+                    && !s.is_broadcast()
+                    && s.state == State::ReceivingData
+            })
+            .count()
+    }
+
+    /// Request the data of held connections, when other connections are finished.
+    fn resume_held_sessions(&mut self, queue: &mut TransmitQueue, time: u64) {
+        let mut receiving = self.receiving_sessions();
+        for session in self.sessions.iter_mut() {
+            if receiving >= self.config.max_receiving_sessions {
+                return;
+            }
+
+            if session.state == State::Holding {
+                session.clear_to_send(queue, time, &self.config);
+                receiving += 1;
             }
         }
     }
 
-    // TODO: Cleanup connection creation
-    fn open_sending_connection(&mut self, can: &mut Box<dyn CanDriverTrait>, pdu: PDU, time: u64) {
-        let number_of_bytes = pdu.data_len() as u16;
-        let number_of_packets = ((number_of_bytes + 7 - 1) / 7) as u8; // Round up using (x+d-1)/d
+    fn open_broadcast_session(&mut self, pdu: &PDU, time: u64) {
+        let data: [u8; 8] = pdu.data::<8>();
+        let nr_of_bytes = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let nr_of_packets = data[3];
 
-        if pdu.is_pdu2() {
-            can.write(
-                PDU::new_tp_broadcast_announce_message(
-                    number_of_bytes,
-                    number_of_packets,
-                    pdu.pgn(),
-                    pdu.source_address(),
-                )
-                .into(),
+        if !(9..=1785).contains(&nr_of_bytes) || nr_of_packets as usize != nr_of_bytes.div_ceil(7) {
+            log::error!(
+                "Invalid BAM from {}: {nr_of_bytes} bytes in {nr_of_packets} packets",
+                pdu.source_address()
             );
-            self.send_pdu_data(
-                can,
-                1,
-                number_of_packets,
-                IsobusAddress::GLOBAL,
-                pdu.source_address(),
-            );
-        } else {
-            can.write(
-                PDU::new_tp_request_to_send(
-                    number_of_bytes,
-                    number_of_packets,
-                    pdu.pgn(),
-                    pdu.destination_address(),
-                    pdu.source_address(),
-                )
-                .into(),
+            return;
+        }
+
+        // A new broadcast from the same source replaces the unfinished one.
+        self.sessions.retain(|s| {
+            s.direction != Direction::Inbound
+                || !s.is_broadcast()
+                || s.source_address != pdu.source_address()
+        });
+
+        if self.sessions.len() >= self.max_sessions {
+            log::error!(
+                "No session available for the BAM from {}",
+                pdu.source_address()
             );
+            return;
+        }
+
+        let mut session = Session::new(
+            TransportProtocol::Tp,
+            Direction::Inbound,
+            pdu.source_address(),
+            IsobusAddress::GLOBAL,
+            message_pgn(pdu),
+            nr_of_bytes,
+            nr_of_packets as u32,
+        );
+        session.buffer = Vec::with_capacity(nr_of_bytes);
+        session.timeout_time = time + self.config.t1;
+        self.events.push(TransportEvent::Started(session.info()));
+        self.sessions.push(session);
+    }
+
+    fn receive_data(&mut self, queue: &mut TransmitQueue, pdu: &PDU, time: u64) -> Option<PDU> {
+        let index = self.position(pdu, Direction::Inbound)?;
+        let session = &mut self.sessions[index];
+        let data: [u8; 8] = pdu.data::<8>();
+        let sequence_number = data[0] as u32;
+
+        // No data is requested while the connection is held open.
+        if session.state != State::ReceivingData {
+            return None;
         }
 
-        self.timeout_time = time + TP_TIMEOUT_T3;
-        self.pdu_to_send = Some(pdu);
-    }
-
-    fn close_connection(&mut self) {
-        self.pdu_to_send = None;
-        self.timeout_time = u64::MAX;
-        self.receive_buffer.clear();
-        self.receive_pgn = None;
-        self.receive_nr_of_packets = 0;
-    }
-
-    fn send_pdu_data(
-        &self,
-        can: &mut Box<dyn CanDriverTrait>,
-        next_packet: u8,
-        number_of_packets: u8,
-        da: IsobusAddress,
-        sa: IsobusAddress,
-    ) {
-        if let Some(pdu_to_send) = &self.pdu_to_send {
-            let chunks: Vec<&[u8]> = pdu_to_send.data_raw().chunks(7).collect();
-            for i in 0..number_of_packets {
-                can.write(
-                    PDU::new_tp_data_transfer(
-                        next_packet + i,
-                        chunks[(next_packet - 1 + i) as usize],
-                        da,
-                        sa,
-                    )
-                    .into(),
+        if sequence_number != session.next_packet {
+            // The packets sent before the retransmission request are ignored.
+            if session.retransmit_requested {
+                return None;
+            }
+
+            // Request the missing packets again.
+            if !session.is_broadcast()
+                && sequence_number > session.next_packet
+                && session.retransmits < self.config.max_retransmits
+            {
+                log::warn!(
+                    "TP from {} is missing packet {}, requesting it again",
+                    session.source_address,
+                    session.next_packet,
                 );
+                session.retransmits += 1;
+                session.retransmit_requested = true;
+                session.clear_to_send(queue, time, &self.config);
+                return None;
+            }
+
+            log::error!(
+                "TP from {} aborted, expected packet {} but received {sequence_number}",
+                session.source_address,
+                session.next_packet,
+            );
+            let reason = if sequence_number < session.next_packet {
+                TpAbortReasons::DuplicateSequenceNumber
+            } else if !session.is_broadcast() && self.config.max_retransmits > 0 {
+                TpAbortReasons::RetransmitLimitReached
+            } else {
+                TpAbortReasons::BadSequenceNumber
+            };
+            if session.is_broadcast() {
+                session.aborted(&mut self.events, reason);
+            } else {
+                session.abort(queue, &mut self.events, reason);
+            }
+            self.sessions.swap_remove(index);
+            return None;
+        }
+
+        let len = usize::min(7, session.nr_of_bytes - session.buffer.len());
+        session.buffer.extend_from_slice(&data[1..=len]);
+        session.retransmit_requested = false;
+        session.timeout_time = time + self.config.t1;
+        if session.is_broadcast() || sequence_number == session.last_packet {
+            session.progress(&mut self.events, sequence_number);
+        }
+
+        if sequence_number < session.nr_of_packets {
+            session.next_packet += 1;
+            if !session.is_broadcast() && sequence_number == session.last_packet {
+                session.clear_to_send(queue, time, &self.config);
             }
+            return None;
+        }
+
+        let session = self.sessions.swap_remove(index);
+        self.events.push(TransportEvent::Completed(session.info()));
+        if !session.is_broadcast() {
+            queue.push(PDU::new_tp_end_of_message_acknowledge(
+                session.nr_of_bytes as u16,
+                session.nr_of_packets as u8,
+                session.pgn,
+                session.source_address,
+                session.destination_address,
+            ));
         }
+
+        // The priority is not part of the transfer, use the default priority.
+        Some(PDU::with_pgn(
+            6,
+            session.pgn,
+            session.destination_address,
+            session.source_address,
+            session.buffer,
+        ))
     }
 
-    fn state(&self) -> State {
-        if self.pdu_to_send.is_some() {
-            return State::Sending;
+    fn send_data(&mut self, queue: &mut TransmitQueue, pdu: &PDU, time: u64) {
+        let index = match self.position(pdu, Direction::Outbound) {
+            Some(index) => index,
+            None => return,
+        };
+        let session = &mut self.sessions[index];
+        let data: [u8; 8] = pdu.data::<8>();
+        let nr_of_packets = data[1];
+        let next_packet = u8::max(data[2], 1);
+
+        // The receiver holds the connection open.
+        if nr_of_packets == 0 {
+            session.timeout_time = time + self.config.t4;
+            return;
         }
 
-        if self.receive_pgn.is_some() {
-            return State::Receiving;
+        let last_packet = u8::min(
+            next_packet.saturating_add(nr_of_packets - 1),
+            session.nr_of_packets as u8,
+        );
+        let chunks = session
+            .pdu
+            .data_raw()
+            .chunks(7)
+            .skip(next_packet as usize - 1);
+        for (sequence_number, chunk) in (next_packet..=last_packet).zip(chunks) {
+            let pdu = PDU::new_tp_data_transfer(
+                sequence_number,
+                chunk,
+                session.destination_address,
+                session.source_address,
+            );
+            if sequence_number == last_packet {
+                queue.push_reported(pdu);
+            } else {
+                queue.push(pdu);
+            }
         }
+        session.progress(&mut self.events, last_packet as u32);
+        // T3 starts when the last packet is written, see `packets_written`.
+        session.timeout_time = u64::MAX;
+    }
+
+    fn close_aborted_session(&mut self, pdu: &PDU) {
+        let reason = TpAbortReasons::from(pdu.data::<2>()[1]);
+        let index = match self
+            .position(pdu, Direction::Outbound)
+            .or_else(|| self.position(pdu, Direction::Inbound))
+        {
+            Some(index) => index,
+            None => return,
+        };
 
-        State::Idle
+        let session = self.sessions.swap_remove(index);
+        log::error!("TP session with {} aborted: \"{reason:?}\"", session.peer());
+        session.aborted(&mut self.events, reason);
+
+        // Send the message again.
+        if session.direction == Direction::Outbound {
+            self.backlog.push_front(session.pdu);
+        }
     }
 
-    fn is_connected(&self) -> bool {
-        self.state() == State::Sending || self.state() == State::Receiving
+    fn process_timeouts(&mut self, queue: &mut TransmitQueue, time: u64) {
+        let mut i = 0;
+        while i < self.sessions.len() {
+            let session = &mut self.sessions[i];
+
+            if session.state == State::Broadcasting {
+                if time < session.timeout_time {
+                    i += 1;
+                    continue;
+                }
+
+                let sequence_number = session.next_packet;
+                if let Some(chunk) = session
+                    .pdu
+                    .data_raw()
+                    .chunks(7)
+                    .nth(sequence_number as usize - 1)
+                {
+                    queue.push_reported(PDU::new_tp_data_transfer(
+                        sequence_number as u8,
+                        chunk,
+                        IsobusAddress::GLOBAL,
+                        session.source_address,
+                    ));
+                }
+                session.progress(&mut self.events, sequence_number);
+
+                if sequence_number >= session.nr_of_packets {
+                    let session = self.sessions.swap_remove(i);
+                    self.events.push(TransportEvent::Completed(session.info()));
+                } else {
+                    session.next_packet += 1;
+                    // The interval starts when the packet is written, see `packets_written`.
+                    session.timeout_time = u64::MAX;
+                    i += 1;
+                }
+                continue;
+            }
+
+            if session.state == State::Holding {
+                if time >= session.timeout_time {
+                    session.hold(queue, time, &self.config);
+                }
+                i += 1;
+                continue;
+            }
+
+            if time <= session.timeout_time {
+                i += 1;
+                continue;
+            }
+
+            let session = self.sessions.swap_remove(i);
+            if session.is_broadcast() {
+                // A broadcast can not be aborted, the received data is dropped.
+                log::error!("BAM from {} timed out", session.source_address);
+                session.aborted(&mut self.events, TpAbortReasons::Timeout);
+            } else {
+                log::error!("TP session with {} timed out", session.peer());
+                session.abort(queue, &mut self.events, TpAbortReasons::Timeout);
+            }
+        }
     }
 }
 
 impl Default for TransportProtocolManager {
     fn default() -> Self {
         Self {
+            sessions: Vec::new(),
             backlog: VecDeque::new(),
-            pdu_to_send: None,
-            timeout_time: u64::MAX,
-            receive_buffer: Vec::new(),
-            receive_pgn: None,
-            receive_nr_of_packets: 0,
+            max_sessions: Self::DEFAULT_MAX_SESSIONS,
+            config: TransportConfig::default(),
+            events: TransportEvents::default(),
+        }
+    }
+}
+
+/// The destination of a message, PDU2 messages are sent to all.
+fn destination(pdu: &PDU) -> IsobusAddress {
+    if pdu.is_address_global() {
+        IsobusAddress::GLOBAL
+    } else {
+        pdu.destination_address()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::{
+        iso_11783_3::{
+            TpAbortReasons, TransmitQueue, TransportAbortReason, TransportConfig, TransportEvent,
+            TransportProtocol, TransportSession, PDU, PGN,
+        },
+        isobus::IsobusAddress,
+    };
+
+    use super::TransportProtocolManager;
+
+    const DM1: PGN = PGN::new(0xFECA);
+
+    fn broadcast(sa: u8, data: &[u8]) -> Vec<PDU> {
+        let sa = IsobusAddress(sa);
+        let nr_of_packets = data.len().div_ceil(7) as u8;
+        let mut pdus = Vec::new();
+        pdus.push(PDU::new_tp_broadcast_announce_message(
+            data.len() as u16,
+            nr_of_packets,
+            DM1,
+            sa,
+        ));
+        for (i, chunk) in data.chunks(7).enumerate() {
+            pdus.push(PDU::new_tp_data_transfer(
+                i as u8 + 1,
+                chunk,
+                IsobusAddress::GLOBAL,
+                sa,
+            ));
+        }
+        pdus
+    }
+
+    fn sent(queue: &mut TransmitQueue) -> Vec<PDU> {
+        core::iter::from_fn(|| queue.pop())
+            .map(|f| PDU::from(&f))
+            .collect()
+    }
+
+    struct Node {
+        address: IsobusAddress,
+        tp: TransportProtocolManager,
+        queue: TransmitQueue,
+        received: Vec<PDU>,
+    }
+
+    impl Node {
+        fn new(address: u8) -> Self {
+            Self {
+                address: IsobusAddress(address),
+                tp: TransportProtocolManager::new(),
+                queue: TransmitQueue::new(),
+                received: Vec::new(),
+            }
         }
     }
+
+    /// Exchange the frames of the nodes until all sessions are finished.
+    fn run(nodes: &mut [Node]) {
+        for time in 0..100 {
+            let mut frames = Vec::new();
+            for node in nodes.iter_mut() {
+                node.tp.process(&mut node.queue, node.address, None, time);
+                frames.extend(sent(&mut node.queue));
+            }
+
+            for pdu in frames {
+                for node in nodes.iter_mut() {
+                    if pdu.source_address() != node.address
+                        && (pdu.is_address_global() || pdu.is_address_specific(node.address))
+                    {
+                        let received =
+                            node.tp
+                                .process(&mut node.queue, node.address, Some(pdu.clone()), time);
+                        node.received.extend(received);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn receives_concurrent_broadcasts() {
+        let mut tp = TransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+
+        let data_a: Vec<u8> = (0..20).collect();
+        let data_b: Vec<u8> = (100..110).collect();
+        let mut a = broadcast(0x10, &data_a).into_iter();
+        let mut b = broadcast(0x20, &data_b).into_iter();
+
+        let mut received = Vec::new();
+        for time in 0..4 {
+            for pdu in [a.next(), b.next()].into_iter().flatten() {
+                received.extend(tp.process(&mut queue, address, Some(pdu), time * 50));
+            }
+        }
+
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].source_address(), IsobusAddress(0x20));
+        assert_eq!(received[0].pgn(), DM1);
+        assert_eq!(received[0].data_raw(), data_b.as_slice());
+        assert_eq!(received[1].source_address(), IsobusAddress(0x10));
+        assert_eq!(received[1].data_raw(), data_a.as_slice());
+        // Broadcasts are not acknowledged.
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn broadcast_times_out() {
+        let mut tp = TransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+
+        let data: Vec<u8> = (0..10).collect();
+        let mut pdus = broadcast(0x10, &data).into_iter();
+        tp.process(&mut queue, address, pdus.next(), 0);
+        tp.process(&mut queue, address, pdus.next(), 100);
+
+        // The last packet arrives after T1.
+        assert!(tp.process(&mut queue, address, None, 900).is_none());
+        assert!(tp.process(&mut queue, address, pdus.next(), 900).is_none());
+    }
+
+    #[test]
+    fn concurrent_sessions_with_multiple_peers() {
+        let mut nodes = [Node::new(128), Node::new(0x10), Node::new(0x20)];
+        let vt_to_ecu: Vec<u8> = (0..20).collect();
+        let ecu_to_vt: Vec<u8> = (0..30).collect();
+        let file_server: Vec<u8> = (0..40).collect();
+
+        // Two messages to the same peer are sent one after the other.
+        let messages = [
+            (
+                1,
+                PDU::new_vt_to_ecu(IsobusAddress(128), IsobusAddress(0x10), vt_to_ecu.clone()),
+            ),
+            (
+                1,
+                PDU::new_ecu_to_vt(IsobusAddress(128), IsobusAddress(0x10), ecu_to_vt.clone()),
+            ),
+            (
+                2,
+                PDU::with_pgn(
+                    6,
+                    PGN::new(0xAB00),
+                    IsobusAddress(128),
+                    IsobusAddress(0x20),
+                    file_server.clone(),
+                ),
+            ),
+        ];
+        for (i, pdu) in messages {
+            let node = &mut nodes[i];
+            node.tp.subscribe_events();
+            node.tp.send(&mut node.queue, pdu, 0);
+        }
+
+        run(&mut nodes);
+
+        let received = &nodes[0].received;
+        assert_eq!(received.len(), 3);
+        assert!(received
+            .iter()
+            .any(|p| p.data_raw() == vt_to_ecu.as_slice()));
+        assert!(received
+            .iter()
+            .any(|p| p.data_raw() == ecu_to_vt.as_slice()));
+        let message = received
+            .iter()
+            .find(|p| p.source_address() == IsobusAddress(0x20))
+            .unwrap();
+        assert_eq!(message.pgn(), PGN::new(0xAB00));
+        assert_eq!(message.destination_address(), IsobusAddress(128));
+        assert_eq!(message.data_raw(), file_server.as_slice());
+
+        // The senders report their messages as completed, they are not returned.
+        for (node, completed) in nodes[1..].iter_mut().zip([2, 1]) {
+            assert!(node.received.is_empty());
+            let events = core::iter::from_fn(|| node.tp.next_event());
+            assert_eq!(
+                events
+                    .filter(|e| matches!(e, TransportEvent::Completed(_)))
+                    .count(),
+                completed
+            );
+        }
+    }
+
+    #[test]
+    fn requests_to_send_are_aborted_without_a_session() {
+        let mut tp = TransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+        tp.set_max_sessions(2);
+
+        let rts = |pgn: u32, sa: u8| {
+            Some(PDU::new_tp_request_to_send(
+                20,
+                3,
+                PGN::new(pgn),
+                address,
+                IsobusAddress(sa),
+            ))
+        };
+        let abort_reason = |pdu: &PDU| {
+            assert!(pdu.is_tp_connection_abort());
+            TpAbortReasons::from(pdu.data::<2>()[1])
+        };
+
+        tp.process(&mut queue, address, rts(0xE600, 0x10), 0);
+        assert!(sent(&mut queue)[0].is_tp_clear_to_send());
+
+        // Another PGN from the same peer.
+        tp.process(&mut queue, address, rts(0xE700, 0x10), 0);
+        assert_eq!(
+            abort_reason(&sent(&mut queue)[0]),
+            TpAbortReasons::AlreadyConnected
+        );
+
+        tp.process(&mut queue, address, rts(0xE700, 0x20), 0);
+        assert!(sent(&mut queue)[0].is_tp_clear_to_send());
+
+        // The session limit is reached.
+        tp.process(&mut queue, address, rts(0xE700, 0x30), 0);
+        assert_eq!(
+            abort_reason(&sent(&mut queue)[0]),
+            TpAbortReasons::NoResources
+        );
+
+        // The sessions time out after T2.
+        tp.process(&mut queue, address, None, 1300);
+        let aborts = sent(&mut queue);
+        assert_eq!(aborts.len(), 2);
+        assert!(aborts
+            .iter()
+            .all(|p| abort_reason(p) == TpAbortReasons::Timeout));
+    }
+
+    fn data_transfer(sequence_number: u8, sa: u8) -> Option<PDU> {
+        Some(PDU::new_tp_data_transfer(
+            sequence_number,
+            &[sequence_number; 7],
+            IsobusAddress(128),
+            IsobusAddress(sa),
+        ))
+    }
+
+    #[test]
+    fn missing_packets_are_requested_again() {
+        let mut tp = TransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+        tp.set_config(TransportConfig {
+            max_retransmits: 1,
+            ..Default::default()
+        });
+
+        let rts =
+            PDU::new_tp_request_to_send(20, 3, PGN::new(0xE600), address, IsobusAddress(0x10));
+        tp.process(&mut queue, address, Some(rts), 0);
+        sent(&mut queue);
+
+        // Packet 2 is lost, it is requested again and packet 3 is ignored.
+        tp.process(&mut queue, address, data_transfer(1, 0x10), 10);
+        tp.process(&mut queue, address, data_transfer(3, 0x10), 10);
+        let cts = sent(&mut queue);
+        assert_eq!(cts.len(), 1);
+        assert!(cts[0].is_tp_clear_to_send());
+        assert_eq!(cts[0].data::<3>(), [17, 2, 2]);
+
+        tp.process(&mut queue, address, data_transfer(2, 0x10), 20);
+        let pdu = tp.process(&mut queue, address, data_transfer(3, 0x10), 20);
+        assert_eq!(pdu.unwrap().data_raw()[7..14], [2; 7]);
+        assert!(sent(&mut queue)[0].is_tp_end_of_message_acknowledge());
+
+        // The retransmit limit is reached.
+        let rts =
+            PDU::new_tp_request_to_send(20, 3, PGN::new(0xE600), address, IsobusAddress(0x10));
+        tp.process(&mut queue, address, Some(rts), 100);
+        tp.process(&mut queue, address, data_transfer(2, 0x10), 110);
+        tp.process(&mut queue, address, data_transfer(1, 0x10), 120);
+        tp.process(&mut queue, address, data_transfer(3, 0x10), 120);
+        let abort = sent(&mut queue).pop().unwrap();
+        assert!(abort.is_tp_connection_abort());
+        assert_eq!(
+            TpAbortReasons::from(abort.data::<2>()[1]),
+            TpAbortReasons::RetransmitLimitReached
+        );
+    }
+
+    #[test]
+    fn connections_are_held_open_while_receiving() {
+        let mut tp = TransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        let address = IsobusAddress(128);
+        tp.set_config(TransportConfig {
+            max_receiving_sessions: 1,
+            ..Default::default()
+        });
+
+        let rts = |sa: u8| {
+            Some(PDU::new_tp_request_to_send(
+                14,
+                2,
+                PGN::new(0xE600),
+                address,
+                IsobusAddress(sa),
+            ))
+        };
+        tp.process(&mut queue, address, rts(0x10), 0);
+        tp.process(&mut queue, address, rts(0x20), 0);
+        let cts = sent(&mut queue);
+        assert_eq!(cts[0].data::<2>(), [17, 2]);
+        assert_eq!(cts[1].data::<2>(), [17, 0]);
+        assert_eq!(cts[1].destination_address(), IsobusAddress(0x20));
+
+        // The hold is repeated every Th, before T4 of the sender expires.
+        tp.process(&mut queue, address, data_transfer(1, 0x10), 300);
+        tp.process(&mut queue, address, None, 500);
+        assert_eq!(sent(&mut queue)[0].data::<2>(), [17, 0]);
+
+        // The held connection continues when the first is finished.
+        assert!(tp
+            .process(&mut queue, address, data_transfer(2, 0x10), 700)
+            .is_some());
+        tp.process(&mut queue, address, None, 710);
+        let pdus = sent(&mut queue);
+        assert!(pdus[0].is_tp_end_of_message_acknowledge());
+        assert_eq!(pdus[1].data::<2>(), [17, 2]);
+        assert_eq!(pdus[1].destination_address(), IsobusAddress(0x20));
+    }
+
+    #[test]
+    fn reports_transfer_events() {
+        let mut nodes = [Node::new(128), Node::new(0x10)];
+        for node in nodes.iter_mut() {
+            node.tp.subscribe_events();
+        }
+        let data: Vec<u8> = (0..20).collect();
+        let pdu = PDU::new_ecu_to_vt(IsobusAddress(0x10), IsobusAddress(128), data);
+        nodes[0].tp.send(&mut nodes[0].queue, pdu, 0);
+
+        run(&mut nodes);
+
+        let session = TransportSession {
+            protocol: TransportProtocol::Tp,
+            pgn: PGN::new(0xE700),
+            source_address: IsobusAddress(128),
+            destination_address: IsobusAddress(0x10),
+            nr_of_bytes: 20,
+        };
+        let expected = [
+            TransportEvent::Started(session),
+            TransportEvent::Progress {
+                session,
+                bytes: 20,
+                packets: 3,
+            },
+            TransportEvent::Completed(session),
+        ];
+        for node in nodes.iter_mut() {
+            let events: Vec<_> = core::iter::from_fn(|| node.tp.next_event()).collect();
+            assert_eq!(events, expected);
+        }
+
+        // The sender stops responding.
+        let mut tp = TransportProtocolManager::new();
+        let mut queue = TransmitQueue::new();
+        tp.subscribe_events();
+        let rts = PDU::new_tp_request_to_send(
+            20,
+            3,
+            PGN::new(0xE600),
+            session.source_address,
+            IsobusAddress(0x10),
+        );
+        tp.process(&mut queue, session.source_address, Some(rts), 0);
+        tp.process(&mut queue, session.source_address, None, 2000);
+        assert_eq!(
+            tp.next_event().map(|e| e.session().source_address),
+            Some(IsobusAddress(0x10))
+        );
+        assert!(matches!(
+            tp.next_event(),
+            Some(TransportEvent::Aborted {
+                reason: TransportAbortReason::Tp(TpAbortReasons::Timeout),
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,209 @@
+use alloc::vec::Vec;
+
+use crate::isobus::IsobusAddress;
+
+use super::{
+    transmit_queue::TransmitQueue,
+    transport_event::{TransportEvents, TransportSession},
+    TransportAbortReason, TransportConfig, TransportEvent, TransportProtocol, PDU, PGN,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(super) enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum State {
+    /// Sending the packets of a TP broadcast, one every `bam_packet_interval`.
+    Broadcasting,
+    /// Waiting for a CTS or the EoMA of the receiver.
+    WaitingForClearToSend,
+    /// An ETP CTS is sent, waiting for the DPO of the sender.
+    WaitingForDataPacketOffset,
+    /// Receiving the packets requested with a CTS, announced with a BAM or with a DPO.
+    ReceivingData,
+    /// The connection is held open with a CTS without packets, until data can be received.
+    Holding,
+}
+
+/// A TP or ETP session, a connection (RTS/CTS) or a TP broadcast (BAM).
+/// The data packets carry no PGN, so there is at most one session per direction between two addresses.
+pub(super) struct Session {
+    pub protocol: TransportProtocol,
+    pub direction: Direction,
+    pub source_address: IsobusAddress,
+    pub destination_address: IsobusAddress,
+    pub pgn: PGN,
+    pub state: State,
+    /// The message being sent.
+    pub pdu: PDU,
+    /// The message being received.
+    pub buffer: Vec<u8>,
+    pub nr_of_bytes: usize,
+    pub nr_of_packets: u32,
+    /// The packet number of the next packet to receive, starting at 1.
+    pub next_packet: u32,
+    /// The last packet of the current CTS window.
+    pub last_packet: u32,
+    /// The number of packets requested with the last CTS.
+    pub cts_packets: u8,
+    /// The number of packets requested with a CTS, at most.
+    pub max_packets_per_cts: u8,
+    /// The offset of the packets announced with the last ETP DPO.
+    pub offset: u32,
+    /// The number of packets announced with the last ETP DPO.
+    pub dpo_packets: u8,
+    /// The number of times missing packets were requested again.
+    pub retransmits: u8,
+    /// Missing packets are requested again, the packets still underway are ignored.
+    pub retransmit_requested: bool,
+    pub timeout_time: u64,
+}
+
+impl Session {
+    pub fn new(
+        protocol: TransportProtocol,
+        direction: Direction,
+        source_address: IsobusAddress,
+        destination_address: IsobusAddress,
+        pgn: PGN,
+        nr_of_bytes: usize,
+        nr_of_packets: u32,
+    ) -> Self {
+        Self {
+            protocol,
+            direction,
+            source_address,
+            destination_address,
+            pgn,
+            state: State::ReceivingData,
+            pdu: PDU::default(),
+            buffer: Vec::new(),
+            nr_of_bytes,
+            nr_of_packets,
+            next_packet: 1,
+            last_packet: nr_of_packets,
+            cts_packets: 0,
+            max_packets_per_cts: 0xFF,
+            offset: 0,
+            dpo_packets: 0,
+            retransmits: 0,
+            retransmit_requested: false,
+            timeout_time: u64::MAX,
+        }
+    }
+
+    pub fn peer(&self) -> IsobusAddress {
+        match self.direction {
+            Direction::Inbound => self.source_address,
+            Direction::Outbound => self.destination_address,
+        }
+    }
+
+    pub fn local(&self) -> IsobusAddress {
+        match self.direction {
+            Direction::Inbound => self.destination_address,
+            Direction::Outbound => self.source_address,
+        }
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.destination_address == IsobusAddress::GLOBAL
+    }
+
+    /// Request the next window of packets from the sender.
+    pub fn clear_to_send(
+        &mut self,
+        queue: &mut TransmitQueue,
+        time: u64,
+        config: &TransportConfig,
+    ) {
+        let remaining = self.nr_of_packets - self.next_packet + 1;
+        self.cts_packets = u32::min(remaining, self.max_packets_per_cts as u32) as u8;
+        self.last_packet = self.next_packet + self.cts_packets as u32 - 1;
+
+        queue.push(self.clear_to_send_pdu(self.cts_packets));
+        self.state = match self.protocol {
+            TransportProtocol::Tp => State::ReceivingData,
+            TransportProtocol::Etp => State::WaitingForDataPacketOffset,
+        };
+        self.timeout_time = time + config.t2;
+    }
+
+    /// Hold the connection open, this is repeated every `th`.
+    pub fn hold(&mut self, queue: &mut TransmitQueue, time: u64, config: &TransportConfig) {
+        queue.push(self.clear_to_send_pdu(0));
+        self.state = State::Holding;
+        self.timeout_time = time + config.th;
+    }
+
+    fn clear_to_send_pdu(&self, nr_of_packets: u8) -> PDU {
+        match self.protocol {
+            TransportProtocol::Tp => PDU::new_tp_clear_to_send(
+                nr_of_packets,
+                self.next_packet as u8,
+                self.pgn,
+                self.source_address,
+                self.destination_address,
+            ),
+            TransportProtocol::Etp => PDU::new_etp_clear_to_send(
+                nr_of_packets,
+                self.next_packet,
+                self.pgn,
+                self.source_address,
+                self.destination_address,
+            ),
+        }
+    }
+
+    pub fn abort(
+        &self,
+        queue: &mut TransmitQueue,
+        events: &mut TransportEvents,
+        reason: impl Into<TransportAbortReason>,
+    ) {
+        let reason = reason.into();
+        queue.push(match reason {
+            TransportAbortReason::Tp(reason) => {
+                PDU::new_tp_connection_abort(reason, self.pgn, self.peer(), self.local())
+            }
+            TransportAbortReason::Etp(reason) => {
+                PDU::new_etp_connection_abort(reason, self.pgn, self.peer(), self.local())
+            }
+        });
+        self.aborted(events, reason);
+    }
+
+    pub fn aborted(&self, events: &mut TransportEvents, reason: impl Into<TransportAbortReason>) {
+        events.push(TransportEvent::Aborted {
+            session: self.info(),
+            reason: reason.into(),
+        });
+    }
+
+    pub fn progress(&self, events: &mut TransportEvents, packets: u32) {
+        events.push(TransportEvent::Progress {
+            session: self.info(),
+            bytes: usize::min(packets as usize * 7, self.nr_of_bytes),
+            packets,
+        });
+    }
+
+    pub fn info(&self) -> TransportSession {
+        TransportSession {
+            protocol: self.protocol,
+            pgn: self.pgn,
+            source_address: self.source_address,
+            destination_address: self.destination_address,
+            nr_of_bytes: self.nr_of_bytes,
+        }
+    }
+}
+
+/// The PGN of the message transferred, from a connection management message.
+pub(super) fn message_pgn(pdu: &PDU) -> PGN {
+    let data: [u8; 8] = pdu.data::<8>();
+    PGN::from_le_bytes([data[5], data[6], data[7]])
+}
@@ -0,0 +1,41 @@
+use crate::isobus::IsobusAddress;
+
+use super::Name;
+
+/// A control function on the network, identified by its NAME.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ControlFunction {
+    pub name: Name,
+    pub address: IsobusAddress,
+}
+
+/// A control function we work with, found by a NAME filter and followed through address changes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Partner(pub(crate) usize);
+
+/// A change of the other control functions on the network.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NetworkEvent {
+    /// A control function claimed an address, for the first time or after it left.
+    Joined(ControlFunction),
+    /// A control function claimed another address.
+    AddressChanged {
+        control_function: ControlFunction,
+        previous_address: IsobusAddress,
+    },
+    /// A control function sent cannot-claim, lost its address to another control function,
+    /// or went silent and did not answer the request for its address claim.
+    Left(ControlFunction),
+}
+
+impl NetworkEvent {
+    pub fn control_function(&self) -> &ControlFunction {
+        match self {
+            NetworkEvent::Joined(control_function)
+            | NetworkEvent::AddressChanged {
+                control_function, ..
+            }
+            | NetworkEvent::Left(control_function) => control_function,
+        }
+    }
+}
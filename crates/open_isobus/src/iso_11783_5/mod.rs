@@ -1,12 +1,16 @@
+pub mod control_function;
 pub mod name;
 pub mod network_manager;
 
-pub use name::Name;
-pub use network_manager::NetworkManager;
+pub use control_function::{ControlFunction, NetworkEvent, Partner};
+pub use name::{Name, NameFilter};
+pub use network_manager::{Entropy, NetworkError, NetworkManager};
 
 use crate::{
     iso_11783_3::{PDU, PGN},
     isobus::IsobusAddress,
+    isobus_message,
+    message::IsobusMessage,
 };
 
 impl PDU {
@@ -29,7 +33,7 @@ impl PDU {
             238,
             IsobusAddress::GLOBAL.into(),
             sa.into(),
-            name.as_vec(),
+            <[u8; 8]>::from(name),
         )
     }
     pub fn is_address_claimed(&self) -> bool {
@@ -44,26 +48,33 @@ impl PDU {
             238,
             IsobusAddress::GLOBAL.into(),
             IsobusAddress::NULL.into(),
-            name.as_vec(),
+            <[u8; 8]>::from(name),
         )
     }
     pub fn is_cannot_claim_source_address(&self) -> bool {
         self.pgn().is_address_claimed() && self.source_address() == IsobusAddress::NULL
     }
 
-    pub fn new_commanded_address(sa: IsobusAddress) -> PDU {
-        // 00FED8
-        PDU::new(
-            6,
-            0,
-            0,
-            254,
-            216,
-            sa.into(),
-            alloc::vec![0xE0u8, 0x15u8, 0x41u8, 0x0Cu8, 0x00u8, 0x80u8, 0x32u8, 0xA0u8, 0x88u8],
-        )
+    /// Sent with BAM, the message is 9 bytes.
+    pub fn new_commanded_address(name: Name, address: IsobusAddress, sa: IsobusAddress) -> PDU {
+        CommandedAddress { name, address }.to_pdu(IsobusAddress::GLOBAL, sa)
     }
     pub fn is_commanded_address(&self) -> bool {
         self.pgn().is_commanded_address()
     }
+    pub fn commanded_address(&self) -> Option<CommandedAddress> {
+        CommandedAddress::from_pdu(self).ok()
+    }
+}
+
+isobus_message! {
+    /// Command the control function with the NAME to claim another address.
+    pub struct CommandedAddress {
+        pgn: PGN::COMMANDED_ADDRESS,
+        priority: 6,
+        length: 9,
+        pub name: Name = [0, 64],
+        /// The address to claim.
+        pub address: IsobusAddress = [64, 8],
+    }
 }
@@ -2,6 +2,8 @@ use core::fmt::Debug;
 
 use alloc::vec::Vec;
 
+use crate::message::BitField;
+
 #[derive(Clone, Copy, Default, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Name {
     value: u64,
@@ -95,6 +97,43 @@ impl From<Name> for [u8; 8] {
     }
 }
 
+/// A field of the NAME to find control functions by, e.g. `Function(130)` for a Task Controller.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NameFilter {
+    IndustryGroup(u8),
+    DeviceClass(u8),
+    DeviceClassInstance(u8),
+    Function(u8),
+    FunctionInstance(u8),
+    EcuInstance(u8),
+    ManufacturerCode(u16),
+    IdentityNumber(u32),
+}
+
+impl NameFilter {
+    pub fn matches(&self, name: &Name) -> bool {
+        match *self {
+            NameFilter::IndustryGroup(value) => name.industry_group() == value,
+            NameFilter::DeviceClass(value) => name.device_class() == value,
+            NameFilter::DeviceClassInstance(value) => name.device_class_instance() == value,
+            NameFilter::Function(value) => name.function() == value,
+            NameFilter::FunctionInstance(value) => name.function_instance() == value,
+            NameFilter::EcuInstance(value) => name.ecu_instance() == value,
+            NameFilter::ManufacturerCode(value) => name.manufacturer_code() == value,
+            NameFilter::IdentityNumber(value) => name.identity_number() == value,
+        }
+    }
+}
+
+impl BitField for Name {
+    fn to_bits(self) -> u64 {
+        self.value
+    }
+    fn from_bits(bits: u64) -> Self {
+        Name { value: bits }
+    }
+}
+
 #[derive(Default)]
 pub struct NameBuilder {
     has_self_configurable_address: bool,
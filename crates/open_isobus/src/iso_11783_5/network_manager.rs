@@ -1,8 +1,14 @@
-use alloc::{collections::BTreeMap, vec::Vec};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
 
 use crate::{
     iso_11783_3::{DataLinkLayer, PDU},
-    iso_11783_5::name::Name,
+    iso_11783_5::{
+        name::Name, CommandedAddress, ControlFunction, NameFilter, NetworkEvent, Partner,
+    },
     isobus::IsobusAddress,
     Isobus,
 };
@@ -16,23 +22,69 @@ enum State {
     UnableToClaimAddress,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum NetworkError {
     Uninitialised,
     UnableToClaimAddress,
+    /// No control function on the network matches the NAME filter of the partner.
+    PartnerNotFound,
     Other,
 }
 
+/// The source of the pseudo-random address claim delay, each call returns a value of 0-255.
+pub type Entropy = Box<dyn FnMut() -> u8>;
+
+/// A pseudo-random sequence seeded with the NAME, the same at every start-up of a device
+/// and different between devices with different NAMEs.
+pub fn name_entropy(name: Name) -> Entropy {
+    let mut state = u64::from(name);
+    Box::new(move || {
+        // SplitMix64
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)) as u8
+    })
+}
+
+struct Node {
+    address: IsobusAddress,
+    /// The time of the last PDU from the node, or of the request for its address claim.
+    last_seen: u64,
+    /// The node went silent and is requested for its address claim.
+    probed: bool,
+}
+
 pub struct NetworkManager {
     state: State,
     // claimed_address: IsobusAddress,
     address_to_claim: IsobusAddress,
     name: Name,
-    network_nodes: BTreeMap<Name, IsobusAddress>,
+    network_nodes: BTreeMap<Name, Node>,
+    /// The control functions that lost their address to another claim, with that address and the time.
+    /// They move to another address or send cannot-claim.
+    displaced: BTreeMap<Name, (IsobusAddress, u64)>,
     start_delay_time: u64,
+    entropy: Entropy,
+    /// The pseudo-random delay before claiming the address, 0-153 ms.
+    claim_delay: u64,
+    events: VecDeque<NetworkEvent>,
+    /// The NAME filters of the partners.
+    partners: Vec<Vec<NameFilter>>,
+    /// The silent and response timeout of probing silent control functions, off when `None`.
+    silence_probing: Option<(u64, u64)>,
 }
 
 impl NetworkManager {
+    /// A control function silent for this long is requested for its address claim.
+    /// PDUs filtered by the CAN acceptance filters are not seen.
+    pub const DEFAULT_SILENT_TIMEOUT: u64 = 3000;
+    /// The time a silent control function has to answer the request, before it has left.
+    pub const DEFAULT_RESPONSE_TIMEOUT: u64 = 1250;
+    /// The time a displaced control function has to claim another address, before it has left.
+    pub const DISPLACED_TIMEOUT: u64 = 1250;
+
     pub fn new(name: Name) -> Self {
         Self {
             state: State::NotConnected,
@@ -40,38 +92,69 @@ impl NetworkManager {
             address_to_claim: Isobus::DEFAULT_ADDRESS,
             name,
             network_nodes: BTreeMap::new(),
+            displaced: BTreeMap::new(),
             start_delay_time: 0,
+            entropy: name_entropy(name),
+            claim_delay: 0,
+            events: VecDeque::new(),
+            partners: Vec::new(),
+            silence_probing: None,
         }
     }
 
+    /// Replace the entropy of the address claim delay, by default derived from the NAME.
+    pub fn set_entropy(&mut self, entropy: Entropy) {
+        self.entropy = entropy;
+    }
+
+    /// Request the address claim of control functions silent for `silent_timeout` ms,
+    /// those not answering within `response_timeout` ms have left.
+    pub fn set_silence_probing(&mut self, silent_timeout: u64, response_timeout: u64) {
+        self.silence_probing = Some((silent_timeout, response_timeout));
+    }
+
     pub fn process(&mut self, pdus: &Vec<PDU>, dll: &mut DataLinkLayer, time: u64) {
         for pdu in pdus {
+            self.seen(pdu.source_address(), time);
+
             // Request-for-address-claimed
-            if pdu.is_request_for_address_claimed() && self.is_connected() {
-                self.send_address_claimed(dll, self.claimed_address(), time);
+            if pdu.is_request_for_address_claimed() {
+                if self.is_connected() {
+                    self.send_address_claimed(dll, self.claimed_address(), time);
+                } else if self.state == State::UnableToClaimAddress {
+                    dll.send(PDU::new_cannot_claim_source_address(self.name), time);
+                }
             }
 
             // Address-claimed
             if pdu.is_address_claimed() {
-                // self.update_network_nodes(pdu.source_address(), Name::from(pdu.data().as_slice()));
-                self.update_network_nodes(
-                    Name::from(pdu.data::<8>().as_slice()),
-                    pdu.source_address(),
-                );
+                let name = Name::from(pdu.data::<8>().as_slice());
+                self.address_claimed(dll, name, pdu.source_address(), time);
             }
 
             // Cannot-claim-source-address
-            // if id & 0x00FFFFFF == 0x00EEFFFF {
-
-            // }
+            if pdu.is_cannot_claim_source_address() {
+                let name = Name::from(pdu.data::<8>().as_slice());
+                self.update_network_nodes(name, IsobusAddress::NULL, time);
+            }
 
             // Commanded-address
-            // if id & 0x00FFFF00 == 0x00FED800 {
-
-            // }
+            if let Some(command) = pdu.commanded_address() {
+                self.address_commanded(dll, command, time);
+            }
 
             // log::debug!("{:?}", pdu);
         }
+
+        if self.is_connected() {
+            self.probe_silent_nodes(dll, time);
+        }
+        self.expire_displaced_nodes(time);
+    }
+
+    /// The next change of the other control functions on the network.
+    pub fn next_event(&mut self) -> Option<NetworkEvent> {
+        self.events.pop_front()
     }
 
     pub fn connect(
@@ -84,6 +167,8 @@ impl NetworkManager {
             State::NotConnected => {
                 self.address_to_claim = address_to_claim.unwrap_or(Isobus::DEFAULT_ADDRESS);
                 self.start_delay_time = time;
+                // ISO 11783-5 spreads the claims of devices starting together over 0-153 ms.
+                self.claim_delay = (self.entropy)() as u64 * 6 / 10;
                 self.send_request_for_address_claimed(
                     dll,
                     IsobusAddress::GLOBAL,
@@ -94,33 +179,22 @@ impl NetworkManager {
                 Err(nb::Error::WouldBlock)
             }
             State::RequestedClaimedAddresses => {
-                if time < self.start_delay_time + 250 + self.get_random_delay(10) {
+                if time < self.start_delay_time + 250 + self.claim_delay {
                     return Err(nb::Error::WouldBlock);
                 }
 
-                // TODO, create an IsobusAddress Iterator
-                for i in (self.address_to_claim.0..=247).chain(128..self.address_to_claim.0) {
-                    let address = IsobusAddress(i);
-                    if !self.network_nodes.values().any(|&a| a == address) {
-                        self.address_to_claim = address;
-                        self.send_address_claimed(dll, address, time);
-                        self.start_delay_time = time;
-                        self.state = State::ClaimingAddress;
-                        return Err(nb::Error::WouldBlock);
-                    }
+                match self.claim_free_address(dll, time) {
+                    true => Err(nb::Error::WouldBlock),
+                    false => Err(nb::Error::Other(NetworkError::UnableToClaimAddress)),
                 }
-
-                self.state = State::UnableToClaimAddress;
-                log::error!("Unable to claim ISOBUS network address");
-                Err(nb::Error::Other(NetworkError::UnableToClaimAddress))
             }
             State::ClaimingAddress => {
-                if time < self.start_delay_time + 250 + self.get_random_delay(10) {
+                if time < self.start_delay_time + 250 {
                     return Err(nb::Error::WouldBlock);
                 }
 
                 self.state = State::AddressClaimed;
-                self.update_network_nodes(self.name, self.address_to_claim);
+                self.update_network_nodes(self.name, self.address_to_claim, time);
                 // self.log_network(); // TODO, for debugging
                 Ok(self.address_to_claim)
             }
@@ -131,9 +205,15 @@ impl NetworkManager {
         }
     }
 
+    /// Forget the control functions on the network, they are learned again when reconnected.
     pub fn disconnect(&mut self) {
-        if self.is_connected() {
-            self.update_network_nodes(self.name, IsobusAddress::NULL);
+        let names: Vec<Name> = self.network_nodes.keys().copied().collect();
+        for name in names {
+            self.update_network_nodes(name, IsobusAddress::NULL, 0);
+        }
+        let displaced: Vec<Name> = self.displaced.keys().copied().collect();
+        for name in displaced {
+            self.update_network_nodes(name, IsobusAddress::NULL, 0);
         }
         // self.log_network(); // TODO, for debugging
         self.state = State::NotConnected;
@@ -142,8 +222,12 @@ impl NetworkManager {
     pub fn log_network(&self) {
         log::info!("#====#====#====# Logging the network #====#====#====#");
 
-        for (n, a) in &self.network_nodes {
-            log::info!("Address = 0x{:02X?}    self = {}", a.0, n == &self.name);
+        for (n, node) in &self.network_nodes {
+            log::info!(
+                "Address = 0x{:02X?}    self = {}",
+                node.address.0,
+                n == &self.name
+            );
             log::info!(
                 "HasSelfConfigurableAddress:.{}",
                 n.has_self_configurable_address()
@@ -177,29 +261,270 @@ impl NetworkManager {
         // log::debug!("Sending; address_claimed");
     }
 
-    fn update_network_nodes(&mut self, name: Name, source_address: IsobusAddress) {
+    /// Claim the preferred address, or the next free address when it is taken and our address is self-configurable.
+    /// The preferred address is also claimed when a control function with a higher NAME holds it, we win the contention.
+    /// Sends cannot-claim when no address is available.
+    fn claim_free_address(&mut self, dll: &mut DataLinkLayer, time: u64) -> bool {
+        let preferred = self.address_to_claim;
+        let candidates = match self.name.has_self_configurable_address() {
+            true => usize::MAX,
+            false => 1,
+        };
+
+        // Self-configurable addresses are taken from 128..=247, starting at the preferred address.
+        // The null and global address are no preference, those start at 128.
+        let claimable = preferred.0 <= 247;
+        let first = match claimable {
+            true => preferred.0.clamp(128, 247),
+            false => 128,
+        };
+        let self_configurable = (first..=247)
+            .chain(128..first)
+            .filter(|i| *i != preferred.0);
+        for i in Some(preferred.0)
+            .filter(|_| claimable)
+            .into_iter()
+            .chain(self_configurable)
+            .take(candidates)
+        {
+            let address = IsobusAddress(i);
+            let available = self.network_nodes.iter().all(|(name, node)| {
+                node.address != address
+                    || *name == self.name
+                    || (address == preferred && *name > self.name)
+            });
+            if available {
+                self.address_to_claim = address;
+                self.send_address_claimed(dll, address, time);
+                self.start_delay_time = time;
+                self.state = State::ClaimingAddress;
+                return true;
+            }
+        }
+
+        log::error!("Unable to claim ISOBUS network address");
+        dll.send(PDU::new_cannot_claim_source_address(self.name), time);
+        self.state = State::UnableToClaimAddress;
+        false
+    }
+
+    /// Record the claim, resolving a contention for the address we claim or claimed.
+    /// The lower NAME keeps the address, the other control function moves or cannot claim.
+    fn address_claimed(
+        &mut self,
+        dll: &mut DataLinkLayer,
+        name: Name,
+        address: IsobusAddress,
+        time: u64,
+    ) {
+        let contending = matches!(self.state, State::ClaimingAddress | State::AddressClaimed)
+            && address == self.address_to_claim
+            && name != self.name;
+        if !contending {
+            self.update_network_nodes(name, address, time);
+            return;
+        }
+
+        if self.name < name {
+            // We win, claim the address again for the other control function to move.
+            self.send_address_claimed(dll, address, time);
+            return;
+        }
+
+        log::warn!(
+            "Address 0x{:02X} lost to a control function with a lower NAME",
+            address.0
+        );
+        self.network_nodes.remove(&self.name);
+        self.update_network_nodes(name, address, time);
+        self.claim_free_address(dll, time);
+    }
+
+    /// Claim the commanded address when the command is for our NAME.
+    fn address_commanded(&mut self, dll: &mut DataLinkLayer, command: CommandedAddress, time: u64) {
+        if command.name != self.name
+            || !self.is_connected()
+            || command.address == self.claimed_address()
+        {
+            return;
+        }
+        if command.address.0 >= IsobusAddress::NULL.0 {
+            log::warn!("Ignoring commanded address 0x{:02X}", command.address.0);
+            return;
+        }
+
+        log::info!("Commanded to claim address 0x{:02X}", command.address.0);
+        self.network_nodes.remove(&self.name);
+        self.address_to_claim = command.address;
+        self.claim_free_address(dll, time);
+    }
+
+    fn update_network_nodes(&mut self, name: Name, source_address: IsobusAddress, time: u64) {
         if source_address == IsobusAddress::NULL {
-            self.network_nodes.remove(&name);
+            let address = match self.network_nodes.remove(&name) {
+                Some(node) => Some(node.address),
+                None => self.displaced.remove(&name).map(|(address, _)| address),
+            };
+            if let Some(address) = address {
+                self.push_event(NetworkEvent::Left(ControlFunction { name, address }));
+            }
             return;
         }
-        self.network_nodes.insert(name, source_address);
+
+        // A control function claiming the address of another one has the lower NAME, the other one moves.
+        // It is reported when it claims another address, or has left.
+        let displaced: Vec<Name> = self
+            .network_nodes
+            .iter()
+            .filter(|(n, node)| node.address == source_address && **n != name)
+            .map(|(n, _)| *n)
+            .collect();
+        for n in displaced {
+            self.network_nodes.remove(&n);
+            self.displaced.insert(n, (source_address, time));
+        }
+
+        let node = Node {
+            address: source_address,
+            last_seen: time,
+            probed: false,
+        };
+        let control_function = ControlFunction {
+            name,
+            address: source_address,
+        };
+        let previous = self
+            .network_nodes
+            .insert(name, node)
+            .map(|node| node.address)
+            .or_else(|| self.displaced.remove(&name).map(|(address, _)| address));
+        match previous {
+            None => self.push_event(NetworkEvent::Joined(control_function)),
+            Some(previous_address) if previous_address != source_address => {
+                self.push_event(NetworkEvent::AddressChanged {
+                    control_function,
+                    previous_address,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// The displaced control functions that did not claim another address in time have left.
+    fn expire_displaced_nodes(&mut self, time: u64) {
+        let expired: Vec<Name> = self
+            .displaced
+            .iter()
+            .filter(|(_, (_, displaced))| time >= displaced + Self::DISPLACED_TIMEOUT)
+            .map(|(name, _)| *name)
+            .collect();
+        for name in expired {
+            self.update_network_nodes(name, IsobusAddress::NULL, time);
+        }
+    }
+
+    /// Only the changes of the other control functions are reported.
+    fn push_event(&mut self, event: NetworkEvent) {
+        if event.control_function().name != self.name {
+            self.events.push_back(event);
+        }
+    }
+
+    fn seen(&mut self, address: IsobusAddress, time: u64) {
+        let own = self.name;
+        if let Some((_, node)) = self
+            .network_nodes
+            .iter_mut()
+            .find(|(name, node)| node.address == address && **name != own)
+        {
+            node.last_seen = time;
+            node.probed = false;
+        }
     }
 
-    fn get_random_delay(&self, max: u64) -> u64 {
-        let mut val: u64 = 0;
-        val ^= 0xAAAAAAAA << 13;
-        val ^= val >> 7;
-        val ^= val << 17;
-        val % max
+    /// Request the address claim of the silent control functions, those that do not answer have left.
+    fn probe_silent_nodes(&mut self, dll: &mut DataLinkLayer, time: u64) {
+        let Some((silent_timeout, response_timeout)) = self.silence_probing else {
+            return;
+        };
+        let own = self.name;
+        let claimed_address = self.claimed_address();
+        let mut left = Vec::new();
+        for (name, node) in self.network_nodes.iter_mut() {
+            if *name == own {
+                continue;
+            }
+            if !node.probed && time > node.last_seen + silent_timeout {
+                dll.send(
+                    PDU::new_request_for_address_claimed(node.address, claimed_address),
+                    time,
+                );
+                node.probed = true;
+                node.last_seen = time;
+            } else if node.probed && time > node.last_seen + response_timeout {
+                left.push(*name);
+            }
+        }
+
+        for name in left {
+            log::info!("Control function {:?} went silent", name);
+            self.update_network_nodes(name, IsobusAddress::NULL, time);
+        }
     }
 
     pub fn is_connected(&self) -> bool {
         self.network_nodes.contains_key(&self.name)
     }
 
+    /// The other control functions on the network.
+    pub fn control_functions(&self) -> impl Iterator<Item = ControlFunction> + '_ {
+        self.network_nodes
+            .iter()
+            .filter(|(name, _)| **name != self.name)
+            .map(|(name, node)| ControlFunction {
+                name: *name,
+                address: node.address,
+            })
+    }
+
+    /// The other control functions matching all filters.
+    pub fn find<'a>(
+        &'a self,
+        filters: &'a [NameFilter],
+    ) -> impl Iterator<Item = ControlFunction> + 'a {
+        self.control_functions()
+            .filter(|cf| filters.iter().all(|f| f.matches(&cf.name)))
+    }
+
+    /// Track the control function matching all filters.
+    pub fn add_partner(&mut self, filters: &[NameFilter]) -> Partner {
+        self.partners.push(filters.to_vec());
+        Partner(self.partners.len() - 1)
+    }
+
+    /// The control function of the partner, the one with the lowest NAME when several match.
+    pub fn partner(&self, partner: Partner) -> Option<ControlFunction> {
+        self.find(self.partners.get(partner.0)?).next()
+    }
+
+    /// The other control function that claimed the address.
+    pub fn control_function(&self, address: IsobusAddress) -> Option<ControlFunction> {
+        self.control_functions().find(|cf| cf.address == address)
+    }
+
+    /// The NAME of the other control function that claimed the address.
+    pub fn name_of(&self, address: IsobusAddress) -> Option<Name> {
+        self.control_function(address).map(|cf| cf.name)
+    }
+
+    /// The address claimed by the control function with the NAME.
+    pub fn address_of(&self, name: Name) -> Option<IsobusAddress> {
+        self.network_nodes.get(&name).map(|node| node.address)
+    }
+
     pub fn claimed_address(&self) -> IsobusAddress {
         match self.network_nodes.get(&self.name) {
-            Some(v) => *v,
+            Some(node) => node.address,
             None => IsobusAddress::NULL,
         }
     }
@@ -1,5 +1,7 @@
 use alloc::string::String;
 
+use crate::{iso_11783_3::TransportEvent, IsobusEvent};
+
 use super::objects::ObjectId;
 
 pub enum EventType {
@@ -18,4 +20,9 @@ pub enum EventType {
     NumericValueChanged(ObjectId, u32),
     ActiveMaskChanged(ObjectId, ObjectId),
     StringValueChanged(ObjectId, String),
+    /// The progress of the object pool upload to the VT.
+    ObjectPoolTransfer(TransportEvent),
+    /// An event of the `Isobus`, forwarded after the working set handled it.
+    /// The transport events of the object pool upload are reported as `ObjectPoolTransfer` instead.
+    Isobus(IsobusEvent),
 }
@@ -3,7 +3,7 @@ pub mod writer;
 
 use alloc::{string::String, vec::Vec};
 
-use crate::{iso_11783_5::Name, iso_11783_6::ParseError};
+use crate::{iso_11783_5::Name, iso_11783_6::ParseError, message::BitField};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ObjectType {
@@ -386,6 +386,14 @@ impl From<&[u8]> for ObjectId {
         }
     }
 }
+impl BitField for ObjectId {
+    fn to_bits(self) -> u64 {
+        self.0 as u64
+    }
+    fn from_bits(bits: u64) -> Self {
+        ObjectId(bits as u16)
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct ObjectRef {
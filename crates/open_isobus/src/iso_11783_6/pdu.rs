@@ -8,12 +8,14 @@ use bitflags::bitflags;
 
 use crate::{
     iso_11783_3::{PDU, PGN},
+    isobus_message,
+    message::{BitField, IsobusMessage},
     IsobusAddress,
 };
 
 use super::{objects::ObjectId, MessageType, ObjectPool};
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum KeyActivationCode {
     Released = 0,
     Pressed = 1,
@@ -31,6 +33,14 @@ impl From<u8> for KeyActivationCode {
         }
     }
 }
+impl BitField for KeyActivationCode {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+    fn from_bits(bits: u64) -> Self {
+        (bits as u8).into()
+    }
+}
 
 bitflags! {
     #[derive(Default)]
@@ -70,7 +80,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
 pub enum VTVersion {
     #[default]
     V2 = 255,
@@ -90,6 +100,14 @@ impl From<u8> for VTVersion {
         }
     }
 }
+impl BitField for VTVersion {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+    fn from_bits(bits: u64) -> Self {
+        (bits as u8).into()
+    }
+}
 
 bitflags! {
     #[derive(Default)]
@@ -110,6 +128,23 @@ bitflags! {
     }
 }
 
+/// The unknown bits of the codes are dropped.
+macro_rules! bit_field_flags {
+    ($($ty:ty),*) => {
+        $(
+            impl BitField for $ty {
+                fn to_bits(self) -> u64 {
+                    self.bits() as u64
+                }
+                fn from_bits(bits: u64) -> Self {
+                    Self::from_bits_truncate(bits as u8)
+                }
+            }
+        )*
+    };
+}
+bit_field_flags!(VTBusyCode, WorkingSetMaintenanceCode);
+
 // // TODO, Add support for Transaction numbers. Required when VT version >= 6
 // #[derive(Debug)]
 // pub struct SoftKeyActivation {
@@ -220,7 +255,7 @@ impl PDU {
         sa: IsobusAddress,
         data: SoftKeyActivationMessage,
     ) -> PDU {
-        PDU::new_vt_to_ecu(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Soft Key Activation message` PDU.
     pub fn is_soft_key_activation_message(&self) -> bool {
@@ -235,7 +270,7 @@ impl PDU {
         sa: IsobusAddress,
         data: SoftKeyActivationResponse,
     ) -> PDU {
-        PDU::new_ecu_to_vt(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Soft Key Activation response` PDU.
     pub fn is_soft_key_activation_response(&self) -> bool {
@@ -250,7 +285,7 @@ impl PDU {
         sa: IsobusAddress,
         data: ButtonActivationMessage,
     ) -> PDU {
-        PDU::new_vt_to_ecu(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Soft Key Activation message` PDU.
     pub fn is_button_activation_message(&self) -> bool {
@@ -265,7 +300,7 @@ impl PDU {
         sa: IsobusAddress,
         data: ButtonActivationResponse,
     ) -> PDU {
-        PDU::new_ecu_to_vt(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Soft Key Activation response` PDU.
     pub fn is_button_activation_response(&self) -> bool {
@@ -280,7 +315,7 @@ impl PDU {
         sa: IsobusAddress,
         data: VTChangeNumericValueCommand,
     ) -> PDU {
-        PDU::new_vt_to_ecu(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `VT Change Numeric Value command` PDU.
     pub fn is_vt_change_numeric_value_command(&self) -> bool {
@@ -295,7 +330,7 @@ impl PDU {
         sa: IsobusAddress,
         data: VTChangeNumericValueResponse,
     ) -> PDU {
-        PDU::new_ecu_to_vt(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `VT Change Numeric Value response` PDU.
     pub fn is_vt_change_numeric_value_response(&self) -> bool {
@@ -340,7 +375,7 @@ impl PDU {
         sa: IsobusAddress,
         data: ChangeNumericValueCommand,
     ) -> PDU {
-        PDU::new_ecu_to_vt(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Soft Key Activation message` PDU.
     pub fn is_change_numeric_value_command(&self) -> bool {
@@ -370,7 +405,7 @@ impl PDU {
         sa: IsobusAddress,
         data: ChangeActiveMaskCommand,
     ) -> PDU {
-        PDU::new_ecu_to_vt(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Change Active Mask message` PDU.
     pub fn is_change_active_mask_command(&self) -> bool {
@@ -385,7 +420,7 @@ impl PDU {
         sa: IsobusAddress,
         data: ChangeActiveMaskResponse,
     ) -> PDU {
-        PDU::new_vt_to_ecu(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Change Active Mask response` PDU.
     pub fn is_change_active_mask_response(&self) -> bool {
@@ -415,7 +450,7 @@ impl PDU {
         sa: IsobusAddress,
         data: ChangeStringValueResponse,
     ) -> PDU {
-        PDU::new_vt_to_ecu(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Change String Value response` PDU.
     pub fn is_change_string_value_response(&self) -> bool {
@@ -437,17 +472,7 @@ impl PDU {
     }
 
     pub fn new_get_hardware_message(da: IsobusAddress, sa: IsobusAddress) -> PDU {
-        let data: Vec<u8> = vec![
-            MessageType::GetHardware as u8,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-        ];
-        PDU::new_ecu_to_vt(da, sa, data)
+        GetHardwareMessage::default().to_pdu(da, sa)
     }
     pub fn is_get_hardware_message(&self) -> bool {
         self.is_ecu_to_vt() && self.data::<1>()[0] == MessageType::GetHardware as u8
@@ -457,17 +482,7 @@ impl PDU {
     }
 
     pub fn new_get_number_of_softkeys_message(da: IsobusAddress, sa: IsobusAddress) -> PDU {
-        let data: Vec<u8> = vec![
-            MessageType::GetNumberOfSoftKeys as u8,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-        ];
-        PDU::new_ecu_to_vt(da, sa, data)
+        GetNumberOfSoftKeysMessage::default().to_pdu(da, sa)
     }
     pub fn is_get_number_of_softkeys_message(&self) -> bool {
         self.is_ecu_to_vt() && self.data::<1>()[0] == MessageType::GetNumberOfSoftKeys as u8
@@ -477,17 +492,7 @@ impl PDU {
     }
 
     pub fn new_get_text_font_data_message(da: IsobusAddress, sa: IsobusAddress) -> PDU {
-        let data: Vec<u8> = vec![
-            MessageType::GetTextFontData as u8,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-        ];
-        PDU::new_ecu_to_vt(da, sa, data)
+        GetTextFontDataMessage::default().to_pdu(da, sa)
     }
     pub fn is_get_text_font_data_message(&self) -> bool {
         self.is_ecu_to_vt() && self.data::<1>()[0] == MessageType::GetTextFontData as u8
@@ -501,18 +506,7 @@ impl PDU {
         sa: IsobusAddress,
         required_memory: u32,
     ) -> PDU {
-        let required_memory: [u8; 4] = required_memory.to_le_bytes();
-        let data: Vec<u8> = vec![
-            MessageType::GetMemory as u8,
-            0xFF,
-            required_memory[0],
-            required_memory[1],
-            required_memory[2],
-            required_memory[3],
-            0xFF,
-            0xFF,
-        ];
-        PDU::new_ecu_to_vt(da, sa, data)
+        GetMemoryMessage { required_memory }.to_pdu(da, sa)
     }
     pub fn is_get_memory_message(&self) -> bool {
         self.is_ecu_to_vt() && self.data::<1>()[0] == MessageType::GetMemory as u8
@@ -522,17 +516,7 @@ impl PDU {
     }
 
     pub fn new_get_versions_message(da: IsobusAddress, sa: IsobusAddress) -> PDU {
-        let data: Vec<u8> = vec![
-            MessageType::GetVersionsMessage as u8,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-            0xFF,
-        ];
-        PDU::new_ecu_to_vt(da, sa, data)
+        GetVersionsMessage::default().to_pdu(da, sa)
     }
     pub fn is_get_versions_message(&self) -> bool {
         self.is_ecu_to_vt() && self.data::<1>()[0] == MessageType::GetVersionsMessage as u8
@@ -562,7 +546,7 @@ impl PDU {
     ///
     /// VT Function = 18
     pub fn new_end_of_object_pool_message(da: IsobusAddress, sa: IsobusAddress) -> PDU {
-        PDU::new_ecu_to_vt(da, sa, EndOfObjectPoolMessage::default().into())
+        EndOfObjectPoolMessage::default().to_pdu(da, sa)
     }
     /// Check if `&self` is a `End of Object Pool message` PDU.
     pub fn is_end_of_object_pool_message(&self) -> bool {
@@ -588,7 +572,7 @@ impl PDU {
         sa: IsobusAddress,
         data: VTStatusMessage,
     ) -> PDU {
-        PDU::new_vt_to_ecu(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `VT Status message` PDU.
     pub fn is_vt_status_message(&self) -> bool {
@@ -603,7 +587,7 @@ impl PDU {
         sa: IsobusAddress,
         data: WorkingSetMaintenanceMessage,
     ) -> PDU {
-        PDU::new_ecu_to_vt(da, sa, data.into())
+        data.to_pdu(da, sa)
     }
     /// Check if `&self` is a `Working Set Maintenance message` PDU.
     pub fn is_working_set_maintenance_message(&self) -> bool {
@@ -611,274 +595,148 @@ impl PDU {
     }
 }
 
-/// Datastructure for [`MessageType::SoftKeyActivation`] messages.
-#[derive(Debug, Default)]
-pub struct SoftKeyActivationMessage {
-    pub key_activation_code: KeyActivationCode,
-    pub id: ObjectId,
-    pub parent_id: ObjectId,
-    pub key_number: u8,
+isobus_message! {
+    /// Datastructure for [`MessageType::SoftKeyActivation`] messages.
+    #[derive(Default)]
+    pub struct SoftKeyActivationMessage {
+        pgn: PGN::VT_TO_ECU,
+        function_code: MessageType::SoftKeyActivation as u8,
+        priority: 5,
+        length: 8,
+        pub key_activation_code: KeyActivationCode = [8, 8],
+        pub id: ObjectId = [16, 16],
+        pub parent_id: ObjectId = [32, 16],
+        pub key_number: u8 = [48, 8],
+    }
 }
 impl From<SoftKeyActivationResponse> for SoftKeyActivationMessage {
     fn from(src: SoftKeyActivationResponse) -> Self {
-        let mut dst = SoftKeyActivationMessage::default();
-        dst.key_activation_code = src.key_activation_code;
-        dst.id = src.id;
-        dst.parent_id = src.parent_id;
-        dst.key_number = src.key_number;
-        dst
-    }
-}
-impl From<SoftKeyActivationMessage> for Vec<u8> {
-    fn from(src: SoftKeyActivationMessage) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::SoftKeyActivation as u8;
-        dst[1] = src.key_activation_code as u8;
-        dst[2..=3].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[4..=5].copy_from_slice(&Vec::<u8>::from(src.parent_id));
-        dst[6] = src.key_number;
-        dst
-    }
-}
-impl From<&[u8]> for SoftKeyActivationMessage {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = SoftKeyActivationMessage::default();
-        if let Some(&val) = src.get(1) {
-            dst.key_activation_code = val.into();
-        }
-        if let Some(val) = src.get(2..=3) {
-            dst.id = val.into();
+        Self {
+            key_activation_code: src.key_activation_code,
+            id: src.id,
+            parent_id: src.parent_id,
+            key_number: src.key_number,
         }
-        if let Some(val) = src.get(4..=5) {
-            dst.parent_id = val.into();
-        }
-        if let Some(&val) = src.get(6) {
-            dst.key_number = val.into();
-        }
-        dst
     }
 }
 
-/// Datastructure for [`MessageType::SoftKeyActivation`] responses.
-#[derive(Debug, Default)]
-pub struct SoftKeyActivationResponse {
-    pub key_activation_code: KeyActivationCode,
-    pub id: ObjectId,
-    pub parent_id: ObjectId,
-    pub key_number: u8,
+isobus_message! {
+    /// Datastructure for [`MessageType::SoftKeyActivation`] responses.
+    #[derive(Default)]
+    pub struct SoftKeyActivationResponse {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::SoftKeyActivation as u8,
+        priority: 5,
+        length: 8,
+        pub key_activation_code: KeyActivationCode = [8, 8],
+        pub id: ObjectId = [16, 16],
+        pub parent_id: ObjectId = [32, 16],
+        pub key_number: u8 = [48, 8],
+    }
 }
 impl From<SoftKeyActivationMessage> for SoftKeyActivationResponse {
     fn from(src: SoftKeyActivationMessage) -> Self {
-        let mut dst = SoftKeyActivationResponse::default();
-        dst.key_activation_code = src.key_activation_code;
-        dst.id = src.id;
-        dst.parent_id = src.parent_id;
-        dst.key_number = src.key_number;
-        dst
-    }
-}
-impl From<SoftKeyActivationResponse> for Vec<u8> {
-    fn from(src: SoftKeyActivationResponse) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::SoftKeyActivation as u8;
-        dst[1] = src.key_activation_code as u8;
-        dst[2..=3].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[4..=5].copy_from_slice(&Vec::<u8>::from(src.parent_id));
-        dst[6] = src.key_number;
-        dst
-    }
-}
-impl From<&[u8]> for SoftKeyActivationResponse {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = SoftKeyActivationResponse::default();
-        if let Some(&val) = src.get(1) {
-            dst.key_activation_code = val.into();
+        Self {
+            key_activation_code: src.key_activation_code,
+            id: src.id,
+            parent_id: src.parent_id,
+            key_number: src.key_number,
         }
-        if let Some(val) = src.get(2..=3) {
-            dst.id = val.into();
-        }
-        if let Some(val) = src.get(4..=5) {
-            dst.parent_id = val.into();
-        }
-        if let Some(&val) = src.get(6) {
-            dst.key_number = val.into();
-        }
-        dst
     }
 }
 
-/// Datastructure for [`MessageType::ButtonActivation`] messages.
-#[derive(Debug, Default)]
-pub struct ButtonActivationMessage {
-    pub key_activation_code: KeyActivationCode,
-    pub id: ObjectId,
-    pub parent_id: ObjectId,
-    pub key_number: u8,
+isobus_message! {
+    /// Datastructure for [`MessageType::ButtonActivation`] messages.
+    #[derive(Default)]
+    pub struct ButtonActivationMessage {
+        pgn: PGN::VT_TO_ECU,
+        function_code: MessageType::ButtonActivation as u8,
+        priority: 5,
+        length: 8,
+        pub key_activation_code: KeyActivationCode = [8, 8],
+        pub id: ObjectId = [16, 16],
+        pub parent_id: ObjectId = [32, 16],
+        pub key_number: u8 = [48, 8],
+    }
 }
 impl From<ButtonActivationResponse> for ButtonActivationMessage {
     fn from(src: ButtonActivationResponse) -> Self {
-        let mut dst = ButtonActivationMessage::default();
-        dst.key_activation_code = src.key_activation_code;
-        dst.id = src.id;
-        dst.parent_id = src.parent_id;
-        dst.key_number = src.key_number;
-        dst
-    }
-}
-impl From<ButtonActivationMessage> for Vec<u8> {
-    fn from(src: ButtonActivationMessage) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::ButtonActivation as u8;
-        dst[1] = src.key_activation_code as u8;
-        dst[2..=3].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[4..=5].copy_from_slice(&Vec::<u8>::from(src.parent_id));
-        dst[6] = src.key_number;
-        dst
-    }
-}
-impl From<&[u8]> for ButtonActivationMessage {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = ButtonActivationMessage::default();
-        if let Some(&val) = src.get(1) {
-            dst.key_activation_code = val.into();
-        }
-        if let Some(val) = src.get(2..=3) {
-            dst.id = val.into();
-        }
-        if let Some(val) = src.get(4..=5) {
-            dst.parent_id = val.into();
+        Self {
+            key_activation_code: src.key_activation_code,
+            id: src.id,
+            parent_id: src.parent_id,
+            key_number: src.key_number,
         }
-        if let Some(&val) = src.get(6) {
-            dst.key_number = val.into();
-        }
-        dst
     }
 }
 
-/// Datastructure for [`MessageType::ButtonActivation`] responses.
-#[derive(Debug, Default)]
-pub struct ButtonActivationResponse {
-    pub key_activation_code: KeyActivationCode,
-    pub id: ObjectId,
-    pub parent_id: ObjectId,
-    pub key_number: u8,
+isobus_message! {
+    /// Datastructure for [`MessageType::ButtonActivation`] responses.
+    #[derive(Default)]
+    pub struct ButtonActivationResponse {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::ButtonActivation as u8,
+        priority: 5,
+        length: 8,
+        pub key_activation_code: KeyActivationCode = [8, 8],
+        pub id: ObjectId = [16, 16],
+        pub parent_id: ObjectId = [32, 16],
+        pub key_number: u8 = [48, 8],
+    }
 }
 impl From<ButtonActivationMessage> for ButtonActivationResponse {
     fn from(src: ButtonActivationMessage) -> Self {
-        let mut dst = ButtonActivationResponse::default();
-        dst.key_activation_code = src.key_activation_code;
-        dst.id = src.id;
-        dst.parent_id = src.parent_id;
-        dst.key_number = src.key_number;
-        dst
-    }
-}
-impl From<ButtonActivationResponse> for Vec<u8> {
-    fn from(src: ButtonActivationResponse) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::ButtonActivation as u8;
-        dst[1] = src.key_activation_code as u8;
-        dst[2..=3].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[4..=5].copy_from_slice(&Vec::<u8>::from(src.parent_id));
-        dst[6] = src.key_number;
-        dst
-    }
-}
-impl From<&[u8]> for ButtonActivationResponse {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = ButtonActivationResponse::default();
-        if let Some(&val) = src.get(1) {
-            dst.key_activation_code = val.into();
-        }
-        if let Some(val) = src.get(2..=3) {
-            dst.id = val.into();
-        }
-        if let Some(val) = src.get(4..=5) {
-            dst.parent_id = val.into();
+        Self {
+            key_activation_code: src.key_activation_code,
+            id: src.id,
+            parent_id: src.parent_id,
+            key_number: src.key_number,
         }
-        if let Some(&val) = src.get(6) {
-            dst.key_number = val.into();
-        }
-        dst
     }
 }
 
 // TODO: Accept diffrent sized values
-/// Datastructure for [`MessageType::VTChangeNumericValue`] commands.
-#[derive(Debug, Default)]
-pub struct VTChangeNumericValueCommand {
-    pub id: ObjectId,
-    pub value: u32,
+isobus_message! {
+    /// Datastructure for [`MessageType::VTChangeNumericValue`] commands.
+    #[derive(Default)]
+    pub struct VTChangeNumericValueCommand {
+        pgn: PGN::VT_TO_ECU,
+        function_code: MessageType::VTChangeNumericValue as u8,
+        priority: 5,
+        length: 8,
+        pub id: ObjectId = [8, 16],
+        pub value: u32 = [32, 32],
+    }
 }
 impl From<VTChangeNumericValueResponse> for VTChangeNumericValueCommand {
     fn from(src: VTChangeNumericValueResponse) -> Self {
-        let mut dst = VTChangeNumericValueCommand::default();
-        dst.id = src.id;
-        dst.value = src.value;
-        dst
-    }
-}
-impl From<VTChangeNumericValueCommand> for Vec<u8> {
-    fn from(src: VTChangeNumericValueCommand) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::VTChangeNumericValue as u8;
-        dst[1..=2].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[4..=7].copy_from_slice(&src.value.to_le_bytes());
-        dst
-    }
-}
-impl From<&[u8]> for VTChangeNumericValueCommand {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = VTChangeNumericValueCommand::default();
-        if let Some(val) = src.get(1..=2) {
-            dst.id = val.into();
-        }
-        if let Some(val) = src.get(4..=7) {
-            dst.value = u32::from_le_bytes([val[0], val[1], val[2], val[3]]);
+        Self {
+            id: src.id,
+            value: src.value,
         }
-        dst
     }
 }
 
-/// Datastructure for [`MessageType::VTChangeNumericValue`] responses.
-#[derive(Debug, Default)]
-pub struct VTChangeNumericValueResponse {
-    pub id: ObjectId,
-    pub error_code: u8,
-    pub value: u32,
+isobus_message! {
+    /// Datastructure for [`MessageType::VTChangeNumericValue`] responses.
+    #[derive(Default)]
+    pub struct VTChangeNumericValueResponse {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::VTChangeNumericValue as u8,
+        priority: 5,
+        length: 8,
+        pub id: ObjectId = [8, 16],
+        pub error_code: u8 = [24, 8],
+        pub value: u32 = [32, 32],
+    }
 }
 impl From<VTChangeNumericValueCommand> for VTChangeNumericValueResponse {
     fn from(src: VTChangeNumericValueCommand) -> Self {
-        let mut dst = VTChangeNumericValueResponse::default();
-        dst.id = src.id;
-        dst.error_code = 0;
-        dst.value = src.value;
-        dst
-    }
-}
-impl From<VTChangeNumericValueResponse> for Vec<u8> {
-    fn from(src: VTChangeNumericValueResponse) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::VTChangeNumericValue as u8;
-        dst[1..=2].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[3] = src.error_code;
-        dst[4..=7].copy_from_slice(&src.value.to_le_bytes());
-        dst
-    }
-}
-impl From<&[u8]> for VTChangeNumericValueResponse {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = VTChangeNumericValueResponse::default();
-        if let Some(val) = src.get(1..=2) {
-            dst.id = val.into();
+        Self {
+            id: src.id,
+            error_code: 0,
+            value: src.value,
         }
-        if let Some(&val) = src.get(3) {
-            dst.error_code = val;
-        }
-        if let Some(val) = src.get(4..=7) {
-            dst.value = u32::from_le_bytes([val[0], val[1], val[2], val[3]]);
-        }
-        dst
     }
 }
 
@@ -890,10 +748,10 @@ pub struct VTChangeStringValueCommand {
 }
 impl From<VTChangeStringValueResponse> for VTChangeStringValueCommand {
     fn from(src: VTChangeStringValueResponse) -> Self {
-        let mut dst = VTChangeStringValueCommand::default();
-        dst.id = src.id;
-        dst.value = src.value;
-        dst
+        Self {
+            id: src.id,
+            value: src.value,
+        }
     }
 }
 impl From<VTChangeStringValueCommand> for Vec<u8> {
@@ -930,10 +788,10 @@ pub struct VTChangeStringValueResponse {
 }
 impl From<VTChangeStringValueCommand> for VTChangeStringValueResponse {
     fn from(src: VTChangeStringValueCommand) -> Self {
-        let mut dst = VTChangeStringValueResponse::default();
-        dst.id = src.id;
-        dst.value = src.value;
-        dst
+        Self {
+            id: src.id,
+            value: src.value,
+        }
     }
 }
 impl From<VTChangeStringValueResponse> for Vec<u8> {
@@ -944,55 +802,25 @@ impl From<VTChangeStringValueResponse> for Vec<u8> {
         dst
     }
 }
-// impl From<&[u8]> for VTChangeStringValueResponse {
-//     fn from(src: &[u8]) -> Self {
-//         let mut dst = VTChangeStringValueResponse::default();
-//         if let Some(val) = src.get(1..=2) {
-//             dst.id = val.into();
-//         }
-//         if let Some(&val) = src.get(3) {
-//             dst.error_code = val;
-//         }
-//         if let Some(val) = src.get(4..=7) {
-//             dst.value = u32::from_le_bytes([val[0], val[1], val[2], val[3]]);
-//         }
-//         dst
-//     }
-// }
 
-/// Datastructure for [`MessageType::ChangeNumericValue`] commands.
-#[derive(Debug, Default)]
-pub struct ChangeNumericValueCommand {
-    pub id: ObjectId,
-    pub value: u32,
+isobus_message! {
+    /// Datastructure for [`MessageType::ChangeNumericValue`] commands.
+    #[derive(Default)]
+    pub struct ChangeNumericValueCommand {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::ChangeNumericValue as u8,
+        priority: 5,
+        length: 8,
+        pub id: ObjectId = [8, 16],
+        pub value: u32 = [32, 32],
+    }
 }
 impl From<ChangeNumericValueResponse> for ChangeNumericValueCommand {
     fn from(src: ChangeNumericValueResponse) -> Self {
-        let mut dst = ChangeNumericValueCommand::default();
-        dst.id = src.id;
-        dst.value = src.value;
-        dst
-    }
-}
-impl From<ChangeNumericValueCommand> for Vec<u8> {
-    fn from(src: ChangeNumericValueCommand) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::ChangeNumericValue as u8;
-        dst[1..=2].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[4..=7].copy_from_slice(&src.value.to_le_bytes());
-        dst
-    }
-}
-impl From<&[u8]> for ChangeNumericValueCommand {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = ChangeNumericValueCommand::default();
-        if let Some(val) = src.get(1..=2) {
-            dst.id = val.into();
-        }
-        if let Some(val) = src.get(4..=7) {
-            dst.value = u32::from_le_bytes([val[0], val[1], val[2], val[3]]);
+        Self {
+            id: src.id,
+            value: src.value,
         }
-        dst
     }
 }
 
@@ -1005,11 +833,11 @@ pub struct ChangeNumericValueResponse {
 }
 impl From<ChangeNumericValueCommand> for ChangeNumericValueResponse {
     fn from(src: ChangeNumericValueCommand) -> Self {
-        let mut dst = ChangeNumericValueResponse::default();
-        dst.id = src.id;
-        dst.error_code = 0;
-        dst.value = src.value;
-        dst
+        Self {
+            id: src.id,
+            error_code: 0,
+            value: src.value,
+        }
     }
 }
 impl From<ChangeNumericValueResponse> for Vec<u8> {
@@ -1037,75 +865,37 @@ impl From<&[u8]> for ChangeNumericValueResponse {
     }
 }
 
-/// Datastructure for [`MessageType::ChangeActiveMask`] commands.
-#[derive(Debug, Default)]
-pub struct ChangeActiveMaskCommand {
-    pub working_set_id: ObjectId,
-    pub mask_id: ObjectId,
-}
-// impl From<ChangeActiveMaskResponse> for ChangeActiveMaskCommand {
-//     fn from(src: ChangeActiveMaskResponse) -> Self {
-//         let mut dst = ChangeActiveMaskCommand::default();
-//         dst.working_set_id = src.working_set_id;
-//         dst.mask_id = src.mask_id;
-//         dst
-//     }
-// }
-impl From<ChangeActiveMaskCommand> for Vec<u8> {
-    fn from(src: ChangeActiveMaskCommand) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::ChangeActiveMask as u8;
-        dst[1..=2].copy_from_slice(&Vec::<u8>::from(src.working_set_id));
-        dst[3..=4].copy_from_slice(&Vec::<u8>::from(src.mask_id));
-        dst
-    }
-}
-impl From<&[u8]> for ChangeActiveMaskCommand {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = ChangeActiveMaskCommand::default();
-        if let Some(val) = src.get(1..=2) {
-            dst.working_set_id = val.into();
-        }
-        if let Some(val) = src.get(3..=4) {
-            dst.mask_id = val.into();
-        }
-        dst
+isobus_message! {
+    /// Datastructure for [`MessageType::ChangeActiveMask`] commands.
+    #[derive(Default)]
+    pub struct ChangeActiveMaskCommand {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::ChangeActiveMask as u8,
+        priority: 5,
+        length: 8,
+        pub working_set_id: ObjectId = [8, 16],
+        pub mask_id: ObjectId = [24, 16],
     }
 }
 
-/// Datastructure for [`MessageType::ChangeActiveMask`] responses.
-#[derive(Debug, Default)]
-pub struct ChangeActiveMaskResponse {
-    pub mask_id: ObjectId,
-    pub error_code: u8,
+isobus_message! {
+    /// Datastructure for [`MessageType::ChangeActiveMask`] responses.
+    #[derive(Default)]
+    pub struct ChangeActiveMaskResponse {
+        pgn: PGN::VT_TO_ECU,
+        function_code: MessageType::ChangeActiveMask as u8,
+        priority: 5,
+        length: 8,
+        pub mask_id: ObjectId = [8, 16],
+        pub error_code: u8 = [24, 8],
+    }
 }
 impl From<ChangeActiveMaskCommand> for ChangeActiveMaskResponse {
     fn from(src: ChangeActiveMaskCommand) -> Self {
-        let mut dst = ChangeActiveMaskResponse::default();
-        dst.mask_id = src.mask_id;
-        dst.error_code = 0;
-        dst
-    }
-}
-impl From<ChangeActiveMaskResponse> for Vec<u8> {
-    fn from(src: ChangeActiveMaskResponse) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::ChangeActiveMask as u8;
-        dst[1..=2].copy_from_slice(&Vec::<u8>::from(src.mask_id));
-        dst[3] = src.error_code;
-        dst
-    }
-}
-impl From<&[u8]> for ChangeActiveMaskResponse {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = ChangeActiveMaskResponse::default();
-        if let Some(val) = src.get(1..=2) {
-            dst.mask_id = val.into();
+        Self {
+            mask_id: src.mask_id,
+            error_code: 0,
         }
-        if let Some(&val) = src.get(3) {
-            dst.error_code = val;
-        }
-        dst
     }
 }
 
@@ -1149,56 +939,27 @@ impl From<&[u8]> for ChangeStringValueCommand {
     }
 }
 
-/// Datastructure for [`MessageType::ChangeStringValue`] responses.
-#[derive(Debug, Default)]
-pub struct ChangeStringValueResponse {
-    pub id: ObjectId,
-    pub error_code: u8,
-}
-// impl From<ChangeStringValueCommand> for ChangeStringValueResponse {
-//     fn from(src: ChangeStringValueCommand) -> Self {
-//         let mut dst = ChangeStringValueResponse::default();
-//         dst.id = src.id;
-//         dst.error_code = 0;
-//         dst.value = src.value;
-//         dst
-//     }
-// }
-impl From<ChangeStringValueResponse> for Vec<u8> {
-    fn from(src: ChangeStringValueResponse) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::ChangeStringValue as u8;
-        dst[3..=4].copy_from_slice(&Vec::<u8>::from(src.id));
-        dst[5] = src.error_code;
-        dst
-    }
-}
-impl From<&[u8]> for ChangeStringValueResponse {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = ChangeStringValueResponse::default();
-        if let Some(val) = src.get(3..=4) {
-            dst.id = val.into();
-        }
-        if let Some(&val) = src.get(5) {
-            dst.error_code = val;
-        }
-        dst
+isobus_message! {
+    /// Datastructure for [`MessageType::ChangeStringValue`] responses.
+    #[derive(Default)]
+    pub struct ChangeStringValueResponse {
+        pgn: PGN::VT_TO_ECU,
+        function_code: MessageType::ChangeStringValue as u8,
+        priority: 5,
+        length: 8,
+        pub id: ObjectId = [24, 16],
+        pub error_code: u8 = [40, 8],
     }
 }
 
-/// Datastructure for [`MessageType::EndOfObjectPool`] messages.
-#[derive(Debug, Default)]
-pub struct EndOfObjectPoolMessage {}
-impl From<EndOfObjectPoolMessage> for Vec<u8> {
-    fn from(_src: EndOfObjectPoolMessage) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::EndOfObjectPool as u8;
-        dst
-    }
-}
-impl From<&[u8]> for EndOfObjectPoolMessage {
-    fn from(_src: &[u8]) -> Self {
-        EndOfObjectPoolMessage::default()
+isobus_message! {
+    /// Datastructure for [`MessageType::EndOfObjectPool`] messages.
+    #[derive(Default)]
+    pub struct EndOfObjectPoolMessage {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::EndOfObjectPool as u8,
+        priority: 5,
+        length: 8,
     }
 }
 
@@ -1240,73 +1001,122 @@ impl From<&[u8]> for EndOfObjectPoolResponse {
     }
 }
 
-/// Datastructure for [`MessageType::VTStatus`] messages.
-#[derive(Debug, Default)]
-pub struct VTStatusMessage {
-    pub active_working_set: IsobusAddress,
-    pub data_alarm_mask: ObjectId,
-    pub soft_key_mask: ObjectId,
-    pub vt_busy_code: VTBusyCode,
-    pub vt_function_code: u8,
+isobus_message! {
+    /// Datastructure for [`MessageType::VTStatus`] messages.
+    #[derive(Default)]
+    pub struct VTStatusMessage {
+        pgn: PGN::VT_TO_ECU,
+        function_code: MessageType::VTStatus as u8,
+        priority: 5,
+        length: 8,
+        pub active_working_set: IsobusAddress = [8, 8],
+        pub data_alarm_mask: ObjectId = [16, 16],
+        pub soft_key_mask: ObjectId = [32, 16],
+        pub vt_busy_code: VTBusyCode = [48, 8],
+        pub vt_function_code: u8 = [56, 8],
+    }
 }
-impl From<VTStatusMessage> for Vec<u8> {
-    fn from(src: VTStatusMessage) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::VTStatus as u8;
-        dst[1] = src.active_working_set.into();
-        dst[2..=3].copy_from_slice(&Vec::<u8>::from(src.data_alarm_mask));
-        dst[4..=5].copy_from_slice(&Vec::<u8>::from(src.soft_key_mask));
-        dst[6] = src.vt_busy_code.bits();
-        dst[7] = src.vt_function_code;
-        dst
+
+isobus_message! {
+    /// Datastructure for [`MessageType::WorkingSetMaintenance`] messages.
+    #[derive(Default)]
+    pub struct WorkingSetMaintenanceMessage {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::WorkingSetMaintenance as u8,
+        priority: 5,
+        length: 8,
+        pub bit_mask: WorkingSetMaintenanceCode = [8, 8],
+        pub version_number: VTVersion = [16, 8],
     }
 }
-impl From<&[u8]> for VTStatusMessage {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = VTStatusMessage::default();
-        if let Some(&val) = src.get(1) {
-            dst.active_working_set = val.into();
-        }
-        if let Some(val) = src.get(2..=3) {
-            dst.data_alarm_mask = val.into();
-        }
-        if let Some(val) = src.get(4..=5) {
-            dst.soft_key_mask = val.into();
-        }
-        if let Some(&val) = src.get(6) {
-            dst.vt_busy_code = VTBusyCode::from_bits_truncate(val);
-        }
-        if let Some(&val) = src.get(7) {
-            dst.vt_function_code = val.into();
-        }
-        dst
+
+isobus_message! {
+    /// Datastructure for [`MessageType::GetHardware`] messages.
+    #[derive(Default)]
+    pub struct GetHardwareMessage {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::GetHardware as u8,
+        priority: 5,
+        length: 8,
     }
 }
 
-/// Datastructure for [`MessageType::WorkingSetMaintenance`] messages.
-#[derive(Debug, Default)]
-pub struct WorkingSetMaintenanceMessage {
-    pub bit_mask: WorkingSetMaintenanceCode,
-    pub version_number: VTVersion,
+isobus_message! {
+    /// Datastructure for [`MessageType::GetNumberOfSoftKeys`] messages.
+    #[derive(Default)]
+    pub struct GetNumberOfSoftKeysMessage {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::GetNumberOfSoftKeys as u8,
+        priority: 5,
+        length: 8,
+    }
 }
-impl From<WorkingSetMaintenanceMessage> for Vec<u8> {
-    fn from(src: WorkingSetMaintenanceMessage) -> Self {
-        let mut dst: Vec<u8> = vec![0xFF; 8];
-        dst[0] = MessageType::WorkingSetMaintenance as u8;
-        dst[1] = src.bit_mask.bits();
-        dst[2] = src.version_number as u8;
-        dst
+
+isobus_message! {
+    /// Datastructure for [`MessageType::GetTextFontData`] messages.
+    #[derive(Default)]
+    pub struct GetTextFontDataMessage {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::GetTextFontData as u8,
+        priority: 5,
+        length: 8,
     }
 }
-impl From<&[u8]> for WorkingSetMaintenanceMessage {
-    fn from(src: &[u8]) -> Self {
-        let mut dst = WorkingSetMaintenanceMessage::default();
-        if let Some(&val) = src.get(1) {
-            dst.bit_mask = WorkingSetMaintenanceCode::from_bits_truncate(val);
-        }
-        if let Some(&val) = src.get(2) {
-            dst.version_number = val.into();
-        }
-        dst
+
+isobus_message! {
+    /// Datastructure for [`MessageType::GetMemory`] messages.
+    #[derive(Default)]
+    pub struct GetMemoryMessage {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::GetMemory as u8,
+        priority: 5,
+        length: 8,
+        /// The memory in bytes required for the object pool.
+        pub required_memory: u32 = [16, 32],
     }
 }
+
+isobus_message! {
+    /// Datastructure for [`MessageType::GetVersionsMessage`] messages.
+    #[derive(Default)]
+    pub struct GetVersionsMessage {
+        pgn: PGN::ECU_TO_VT,
+        function_code: MessageType::GetVersionsMessage as u8,
+        priority: 5,
+        length: 8,
+    }
+}
+
+/// The byte conversions of the messages, kept next to [`IsobusMessage`].
+/// Data that does not decode, e.g. a short frame, converts to the default message.
+macro_rules! byte_conversions {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Vec<u8> {
+                fn from(src: $ty) -> Self {
+                    src.encode()
+                }
+            }
+            impl From<&[u8]> for $ty {
+                fn from(src: &[u8]) -> Self {
+                    <$ty>::decode(src).unwrap_or_default()
+                }
+            }
+        )*
+    };
+}
+byte_conversions!(
+    SoftKeyActivationMessage,
+    SoftKeyActivationResponse,
+    ButtonActivationMessage,
+    ButtonActivationResponse,
+    VTChangeNumericValueCommand,
+    VTChangeNumericValueResponse,
+    ChangeNumericValueCommand,
+    ChangeActiveMaskCommand,
+    ChangeActiveMaskResponse,
+    ChangeStringValueResponse,
+    EndOfObjectPoolMessage,
+    VTStatusMessage,
+    WorkingSetMaintenanceMessage
+);
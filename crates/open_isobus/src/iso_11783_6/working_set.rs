@@ -1,10 +1,13 @@
-use alloc::collections::VecDeque;
+use alloc::{boxed::Box, collections::VecDeque};
 
 use crate::{
-    iso_11783_3::PDU,
-    iso_11783_5::Name,
+    drivers::CanDriverTrait,
+    iso_11783_3::{TransportEvent, PDU, PGN},
+    iso_11783_5::{Name, NameFilter, NetworkEvent},
     iso_11783_7::{LanguageSettings, LanguageSettingsBuilder},
-    Isobus, IsobusAddress,
+    isobus::IsobusBuilder,
+    message::IsobusMessage,
+    Isobus, IsobusAddress, IsobusEvent, Listener, Partner,
 };
 
 use super::{events::EventType, pdu::*, ObjectPool};
@@ -28,8 +31,12 @@ enum State {
 pub struct WorkingSet {
     state: State,
     isobus: Isobus,
+    listener: Listener,
+    /// The primary VT.
+    vt: Partner,
     object_pool: ObjectPool,
-    connected_vt: IsobusAddress,
+    /// The NAME of the VT of the session, its address is looked up for every message.
+    connected_vt: Option<Name>,
     language_settings: LanguageSettings,
 
     event_queue: VecDeque<EventType>,
@@ -40,25 +47,45 @@ pub struct WorkingSet {
 
 impl WorkingSet {
     pub fn new(object_pool: ObjectPool) -> Self {
-        let isobus = Isobus::builder()
-            .name(
-                Name::builder()
-                    .has_self_configurable_address(true) // Dynamicaly claim address
-                    .industry_group(2) // Agricultural machinery
-                    .device_class(25) // Slurry/Manure Applicators
-                    .function(128) // Slurry/Manure Rate Control
-                    .manufacturer_code(1407) // Open-Agriculture
-                    .ecu_instance(1)
-                    .build(),
-            )
-            // .address_to_claim(IsobusAddress(0x80)) // Address for the in cab VT
-            .build();
+        Self::with_isobus(object_pool, Self::isobus_builder().build())
+    }
+
+    /// Use the given driver instead of the default driver selected by the enabled features.
+    pub fn with_driver(object_pool: ObjectPool, driver: Box<dyn CanDriverTrait>) -> Self {
+        Self::with_isobus(object_pool, Self::isobus_builder().driver(driver).build())
+    }
+
+    fn isobus_builder() -> IsobusBuilder {
+        let mut builder = Isobus::builder();
+        builder.name(
+            Name::builder()
+                .has_self_configurable_address(true) // Dynamicaly claim address
+                .industry_group(2) // Agricultural machinery
+                .device_class(25) // Slurry/Manure Applicators
+                .function(128) // Slurry/Manure Rate Control
+                .manufacturer_code(1407) // Open-Agriculture
+                .ecu_instance(1)
+                .build(),
+        );
+        // builder.address_to_claim(IsobusAddress(0x80)); // Address for the in cab VT
+        builder
+    }
+
+    fn with_isobus(object_pool: ObjectPool, mut isobus: Isobus) -> Self {
+        let listener = isobus.listen(
+            &[PGN::VT_TO_ECU, PGN::LANGUAGE_COMMAND, PGN::TIME_DATE],
+            None,
+        );
+        isobus.subscribe_transport_events();
+        let vt = isobus.add_partner(&[NameFilter::Function(29), NameFilter::FunctionInstance(0)]);
 
         Self {
             state: State::Idle,
             isobus,
+            listener,
+            vt,
             object_pool,
-            connected_vt: IsobusAddress::NULL,
+            connected_vt: None,
             language_settings: LanguageSettingsBuilder::new().build(),
 
             event_queue: VecDeque::new(),
@@ -68,18 +95,65 @@ impl WorkingSet {
         }
     }
 
+    /// The bus of the working set, e.g. to listen to other PGNs or add request responders.
+    pub fn isobus(&self) -> &Isobus {
+        &self.isobus
+    }
+
+    pub fn isobus_mut(&mut self) -> &mut Isobus {
+        &mut self.isobus
+    }
+
     pub fn process(&mut self, time: u64) {
-        let pdus = self.isobus.process(time);
+        self.isobus.process(time);
+
+        while let Some(event) = self.isobus.next_event() {
+            match event {
+                IsobusEvent::BusUnavailable(_) => self.disconnect_vt(),
+                // Start a new VT session, the VT dropped ours while we were gone.
+                IsobusEvent::Reconnected(_) => self.disconnect_vt(),
+                IsobusEvent::Transport(event) => {
+                    // The object pool upload is only reported as `ObjectPoolTransfer`.
+                    if self.transport_event(event, time) {
+                        continue;
+                    }
+                }
+                IsobusEvent::NetworkError(_) => self.disconnect_vt(),
+                IsobusEvent::Network(NetworkEvent::Left(vt))
+                    if Some(vt.name) == self.connected_vt =>
+                {
+                    self.disconnect_vt()
+                }
+                IsobusEvent::Network(NetworkEvent::AddressChanged {
+                    control_function: vt,
+                    previous_address,
+                }) if Some(vt.name) == self.connected_vt => {
+                    // The session continues, the messages follow the VT to its new address.
+                    log::info!("VT moved from {previous_address} to {}", vt.address);
+                }
+                IsobusEvent::Network(_) => {}
+            }
+            self.event_queue.push_back(EventType::Isobus(event));
+        }
+
+        while let Some(pdu) = self.isobus.receive(self.listener) {
+            // Only the VT of the session, or the primary VT when there is none, is listened to.
+            let vt = match self.connected_vt {
+                Some(_) => self.vt_address(),
+                None => self.isobus.partner(self.vt).map(|vt| vt.address),
+            };
+            if vt != Some(pdu.source_address()) {
+                continue;
+            }
 
-        for pdu in pdus {
-            // Received the first VT Status Message
+            // Received the first VT Status Message of the primary VT
             if pdu.is_vt_status_message()
                 && !self.is_vt_connected()
                 && self.isobus.is_connected()
                 && self.state == State::Idle
             {
                 log::info!("Start connecting to VT: {}", pdu.source_address());
-                self.connected_vt = pdu.source_address();
+                self.connected_vt = self.isobus.partner(self.vt).map(|vt| vt.name);
                 self.isobus.send(
                     PDU::new_working_set_master(self.isobus.claimed_address()),
                     time,
@@ -88,13 +162,7 @@ impl WorkingSet {
                 // Send out the first Working set maintenance message.
                 self.cyclic_send_working_set_maintenance_message(time);
 
-                self.isobus.send(
-                    PDU::new_request_language_command(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                    ),
-                    time,
-                );
+                self.send_to_vt(PDU::new_request_language_command, time);
                 self.state = State::RequestedLanguageCommand;
                 break;
             }
@@ -102,10 +170,7 @@ impl WorkingSet {
             // Received the language command
             if pdu.is_language_command() && self.state == State::RequestedLanguageCommand {
                 self.language_settings = LanguageSettings::from_data(&pdu.data::<8>());
-                self.isobus.send(
-                    PDU::new_get_hardware_message(self.connected_vt, self.isobus.claimed_address()),
-                    time,
-                );
+                self.send_to_vt(PDU::new_get_hardware_message, time);
                 self.state = State::RequestedGetHardwareResponse;
                 continue;
             }
@@ -113,13 +178,7 @@ impl WorkingSet {
             // Received the get hardware response
             if pdu.is_get_hardware_response() && self.state == State::RequestedGetHardwareResponse {
                 // self.language_settings = LanguageSettings::from_data(pdu.data());
-                self.isobus.send(
-                    PDU::new_get_number_of_softkeys_message(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                    ),
-                    time,
-                );
+                self.send_to_vt(PDU::new_get_number_of_softkeys_message, time);
                 self.state = State::RequestedGetNumberOfSoftkeysResponse;
                 continue;
             }
@@ -129,13 +188,7 @@ impl WorkingSet {
                 && self.state == State::RequestedGetNumberOfSoftkeysResponse
             {
                 // self.language_settings = LanguageSettings::from_data(pdu.data());
-                self.isobus.send(
-                    PDU::new_get_text_font_data_message(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                    ),
-                    time,
-                );
+                self.send_to_vt(PDU::new_get_text_font_data_message, time);
                 self.state = State::RequestedGetTextFontDataResponse;
                 continue;
             }
@@ -145,10 +198,7 @@ impl WorkingSet {
                 && self.state == State::RequestedGetTextFontDataResponse
             {
                 // self.language_settings = LanguageSettings::from_data(pdu.data());
-                self.isobus.send(
-                    PDU::new_get_versions_message(self.connected_vt, self.isobus.claimed_address()),
-                    time,
-                );
+                self.send_to_vt(PDU::new_get_versions_message, time);
                 self.state = State::RequestedGetVersionsResponse;
                 continue;
             }
@@ -156,10 +206,7 @@ impl WorkingSet {
             // Received the get version response
             if pdu.is_get_versions_response() && self.state == State::RequestedGetVersionsResponse {
                 // self.language_settings = LanguageSettings::from_data(pdu.data());
-                self.isobus.send(
-                    PDU::new_request_time_date(self.connected_vt, self.isobus.claimed_address()),
-                    time,
-                );
+                self.send_to_vt(PDU::new_request_time_date, time);
                 self.state = State::RequestedTimeDate;
                 continue;
             }
@@ -167,14 +214,7 @@ impl WorkingSet {
             // Received the time/date
             if pdu.is_time_date() && self.state == State::RequestedTimeDate {
                 // self.language_settings = LanguageSettings::from_data(pdu.data());
-                self.isobus.send(
-                    PDU::new_get_memory_message(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                        0,
-                    ),
-                    time,
-                );
+                self.send_to_vt(|da, sa| PDU::new_get_memory_message(da, sa, 0), time);
                 self.state = State::RequestedVTVersion;
                 continue;
             }
@@ -182,42 +222,29 @@ impl WorkingSet {
             // Received get memory response containing the VT version
             if pdu.is_get_memory_response() && self.state == State::RequestedVTVersion {
                 // self.language_settings = LanguageSettings::from_data(pdu.data());
-                self.isobus.send(
-                    PDU::new_get_memory_message(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                        self.object_pool.size(),
-                    ),
-                    time,
-                );
+                let size = self.object_pool.size();
+                self.send_to_vt(|da, sa| PDU::new_get_memory_message(da, sa, size), time);
                 self.state = State::RequestedMemory;
                 continue;
             }
 
             // Received get memory response and check if there is enough space for our object pool
             if pdu.is_get_memory_response() && self.state == State::RequestedMemory {
-                self.isobus.send(
-                    PDU::new_object_pool_transfer_message(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                        &self.object_pool,
-                    ),
-                    time,
+                let Some(vt) = self.vt_address() else {
+                    continue;
+                };
+                let transfer = PDU::new_object_pool_transfer_message(
+                    vt,
+                    self.isobus.claimed_address(),
+                    &self.object_pool,
                 );
+                // A pool fitting in a single frame is not sent with TP or ETP.
+                let single_frame = transfer.data_raw().len() <= 8;
+                self.isobus.send(transfer, time);
                 self.state = State::SendingObjectPool;
-                continue;
-            }
-
-            // Received the result, our own object pool transfer message returned by the tp or etp
-            if pdu.is_object_pool_transfer_message() && self.state == State::SendingObjectPool {
-                self.isobus.send(
-                    PDU::new_end_of_object_pool_message(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                    ),
-                    time,
-                );
-                self.state = State::ObjectPoolSend;
+                if single_frame {
+                    self.send_end_of_object_pool(time);
+                }
                 continue;
             }
 
@@ -229,7 +256,13 @@ impl WorkingSet {
 
             if self.state == State::Connected {
                 if pdu.is_vt_status_message() {
-                    let data: VTStatusMessage = pdu.data_raw().into();
+                    let data = match VTStatusMessage::from_pdu(&pdu) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            log::error!("Invalid VT status message: \"{e:?}\"");
+                            continue;
+                        }
+                    };
 
                     if data.active_working_set == self.isobus.claimed_address() {
                         self.event_queue.push_back(EventType::OnActivate);
@@ -239,7 +272,13 @@ impl WorkingSet {
                 }
 
                 if pdu.is_soft_key_activation_message() {
-                    let data: SoftKeyActivationMessage = pdu.data_raw().into();
+                    let data = match SoftKeyActivationMessage::from_pdu(&pdu) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            log::error!("Invalid soft key activation message: \"{e:?}\"");
+                            continue;
+                        }
+                    };
 
                     match data.key_activation_code {
                         KeyActivationCode::Released => {
@@ -267,18 +306,20 @@ impl WorkingSet {
                     }
 
                     // Send optional response.
-                    self.isobus.send(
-                        PDU::new_soft_key_activation_response(
-                            self.connected_vt,
-                            self.isobus.claimed_address(),
-                            data.into(),
-                        ),
+                    self.send_to_vt(
+                        |da, sa| PDU::new_soft_key_activation_response(da, sa, data.into()),
                         time,
                     );
                 }
 
                 if pdu.is_button_activation_message() {
-                    let data: ButtonActivationMessage = pdu.data_raw().into();
+                    let data = match ButtonActivationMessage::from_pdu(&pdu) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            log::error!("Invalid button activation message: \"{e:?}\"");
+                            continue;
+                        }
+                    };
 
                     match data.key_activation_code {
                         KeyActivationCode::Released => {
@@ -306,29 +347,27 @@ impl WorkingSet {
                     }
 
                     // Send optional response.
-                    self.isobus.send(
-                        PDU::new_button_activation_response(
-                            self.connected_vt,
-                            self.isobus.claimed_address(),
-                            data.into(),
-                        ),
+                    self.send_to_vt(
+                        |da, sa| PDU::new_button_activation_response(da, sa, data.into()),
                         time,
                     );
                 }
 
                 if pdu.is_vt_change_numeric_value_command() {
-                    let data: VTChangeNumericValueCommand = pdu.data_raw().into();
+                    let data = match VTChangeNumericValueCommand::from_pdu(&pdu) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            log::error!("Invalid VT change numeric value command: \"{e:?}\"");
+                            continue;
+                        }
+                    };
 
                     self.event_queue
                         .push_back(EventType::NumericValueChanged(data.id, data.value));
 
                     // Send optional response.
-                    self.isobus.send(
-                        PDU::new_vt_change_numeric_value_response(
-                            self.connected_vt,
-                            self.isobus.claimed_address(),
-                            data.into(),
-                        ),
+                    self.send_to_vt(
+                        |da, sa| PDU::new_vt_change_numeric_value_response(da, sa, data.into()),
                         time,
                     );
                 }
@@ -339,12 +378,8 @@ impl WorkingSet {
                         .push_back(EventType::StringValueChanged(data.id, data.value.clone()));
 
                     // Send optional response.
-                    self.isobus.send(
-                        PDU::new_vt_change_string_value_response(
-                            self.connected_vt,
-                            self.isobus.claimed_address(),
-                            data.into(),
-                        ),
+                    self.send_to_vt(
+                        |da, sa| PDU::new_vt_change_string_value_response(da, sa, data.into()),
                         time,
                     );
                 }
@@ -359,7 +394,38 @@ impl WorkingSet {
         }
 
         // Cyclicly send the working set maintenance every second.
-        self.cyclic_send_working_set_maintenance_message(time);
+        if self.is_vt_connected() {
+            self.cyclic_send_working_set_maintenance_message(time);
+        }
+    }
+
+    /// Report the progress of the object pool upload, the upload is finished when it is completed.
+    /// Returns true when the event belongs to the upload.
+    fn transport_event(&mut self, event: TransportEvent, time: u64) -> bool {
+        let session = event.session();
+        if self.state != State::SendingObjectPool
+            || session.pgn != PGN::ECU_TO_VT
+            || Some(session.destination_address) != self.vt_address()
+        {
+            return false;
+        }
+
+        if let TransportEvent::Aborted { reason, .. } = event {
+            log::warn!("Object pool transfer aborted: \"{reason:?}\", sending it again");
+        }
+        let completed = matches!(event, TransportEvent::Completed(_));
+        self.event_queue
+            .push_back(EventType::ObjectPoolTransfer(event));
+
+        if completed {
+            self.send_end_of_object_pool(time);
+        }
+        true
+    }
+
+    fn send_end_of_object_pool(&mut self, time: u64) {
+        self.send_to_vt(PDU::new_end_of_object_pool_message, time);
+        self.state = State::ObjectPoolSend;
     }
 
     pub fn next_event(&mut self) -> Option<EventType> {
@@ -371,12 +437,8 @@ impl WorkingSet {
             EventType::NumericValueChanged(id, value) => {
                 let data = ChangeNumericValueCommand { id, value };
 
-                self.isobus.send(
-                    PDU::new_change_numeric_value_command(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                        data,
-                    ),
+                self.send_to_vt(
+                    |da, sa| PDU::new_change_numeric_value_command(da, sa, data),
                     time,
                 );
             }
@@ -386,24 +448,16 @@ impl WorkingSet {
                     mask_id,
                 };
 
-                self.isobus.send(
-                    PDU::new_change_active_mask_command(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                        data,
-                    ),
+                self.send_to_vt(
+                    |da, sa| PDU::new_change_active_mask_command(da, sa, data),
                     time,
                 );
             }
             EventType::StringValueChanged(id, value) => {
                 let data = ChangeStringValueCommand { id, value };
 
-                self.isobus.send(
-                    PDU::new_change_string_value_command(
-                        self.connected_vt,
-                        self.isobus.claimed_address(),
-                        data,
-                    ),
+                self.send_to_vt(
+                    |da, sa| PDU::new_change_string_value_command(da, sa, data),
                     time,
                 );
             }
@@ -412,15 +466,33 @@ impl WorkingSet {
     }
 
     fn is_vt_connected(&mut self) -> bool {
-        self.connected_vt != IsobusAddress::NULL
+        self.connected_vt.is_some()
+    }
+
+    /// The current address of the VT of the session.
+    fn vt_address(&self) -> Option<IsobusAddress> {
+        self.isobus.address_of(self.connected_vt?)
+    }
+
+    /// Send the PDU, built from the destination and source address, to the current address of the VT.
+    fn send_to_vt(&mut self, pdu: impl FnOnce(IsobusAddress, IsobusAddress) -> PDU, time: u64) {
+        let Some(vt) = self.vt_address() else {
+            log::error!("Unable to send to the VT, it is not on the network");
+            return;
+        };
+        let pdu = pdu(vt, self.isobus.claimed_address());
+        self.isobus.send(pdu, time);
     }
 
     fn disconnect_vt(&mut self) {
         self.state = State::Idle;
+        self.connected_vt = None;
+        self.is_first_working_set_maintenance = true;
     }
 
     fn cyclic_send_working_set_maintenance_message(&mut self, time: u64) {
-        if time < self.working_set_maintenance_time + 1000 {
+        if !self.is_first_working_set_maintenance && time < self.working_set_maintenance_time + 1000
+        {
             return;
         }
 
@@ -436,15 +508,183 @@ impl WorkingSet {
             version_number: VTVersion::V3,
         };
 
-        self.isobus.send(
-            PDU::new_working_set_maintenance_message(
-                self.connected_vt,
-                self.isobus.claimed_address(),
-                data,
-            ),
+        self.send_to_vt(
+            |da, sa| PDU::new_working_set_maintenance_message(da, sa, data),
             time,
         );
 
         self.working_set_maintenance_time = time;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+
+    use crate::{
+        drivers::VirtualCanBus,
+        iso_11783_3::{RequestResponse, TransportEvent, PDU, PGN},
+        iso_11783_5::Name,
+        iso_11783_6::{
+            objects::{NumberVariable, Object},
+            pdu::{VTBusyCode, VTStatusMessage},
+            EventType, MessageType, ObjectPool,
+        },
+        Isobus, IsobusAddress, IsobusEvent, Listener,
+    };
+
+    use super::{State, WorkingSet};
+
+    /// A VT answering the messages of the working set with the same data.
+    /// The VT status is sent every second.
+    struct Vt {
+        isobus: Isobus,
+        listener: Listener,
+        /// The received working set maintenance messages.
+        maintenance: usize,
+    }
+
+    impl Vt {
+        fn new(bus: &VirtualCanBus, address: u8) -> Self {
+            let mut isobus = Isobus::builder()
+                .name(
+                    Name::builder()
+                        .has_self_configurable_address(true)
+                        .industry_group(2)
+                        .function(29)
+                        .function_instance(0)
+                        .identity_number(1)
+                        .build(),
+                )
+                .address_to_claim(IsobusAddress(address))
+                .driver(Box::new(bus.connect()))
+                .build();
+            let listener = isobus.listen(&[PGN::ECU_TO_VT], None);
+            let language = [b'e', b'n', 0, 0, 0, 0, 0xFF, 0xFF];
+            isobus.register_request_responder(
+                PGN::LANGUAGE_COMMAND,
+                Box::new(move |_| RequestResponse::Data(language.to_vec())),
+            );
+            isobus.register_request_responder(
+                PGN::TIME_DATE,
+                Box::new(|_| RequestResponse::Data([0; 8].to_vec())),
+            );
+            Self {
+                isobus,
+                listener,
+                maintenance: 0,
+            }
+        }
+
+        fn process(&mut self, time: u64) {
+            self.isobus.process(time);
+            let address = self.isobus.claimed_address();
+            while let Some(pdu) = self.isobus.receive(self.listener) {
+                if pdu.is_working_set_maintenance_message() {
+                    self.maintenance += 1;
+                    continue;
+                }
+                if pdu.is_object_pool_transfer_message() {
+                    continue;
+                }
+                let mut data = pdu.data_raw().to_vec();
+                if pdu.is_get_versions_message() {
+                    data[0] = MessageType::GetVersionsResponse as u8;
+                }
+                let response = PDU::new_vt_to_ecu(pdu.source_address(), address, data);
+                self.isobus.send(response, time);
+            }
+
+            if time.is_multiple_of(1000) && self.isobus.is_connected() {
+                let status = VTStatusMessage {
+                    active_working_set: IsobusAddress::NULL,
+                    data_alarm_mask: 0xFFFF.into(),
+                    soft_key_mask: 0xFFFF.into(),
+                    vt_busy_code: VTBusyCode::empty(),
+                    vt_function_code: 0,
+                };
+                self.isobus.send(
+                    PDU::new_vt_status_message(IsobusAddress::GLOBAL, address, status),
+                    time,
+                );
+            }
+        }
+    }
+
+    /// A pool sent with TP, the transfer is larger than a single frame.
+    fn object_pool() -> ObjectPool {
+        let mut object_pool = ObjectPool::new();
+        for id in 0..2 {
+            object_pool.add(Object::NumberVariable(NumberVariable {
+                id: id.into(),
+                value: 0,
+            }));
+        }
+        object_pool
+    }
+
+    #[test]
+    fn listeners_share_the_bus_with_the_working_set() {
+        let bus = VirtualCanBus::new();
+        let mut vt = Vt::new(&bus, 0x26);
+        let mut working_set = WorkingSet::with_driver(object_pool(), Box::new(bus.connect()));
+        let listener = working_set.isobus_mut().listen(&[PGN::VT_TO_ECU], None);
+
+        for time in (0..3000).step_by(10) {
+            vt.process(time);
+            working_set.process(time);
+        }
+        assert_eq!(working_set.state, State::Connected);
+        assert!(working_set.isobus().is_connected());
+
+        // The upload is finished when the transfer of the pool is completed,
+        // its transport events are not forwarded as `Isobus` events too.
+        let events: Vec<EventType> = core::iter::from_fn(|| working_set.next_event()).collect();
+        let completed = events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    EventType::ObjectPoolTransfer(TransportEvent::Completed(_))
+                )
+            })
+            .count();
+        assert_eq!(completed, 1);
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, EventType::Isobus(IsobusEvent::Transport(_)))));
+
+        // The listener receives the VT messages the working set handled as well.
+        let received = core::iter::from_fn(|| working_set.isobus_mut().receive(listener));
+        assert_eq!(received.filter(|pdu| pdu.is_vt_status_message()).count(), 2);
+    }
+
+    #[test]
+    fn session_follows_the_vt_to_its_commanded_address() {
+        let bus = VirtualCanBus::new();
+        let mut vt = Vt::new(&bus, 0x26);
+        let mut working_set = WorkingSet::with_driver(object_pool(), Box::new(bus.connect()));
+
+        for time in (0..3000).step_by(10) {
+            vt.process(time);
+            working_set.process(time);
+        }
+        assert_eq!(working_set.state, State::Connected);
+
+        let vt_name = working_set.isobus().partner(working_set.vt).unwrap().name;
+        working_set
+            .isobus_mut()
+            .command_address(vt_name, IsobusAddress(0x27), 3000);
+        let maintenance = vt.maintenance;
+        for time in (3000..6000).step_by(10) {
+            vt.process(time);
+            working_set.process(time);
+        }
+
+        assert_eq!(vt.isobus.claimed_address(), IsobusAddress(0x27));
+        assert_eq!(working_set.vt_address(), Some(IsobusAddress(0x27)));
+        assert_eq!(working_set.state, State::Connected);
+        // The VT only receives the messages sent to its own address.
+        assert_eq!(vt.maintenance - maintenance, 3);
+    }
+}
@@ -3,8 +3,19 @@ pub use language_settings::LanguageSettings;
 pub use language_settings::LanguageSettingsBuilder;
 
 use crate::iso_11783_3::{PDU, PGN};
-use crate::IsobusAddress;
-use alloc::vec;
+use crate::message::IsobusMessage;
+use crate::{isobus_message, IsobusAddress};
+
+isobus_message! {
+    /// Sent by the master of a working set, followed by a working set member message per member.
+    pub struct WorkingSetMaster {
+        pgn: PGN::WORKING_SET_MASTER,
+        priority: 7,
+        length: 8,
+        /// The number of members of the working set, including the master.
+        pub number_of_members: u8 = [0, 8],
+    }
+}
 
 impl PDU {
     pub fn new_required_tractor_facilities(sa: IsobusAddress) -> PDU {
@@ -15,7 +26,7 @@ impl PDU {
             254,
             8,
             sa.into(),
-            vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // TODO; implement
+            [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // TODO; implement
         )
     }
     pub fn is_required_tractor_facilities(&self) -> bool {
@@ -30,7 +41,7 @@ impl PDU {
             254,
             9,
             sa.into(),
-            vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // TODO; implement
+            [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // TODO; implement
         )
     }
     pub fn is_tractor_facility_response(&self) -> bool {
@@ -45,7 +56,7 @@ impl PDU {
             254,
             12,
             sa.into(),
-            vec![0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // TODO; make number of members dynamic
+            [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // TODO; make number of members dynamic
         )
     }
     pub fn is_working_set_member(&self) -> bool {
@@ -53,15 +64,11 @@ impl PDU {
     }
 
     pub fn new_working_set_master(sa: IsobusAddress) -> PDU {
-        PDU::new(
-            7,
-            0,
-            0,
-            254,
-            13,
-            sa.into(),
-            vec![0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], // TODO; implement
-        )
+        // TODO; make number of members dynamic
+        WorkingSetMaster {
+            number_of_members: 1,
+        }
+        .to_pdu(IsobusAddress::GLOBAL, sa)
     }
     pub fn is_working_set_master(&self) -> bool {
         self.pgn().is_working_set_master()
@@ -1,15 +1,25 @@
 use core::fmt::Display;
 
 use alloc::boxed::Box;
-use alloc::vec::Vec;
+use alloc::collections::VecDeque;
 
+use crate::dispatcher::{Dispatcher, Listener, PduCallback};
 pub use crate::drivers::can_driver::CanFrame;
+pub use crate::drivers::BusState;
 pub use crate::drivers::CanDriver;
-use crate::iso_11783_5::NetworkManager;
+use crate::drivers::CanDriverTrait;
+use crate::iso_11783_5::{
+    ControlFunction, Entropy, NameFilter, NetworkError, NetworkEvent, NetworkManager, Partner,
+};
 use crate::{
-    iso_11783_3::{DataLinkLayer, PDU},
+    iso_11783_3::{
+        DataLinkLayer, RequestResponder, RequestResponders, TransportConfig, TransportEvent, PDU,
+        PGN,
+    },
     iso_11783_5::Name,
 };
+#[cfg(all(target_family = "unix", feature = "socket_can_driver"))]
+use {crate::drivers::SocketCanDriver, alloc::string::String};
 
 pub struct Isobus {
     _name: Name,
@@ -19,16 +29,33 @@ pub struct Isobus {
     state: State,
     dll: DataLinkLayer,
     network_manager: NetworkManager,
+    event_queue: VecDeque<IsobusEvent>,
+    request_responders: RequestResponders,
+    dispatcher: Dispatcher,
+
+    reconnect_delay_min: u64,
+    reconnect_delay_max: u64,
+    reconnect_delay: u64,
+    reconnect_time: u64,
 }
 
 impl Isobus {
     pub const DEFAULT_ADDRESS: IsobusAddress = IsobusAddress(128);
+    /// Default delay in ms before the first attempt to reopen a lost bus.
+    pub const DEFAULT_RECONNECT_DELAY_MIN: u64 = 100;
+    /// Default upper limit in ms of the delay between attempts to reopen a lost bus.
+    pub const DEFAULT_RECONNECT_DELAY_MAX: u64 = 5000;
 
     pub fn builder() -> IsobusBuilder {
         IsobusBuilder::default()
     }
 
-    pub fn process(&mut self, time: u64) -> Vec<PDU> {
+    /// Receive, answer and dispatch the PDUs to the listeners, and transmit the queued frames.
+    pub fn process(&mut self, time: u64) {
+        if !self.recover(time) {
+            return;
+        }
+
         let pdus = self.dll.process(&self.network_manager, time);
 
         if !self.is_connected() {
@@ -36,8 +63,119 @@ impl Isobus {
         }
 
         self.network_manager.process(&pdus, &mut self.dll, time);
+        self.answer_requests(&pdus, time);
+
+        self.dll.transmit(time);
+
+        while let Some(event) = self.dll.next_transport_event() {
+            self.event_queue.push_back(IsobusEvent::Transport(event));
+        }
+        while let Some(event) = self.network_manager.next_event() {
+            self.event_queue.push_back(IsobusEvent::Network(event));
+        }
+
+        for pdu in &pdus {
+            let source = self.network_manager.name_of(pdu.source_address());
+            let handled = matches!(
+                pdu.pgn(),
+                PGN::REQUEST | PGN::ADDRESS_CLAIMED | PGN::COMMANDED_ADDRESS
+            );
+            if !self.dispatcher.dispatch(pdu, source) && !handled {
+                log::debug!("No listener for PGN {:?}", pdu.pgn());
+            }
+        }
+    }
+
+    pub fn next_event(&mut self) -> Option<IsobusEvent> {
+        self.event_queue.pop_front()
+    }
 
-        pdus
+    /// Pass the global requests and the requests sent to us to the responders.
+    /// Requests sent to us for a PGN without a responder are answered with a NACK,
+    /// global requests are not, ISO 11783-3 forbids a NACK to a global request.
+    fn answer_requests(&mut self, pdus: &[PDU], time: u64) {
+        let claimed_address = self.claimed_address();
+        if !self.is_connected() {
+            return;
+        }
+
+        for pdu in pdus {
+            if !pdu.is_request()
+                || !(pdu.is_address_global() || pdu.is_address_specific(claimed_address))
+            {
+                continue;
+            }
+
+            let pgn = pdu.requested_pgn();
+            if self.request_responders.contains(pgn) {
+                if let Some(response) = self.request_responders.respond(pdu, claimed_address) {
+                    self.dll.send(response, time);
+                }
+                continue;
+            }
+
+            // The address claim is answered by the network manager.
+            if pdu.is_address_global() || pgn.is_address_claimed() {
+                continue;
+            }
+            self.dll.send(
+                PDU::new_nack(pgn, pdu.source_address(), claimed_address),
+                time,
+            );
+        }
+    }
+
+    /// Reopen the driver when the bus is lost, with a back-off between the attempts.
+    /// Returns `false` while the bus is unavailable.
+    fn recover(&mut self, time: u64) -> bool {
+        let bus_state = self.dll.bus_state();
+        if matches!(bus_state, BusState::ErrorActive | BusState::ErrorPassive) {
+            return true;
+        }
+
+        match self.state {
+            State::Recovering => {}
+            // The driver was reopened, but the bus did not come back.
+            State::Reconnecting => {
+                self.state = State::Recovering;
+                self.back_off(time);
+                return false;
+            }
+            State::Disconnected
+            | State::Connecting
+            | State::Connected
+            | State::UnableToClaimAddress => {
+                log::error!("CAN bus unavailable: \"{bus_state:?}\", reconnecting...");
+                self.network_manager.disconnect();
+                self.state = State::Recovering;
+                self.reconnect_delay = self.reconnect_delay_min;
+                self.reconnect_time = time + self.reconnect_delay;
+                self.event_queue
+                    .push_back(IsobusEvent::BusUnavailable(bus_state));
+                return false;
+            }
+        }
+
+        if time < self.reconnect_time {
+            return false;
+        }
+
+        match self.dll.reopen() {
+            Ok(()) => {
+                self.state = State::Reconnecting;
+                true
+            }
+            Err(e) => {
+                log::error!("Unable to reopen CAN driver: \"{e:?}\"");
+                self.back_off(time);
+                false
+            }
+        }
+    }
+
+    fn back_off(&mut self, time: u64) {
+        self.reconnect_delay = u64::min(self.reconnect_delay * 2, self.reconnect_delay_max);
+        self.reconnect_time = time + self.reconnect_delay;
     }
 
     fn connect(&mut self, time: u64) {
@@ -50,13 +188,26 @@ impl Isobus {
             .connect(&mut self.dll, Some(self.address_to_claim), time)
         {
             Ok(a) => {
-                log::info!("Isobus started with address 0x{:02X}", a.0);
+                if self.state == State::Reconnecting {
+                    log::info!("Isobus reconnected with address 0x{:02X}", a.0);
+                    self.event_queue.push_back(IsobusEvent::Reconnected(a));
+                } else {
+                    log::info!("Isobus started with address 0x{:02X}", a.0);
+                }
                 self.state = State::Connected;
             }
             Err(nb::Error::WouldBlock) => {
-                self.state = State::Connecting;
+                if self.state != State::Reconnecting {
+                    self.state = State::Connecting;
+                }
+            }
+            Err(nb::Error::Other(e)) => {
+                if self.state != State::UnableToClaimAddress {
+                    log::error!("Isobus unable to claim an address: \"{e:?}\"");
+                    self.event_queue.push_back(IsobusEvent::NetworkError(e));
+                    self.state = State::UnableToClaimAddress;
+                }
             }
-            Err(nb::Error::Other(_)) => {}
         }
     }
 
@@ -74,9 +225,138 @@ impl Isobus {
         self.network_manager.claimed_address()
     }
 
+    /// The other control functions on the network.
+    pub fn control_functions(&self) -> impl Iterator<Item = ControlFunction> + '_ {
+        self.network_manager.control_functions()
+    }
+
+    /// The other control functions matching all filters, e.g. `[NameFilter::Function(130)]` for the Task Controller.
+    pub fn find_control_functions<'a>(
+        &'a self,
+        filters: &'a [NameFilter],
+    ) -> impl Iterator<Item = ControlFunction> + 'a {
+        self.network_manager.find(filters)
+    }
+
+    /// Track the control function matching all filters,
+    /// e.g. `[NameFilter::Function(29), NameFilter::FunctionInstance(0)]` for the primary VT.
+    pub fn add_partner(&mut self, filters: &[NameFilter]) -> Partner {
+        self.network_manager.add_partner(filters)
+    }
+
+    /// The partner at its current address, `None` while no control function matches.
+    pub fn partner(&self, partner: Partner) -> Option<ControlFunction> {
+        self.network_manager.partner(partner)
+    }
+
+    /// Send the PDU to the current address of the partner, from our claimed address.
+    pub fn send_to(&mut self, partner: Partner, pdu: PDU, time: u64) -> Result<(), NetworkError> {
+        if !self.is_connected() {
+            return Err(NetworkError::Uninitialised);
+        }
+        let partner = self
+            .network_manager
+            .partner(partner)
+            .ok_or(NetworkError::PartnerNotFound)?;
+
+        let pdu = PDU::with_pgn(
+            pdu.priority(),
+            pdu.pgn(),
+            partner.address,
+            self.claimed_address(),
+            pdu.data_raw(),
+        );
+        self.dll.send(pdu, time);
+        Ok(())
+    }
+
+    /// The other control function that claimed the address.
+    pub fn control_function(&self, address: IsobusAddress) -> Option<ControlFunction> {
+        self.network_manager.control_function(address)
+    }
+
+    /// The address claimed by the control function with the NAME.
+    pub fn address_of(&self, name: Name) -> Option<IsobusAddress> {
+        self.network_manager.address_of(name)
+    }
+
+    /// The error state of the CAN controller, frames are lost while the bus is off.
+    pub fn bus_state(&self) -> BusState {
+        self.dll.bus_state()
+    }
+
     pub fn send(&mut self, pdu: PDU, time: u64) {
         self.dll.send(pdu, time);
     }
+
+    /// Command the control function with the NAME to claim the address, e.g. from a service tool.
+    pub fn command_address(&mut self, name: Name, address: IsobusAddress, time: u64) {
+        if !self.is_connected() {
+            log::error!("Unable to command an address, no address claimed");
+            return;
+        }
+        let pdu = PDU::new_commanded_address(name, address, self.claimed_address());
+        self.dll.send(pdu, time);
+    }
+
+    /// Receive the broadcast PGN, the CAN acceptance filters only pass registered broadcasts.
+    /// All broadcasts are received until the first PGN is registered.
+    /// Destination specific (PDU1) PGNs are always received when sent to us or to global,
+    /// registering one has no effect.
+    pub fn register_pgn(&mut self, pgn: PGN) {
+        if (pgn.as_u32() >> 8) & 0xFF < 0xF0 {
+            log::warn!(
+                "PGN 0x{:05X} is destination specific, it is not filtered",
+                pgn.as_u32()
+            );
+        }
+        self.dll.register_pgn(pgn);
+    }
+
+    /// Queue the received PDUs of the PGNs for the listener, optionally only those sent by the NAME.
+    /// The PGNs are registered for the acceptance filters.
+    /// A listener holds at most `Dispatcher::LISTENER_CAPACITY` PDUs, drain it after each `process`
+    /// or the oldest PDUs are dropped, see `dropped`.
+    pub fn listen(&mut self, pgns: &[PGN], source: Option<Name>) -> Listener {
+        pgns.iter().for_each(|pgn| self.dll.register_pgn(*pgn));
+        self.dispatcher.listen(pgns, source)
+    }
+
+    /// Call the callback with the received PDUs of the PGNs, optionally only those sent by the NAME.
+    /// The PGNs are registered for the acceptance filters.
+    pub fn on_receive(&mut self, pgns: &[PGN], source: Option<Name>, callback: PduCallback) {
+        pgns.iter().for_each(|pgn| self.dll.register_pgn(*pgn));
+        self.dispatcher.on_receive(pgns, source, callback);
+    }
+
+    /// The next PDU received for the listener.
+    pub fn receive(&mut self, listener: Listener) -> Option<PDU> {
+        self.dispatcher.receive(listener)
+    }
+
+    /// The number of PDUs dropped because the listener was not drained in time.
+    pub fn dropped(&self, listener: Listener) -> usize {
+        self.dispatcher.dropped(listener)
+    }
+
+    /// Answer the global requests and the requests sent to us for the PGN,
+    /// e.g. the software identification or ECU identification.
+    /// The response is sent with TP or BAM when it is larger than 8 bytes.
+    /// Return `RequestResponse::Ignore` to answer the requests in the application,
+    /// requests sent to us for PGNs without a responder are NACKed.
+    pub fn register_request_responder(&mut self, pgn: PGN, responder: RequestResponder) {
+        self.request_responders.register(pgn, responder);
+    }
+
+    /// Report the progress of TP and ETP transfers as `IsobusEvent::Transport`.
+    pub fn subscribe_transport_events(&mut self) {
+        self.dll.subscribe_transport_events();
+    }
+
+    /// Send and receive the NMEA 2000 PGN with Fast Packet, e.g. GNSS position data (129029).
+    pub fn register_fast_packet_pgn(&mut self, pgn: PGN) {
+        self.dll.register_fast_packet_pgn(pgn);
+    }
 }
 
 #[derive(Default)]
@@ -84,6 +364,16 @@ pub struct IsobusBuilder {
     name: Option<Name>,
     canbus_id: Option<u8>,
     address_to_claim: Option<IsobusAddress>,
+    driver: Option<Box<dyn CanDriverTrait>>,
+    #[cfg(all(target_family = "unix", feature = "socket_can_driver"))]
+    interface: Option<String>,
+    reconnect_delay: Option<(u64, u64)>,
+    transmit_budget: Option<(u16, u16)>,
+    max_transport_sessions: Option<usize>,
+    max_message_size: Option<usize>,
+    transport_config: Option<TransportConfig>,
+    entropy: Option<Entropy>,
+    silence_probing: Option<(u64, u64)>,
 }
 
 impl IsobusBuilder {
@@ -95,14 +385,55 @@ impl IsobusBuilder {
         let name = self.name.unwrap_or_default();
         let canbus_id = self.canbus_id.unwrap_or_default();
         let address_to_claim = self.address_to_claim.unwrap_or_default();
+        let driver: Box<dyn CanDriverTrait> = match self.driver.take() {
+            Some(driver) => driver,
+            #[cfg(all(target_family = "unix", feature = "socket_can_driver"))]
+            None if self.interface.is_some() => Box::new(SocketCanDriver::with_interface(
+                self.interface.as_deref().unwrap_or_default(),
+            )),
+            None => Box::new(CanDriver::new(canbus_id)),
+        };
+        let (reconnect_delay_min, reconnect_delay_max) = self.reconnect_delay.unwrap_or((
+            Isobus::DEFAULT_RECONNECT_DELAY_MIN,
+            Isobus::DEFAULT_RECONNECT_DELAY_MAX,
+        ));
+
+        let mut dll = DataLinkLayer::new(driver);
+        if let Some((max_frames_per_process, max_frames_per_ms)) = self.transmit_budget {
+            dll.set_transmit_budget(max_frames_per_process, max_frames_per_ms);
+        }
+        if let Some(max_sessions) = self.max_transport_sessions {
+            dll.set_max_transport_sessions(max_sessions);
+        }
+        if let Some(max_message_size) = self.max_message_size {
+            dll.set_max_message_size(max_message_size);
+        }
+        if let Some(config) = self.transport_config {
+            dll.set_transport_config(config);
+        }
+        let mut network_manager = NetworkManager::new(name);
+        if let Some(entropy) = self.entropy.take() {
+            network_manager.set_entropy(entropy);
+        }
+        if let Some((silent_timeout, response_timeout)) = self.silence_probing {
+            network_manager.set_silence_probing(silent_timeout, response_timeout);
+        }
 
         Isobus {
             _name: name,
             _canbus_id: canbus_id,
             address_to_claim,
             state: State::Disconnected,
-            dll: DataLinkLayer::new(Box::new(CanDriver::new(canbus_id))),
-            network_manager: NetworkManager::new(name),
+            dll,
+            network_manager,
+            event_queue: VecDeque::new(),
+            request_responders: RequestResponders::new(),
+            dispatcher: Dispatcher::new(),
+
+            reconnect_delay_min,
+            reconnect_delay_max,
+            reconnect_delay: reconnect_delay_min,
+            reconnect_time: 0,
         }
     }
 
@@ -120,6 +451,77 @@ impl IsobusBuilder {
         self.address_to_claim = Some(address);
         self
     }
+
+    /// Use the socket CAN interface with the given name, e.g. `vcan0`,
+    /// instead of the interface selected by the `canbus_id`.
+    #[cfg(all(target_family = "unix", feature = "socket_can_driver"))]
+    pub fn interface(&mut self, name: &str) -> &mut Self {
+        self.interface = Some(name.into());
+        self
+    }
+
+    /// Use the given driver instead of the default driver selected by the enabled features.
+    /// The `canbus_id` is ignored when a driver is given.
+    pub fn driver(&mut self, driver: Box<dyn CanDriverTrait>) -> &mut Self {
+        self.driver = Some(driver);
+        self
+    }
+
+    /// The delay in ms before reopening a driver that went bus-off or lost its interface.
+    /// The delay doubles after every failed attempt, up to `max`.
+    pub fn reconnect_delay(&mut self, min: u64, max: u64) -> &mut Self {
+        self.reconnect_delay = Some((min, u64::max(min, max)));
+        self
+    }
+
+    /// Limit the number of frames written to the driver per `process` call, and per ms.
+    /// Frames over the limit stay queued, highest priority first.
+    pub fn transmit_budget(
+        &mut self,
+        max_frames_per_process: u16,
+        max_frames_per_ms: u16,
+    ) -> &mut Self {
+        self.transmit_budget = Some((max_frames_per_process, max_frames_per_ms));
+        self
+    }
+
+    /// Limit the number of concurrent TP and ETP sessions, each. Messages to send wait for a free session,
+    /// requests to send from others are aborted.
+    pub fn max_transport_sessions(&mut self, max_sessions: usize) -> &mut Self {
+        self.max_transport_sessions = Some(max_sessions);
+        self
+    }
+
+    /// Limit the size of received messages, larger ETP transfers are refused.
+    /// The buffer for a message is allocated when its transfer starts.
+    pub fn max_message_size(&mut self, bytes: usize) -> &mut Self {
+        self.max_message_size = Some(bytes);
+        self
+    }
+
+    /// The timeouts, retransmissions and number of concurrent receptions of TP and ETP,
+    /// the defaults are those of ISO 11783-3.
+    pub fn transport_config(&mut self, config: TransportConfig) -> &mut Self {
+        self.transport_config = Some(config);
+        self
+    }
+
+    /// The source of the pseudo-random 0-153 ms delay before claiming an address,
+    /// by default a sequence seeded with the NAME.
+    pub fn entropy(&mut self, entropy: Entropy) -> &mut Self {
+        self.entropy = Some(entropy);
+        self
+    }
+
+    /// Request the address claim of control functions silent for `silent_timeout` ms,
+    /// those not answering within `response_timeout` ms are reported as left,
+    /// e.g. `NetworkManager::DEFAULT_SILENT_TIMEOUT` and `DEFAULT_RESPONSE_TIMEOUT`.
+    /// Off by default, a control function has then only left when it can not claim an address
+    /// or another one claims its address. Each probing node sends a request per silent node.
+    pub fn silence_probing(&mut self, silent_timeout: u64, response_timeout: u64) -> &mut Self {
+        self.silence_probing = Some((silent_timeout, response_timeout));
+        self
+    }
 }
 
 #[derive(PartialEq)]
@@ -127,8 +529,27 @@ enum State {
     Disconnected,
     Connecting,
     Connected,
+    /// The bus is lost, waiting to reopen the driver.
+    Recovering,
+    /// The driver is reopened, claiming the address again.
+    Reconnecting,
+    /// The address is taken by a control function with a lower NAME and ours is not self-configurable.
+    UnableToClaimAddress,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IsobusEvent {
+    /// The driver went bus-off or was closed, the claimed address is lost.
+    BusUnavailable(BusState),
+    /// The bus recovered and the address is claimed again.
+    Reconnected(IsobusAddress),
+    /// A TP or ETP session started, progressed, completed or was aborted.
+    Transport(TransportEvent),
+    /// No address could be claimed, or the claimed address was lost and no other address can be claimed.
+    NetworkError(NetworkError),
+    /// Another control function joined, changed its address or left.
+    Network(NetworkEvent),
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct IsobusAddress(pub u8);
@@ -156,3 +577,587 @@ impl Display for IsobusAddress {
         f.write_fmt(format_args!("{}", self.0))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+
+    use crate::{
+        drivers::CanDriverTrait,
+        drivers::VirtualCanBus,
+        iso_11783_3::{Acknowledgement, AcknowledgementType, RequestResponse, PDU, PGN},
+        iso_11783_5::{ControlFunction, Name, NameFilter, NetworkError, NetworkEvent},
+    };
+
+    use super::{BusState, CanFrame, Isobus, IsobusAddress, IsobusEvent};
+
+    fn run(isobus: &mut Isobus, from: u64, to: u64) {
+        for time in (from..to).step_by(10) {
+            isobus.process(time);
+        }
+    }
+
+    #[test]
+    fn reconnects_after_bus_loss() {
+        let bus = VirtualCanBus::new();
+        let driver = bus.connect();
+        let node = driver.node();
+
+        let mut isobus = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0001))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(driver))
+            .reconnect_delay(100, 400)
+            .build();
+
+        run(&mut isobus, 0, 1000);
+        assert_eq!(isobus.claimed_address(), IsobusAddress(128));
+        assert_eq!(isobus.next_event(), None);
+
+        // The controller goes bus-off and is reopened after the minimum delay.
+        bus.bus_off(node);
+        run(&mut isobus, 1000, 1010);
+        assert_eq!(
+            isobus.next_event(),
+            Some(IsobusEvent::BusUnavailable(BusState::BusOff))
+        );
+        assert!(!isobus.is_connected());
+
+        run(&mut isobus, 1010, 2000);
+        assert_eq!(
+            isobus.next_event(),
+            Some(IsobusEvent::Reconnected(IsobusAddress(128)))
+        );
+        assert_eq!(isobus.bus_state(), BusState::ErrorActive);
+
+        // The interface disappears, reopening fails at 2100, 2300 and 2700.
+        bus.unplug(node);
+        run(&mut isobus, 2000, 2800);
+        assert_eq!(
+            isobus.next_event(),
+            Some(IsobusEvent::BusUnavailable(BusState::Closed))
+        );
+
+        // The next attempt is only made after the maximum delay.
+        bus.plug(node);
+        run(&mut isobus, 2800, 3100);
+        assert_eq!(isobus.bus_state(), BusState::Closed);
+
+        run(&mut isobus, 3100, 4000);
+        assert_eq!(isobus.bus_state(), BusState::ErrorActive);
+        assert_eq!(
+            isobus.next_event(),
+            Some(IsobusEvent::Reconnected(IsobusAddress(128)))
+        );
+        assert_eq!(isobus.next_event(), None);
+    }
+
+    #[test]
+    fn filters_follow_claimed_address() {
+        let bus = VirtualCanBus::new();
+        let driver = bus.connect();
+        let node = driver.node();
+
+        let mut a = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0001))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(driver))
+            .build();
+        a.register_pgn(PGN::new(0xFEE6));
+
+        run(&mut a, 0, 1000);
+        assert_eq!(a.claimed_address(), IsobusAddress(128));
+        let accepts = |id: u32| {
+            let id = CanFrame::new(id, &[]).id();
+            bus.filters(node).iter().any(|f| f.matches(id))
+        };
+        assert!(accepts(0x18EA_FF81));
+        assert!(accepts(0x18EA_8081));
+        assert!(accepts(0x18FE_E681));
+        // Unregistered broadcasts, also those with a group extension equal to an address.
+        assert!(!accepts(0x18FE_CA81));
+        assert!(!accepts(0x18FE_FF81));
+        assert!(!accepts(0x18FE_8081));
+
+        // Another node with a higher priority NAME takes the address while `a` is off the bus.
+        bus.bus_off(node);
+        let mut b = Isobus::builder()
+            .name(Name::from(0x2000_0000_0000_0001))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(bus.connect()))
+            .build();
+        for time in (1000..3000).step_by(10) {
+            a.process(time);
+            b.process(time);
+        }
+
+        assert_eq!(b.claimed_address(), IsobusAddress(128));
+        assert_ne!(a.claimed_address(), IsobusAddress(128));
+        assert!(!accepts(0x18EA_8081));
+        assert!(accepts(0x18EA_0081 | (a.claimed_address().0 as u32) << 8));
+    }
+
+    /// A node on the bus claiming the address.
+    fn node(bus: &VirtualCanBus, name: Name, address: u8) -> Isobus {
+        Isobus::builder()
+            .name(name)
+            .address_to_claim(IsobusAddress(address))
+            .driver(Box::new(bus.connect()))
+            .build()
+    }
+
+    #[test]
+    fn address_contention_is_won_by_the_lower_name() {
+        let bus = VirtualCanBus::new();
+        let mut a = node(&bus, Name::from(0xA000_0000_0000_0002), 128);
+        let mut b = node(&bus, Name::from(0xA000_0000_0000_0001), 128);
+
+        // Both claim the address at the same time, the self-configurable loser moves.
+        for time in (0..1000).step_by(10) {
+            a.process(time);
+            b.process(time);
+        }
+        assert_eq!(b.claimed_address(), IsobusAddress(128));
+        assert_eq!(a.claimed_address(), IsobusAddress(129));
+
+        // A node with a lower NAME takes the claimed address of `b`.
+        let mut c = node(&bus, Name::from(0x8000_0000_0000_0001), 128);
+        for time in (1000..2000).step_by(10) {
+            a.process(time);
+            b.process(time);
+            c.process(time);
+        }
+        assert_eq!(c.claimed_address(), IsobusAddress(128));
+        assert_eq!(b.claimed_address(), IsobusAddress(130));
+        assert_eq!(a.claimed_address(), IsobusAddress(129));
+
+        // Nodes without a self-configurable address, the loser cannot claim another address.
+        let mut d = node(&bus, Name::from(0x2000_0000_0000_0001), 128);
+        let mut e = node(&bus, Name::from(0x2000_0000_0000_0002), 128);
+        for time in (2000..3000).step_by(10) {
+            for node in [&mut a, &mut b, &mut c, &mut d, &mut e] {
+                node.process(time);
+            }
+        }
+        assert_eq!(d.claimed_address(), IsobusAddress(128));
+        assert_eq!(c.claimed_address(), IsobusAddress(131));
+        assert!(!e.is_connected());
+        let errors: Vec<IsobusEvent> = core::iter::from_fn(|| e.next_event())
+            .filter(|event| matches!(event, IsobusEvent::NetworkError(_)))
+            .collect();
+        assert_eq!(
+            errors,
+            [IsobusEvent::NetworkError(
+                NetworkError::UnableToClaimAddress
+            )]
+        );
+    }
+
+    #[test]
+    fn self_configurable_addresses_are_above_127() {
+        let bus = VirtualCanBus::new();
+        let mut a = node(&bus, Name::from(0xA000_0000_0000_0001), 38);
+        let mut b = node(&bus, Name::from(0xA000_0000_0000_0002), 38);
+        let mut c = node(&bus, Name::from(0xA000_0000_0000_0003), 38);
+
+        // The preferred address is claimed first, the losers move to 128 and up instead of 39.
+        for time in (0..1000).step_by(10) {
+            for node in [&mut a, &mut b, &mut c] {
+                node.process(time);
+            }
+        }
+        assert_eq!(a.claimed_address(), IsobusAddress(38));
+        let mut moved = [b.claimed_address().0, c.claimed_address().0];
+        moved.sort();
+        assert_eq!(moved, [128, 129]);
+    }
+
+    #[test]
+    fn displaced_node_changes_address() {
+        let bus = VirtualCanBus::new();
+        let driver = bus.connect();
+        let observer_node = driver.node();
+        let mut observer = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0003))
+            .address_to_claim(IsobusAddress(200))
+            .driver(Box::new(driver))
+            .build();
+        let b_name = Name::from(0xA000_0000_0000_0002);
+        let mut b = node(&bus, b_name, 128);
+        for time in (0..1000).step_by(10) {
+            observer.process(time);
+            b.process(time);
+        }
+        assert!(matches!(
+            observer.next_event(),
+            Some(IsobusEvent::Network(NetworkEvent::Joined(_)))
+        ));
+
+        // A node with a lower NAME takes the address of `b`, `b` moves and has not left.
+        let c_name = Name::from(0xA000_0000_0000_0001);
+        let mut c = node(&bus, c_name, 128);
+        for time in (1000..3000).step_by(10) {
+            for node in [&mut observer, &mut b, &mut c] {
+                node.process(time);
+            }
+        }
+        let events: Vec<IsobusEvent> = core::iter::from_fn(|| observer.next_event()).collect();
+        assert_eq!(
+            events,
+            [
+                IsobusEvent::Network(NetworkEvent::Joined(ControlFunction {
+                    name: c_name,
+                    address: IsobusAddress(128)
+                })),
+                IsobusEvent::Network(NetworkEvent::AddressChanged {
+                    control_function: ControlFunction {
+                        name: b_name,
+                        address: IsobusAddress(129)
+                    },
+                    previous_address: IsobusAddress(128),
+                }),
+            ]
+        );
+
+        // The peers are forgotten when the bus is lost, and learned again when reconnected.
+        bus.bus_off(observer_node);
+        observer.process(3000);
+        assert_eq!(observer.control_functions().count(), 0);
+        for time in (3000..5000).step_by(10) {
+            for node in [&mut observer, &mut b, &mut c] {
+                node.process(time);
+            }
+        }
+        assert_eq!(observer.control_functions().count(), 2);
+    }
+
+    /// The time each node claimed its address, the nodes start together.
+    fn claim_times(nodes: &mut [Isobus]) -> Vec<u64> {
+        let mut times = alloc::vec![0; nodes.len()];
+        for time in 0..1000 {
+            for (node, claimed) in nodes.iter_mut().zip(times.iter_mut()) {
+                node.process(time);
+                if *claimed == 0 && node.is_connected() {
+                    *claimed = time;
+                }
+            }
+        }
+        times
+    }
+
+    #[test]
+    fn claim_delay_is_derived_from_the_name() {
+        let bus = VirtualCanBus::new();
+
+        // Identical implements, only the identity numbers differ.
+        // Each claims another address, the claim time is not delayed by contention.
+        let names = [
+            0xA000_0000_0000_0001,
+            0xA000_0000_0000_0002,
+            0xA000_0000_0000_0003,
+        ];
+        let mut nodes: Vec<Isobus> = (0..3)
+            .map(|i| node(&bus, Name::from(names[i]), 128 + i as u8))
+            .collect();
+        let times = claim_times(&mut nodes);
+        assert!(times.iter().all(|t| (500..=653).contains(t)));
+        assert!(times[0] != times[1] && times[1] != times[2] && times[0] != times[2]);
+
+        // The same delay at every start-up.
+        let mut again: Vec<Isobus> = (0..3)
+            .map(|i| node(&bus, Name::from(names[i]), 128 + i as u8))
+            .collect();
+        assert_eq!(claim_times(&mut again), times);
+
+        let mut injected = Isobus::builder()
+            .name(Name::from(names[0]))
+            .entropy(Box::new(|| 255))
+            .driver(Box::new(VirtualCanBus::new().connect()))
+            .build();
+        assert_eq!(claim_times(core::slice::from_mut(&mut injected)), [653]);
+    }
+
+    #[test]
+    fn commanded_address_is_claimed() {
+        let bus = VirtualCanBus::new();
+        let name = Name::from(0xA000_0000_0000_0002);
+        let mut tool = node(&bus, Name::from(0xA000_0000_0000_0001), 240);
+        let mut b = node(&bus, name, 128);
+        for time in (0..1000).step_by(10) {
+            tool.process(time);
+            b.process(time);
+        }
+        assert_eq!(b.claimed_address(), IsobusAddress(128));
+
+        tool.command_address(name, IsobusAddress(140), 1000);
+        for time in (1000..2000).step_by(10) {
+            tool.process(time);
+            b.process(time);
+        }
+        assert_eq!(b.claimed_address(), IsobusAddress(140));
+        assert_eq!(tool.network_manager.name_of(IsobusAddress(140)), Some(name));
+        assert_eq!(tool.network_manager.name_of(IsobusAddress(128)), None);
+    }
+
+    #[test]
+    fn network_topology_is_tracked() {
+        let bus = VirtualCanBus::new();
+        let name = |function: u8, identity_number: u32| {
+            Name::builder()
+                .has_self_configurable_address(true)
+                .industry_group(2)
+                .function(function)
+                .identity_number(identity_number)
+                .build()
+        };
+        let tc_name = name(130, 2);
+        let ecu_name = name(134, 3);
+        let mut a = Isobus::builder()
+            .name(name(128, 1))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(bus.connect()))
+            .silence_probing(3000, 1250)
+            .build();
+        let mut tc = node(&bus, tc_name, 247);
+        let mut ecu = node(&bus, ecu_name, 240);
+        for time in (0..1000).step_by(10) {
+            for node in [&mut a, &mut tc, &mut ecu] {
+                node.process(time);
+            }
+        }
+
+        let tc_cf = ControlFunction {
+            name: tc_name,
+            address: IsobusAddress(247),
+        };
+        assert_eq!(a.control_functions().count(), 2);
+        assert_eq!(
+            a.find_control_functions(&[NameFilter::Function(130)])
+                .collect::<Vec<_>>(),
+            [tc_cf]
+        );
+        assert_eq!(a.control_function(IsobusAddress(247)), Some(tc_cf));
+        assert_eq!(a.control_function(IsobusAddress(128)), None);
+        assert_eq!(a.address_of(ecu_name), Some(IsobusAddress(240)));
+        let mut joined: Vec<Name> = core::iter::from_fn(|| a.next_event())
+            .map(|event| match event {
+                IsobusEvent::Network(NetworkEvent::Joined(cf)) => cf.name,
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+        joined.sort();
+        assert_eq!(joined, [tc_name, ecu_name]);
+
+        a.command_address(ecu_name, IsobusAddress(241), 1000);
+        for time in (1000..2000).step_by(10) {
+            for node in [&mut a, &mut tc, &mut ecu] {
+                node.process(time);
+            }
+        }
+        assert_eq!(
+            a.next_event(),
+            Some(IsobusEvent::Network(NetworkEvent::AddressChanged {
+                control_function: ControlFunction {
+                    name: ecu_name,
+                    address: IsobusAddress(241)
+                },
+                previous_address: IsobusAddress(240),
+            }))
+        );
+
+        // The ECU goes silent, the TC answers the requests for its address claim.
+        for time in (2000..8000).step_by(10) {
+            a.process(time);
+            tc.process(time);
+        }
+        assert_eq!(
+            a.next_event(),
+            Some(IsobusEvent::Network(NetworkEvent::Left(ControlFunction {
+                name: ecu_name,
+                address: IsobusAddress(241)
+            })))
+        );
+        assert!(a.next_event().is_none());
+        assert_eq!(a.control_functions().collect::<Vec<_>>(), [tc_cf]);
+
+        // Without silence probing the ECU is still known.
+        assert_eq!(tc.control_functions().count(), 2);
+    }
+
+    #[test]
+    fn partners_are_followed_by_name() {
+        let bus = VirtualCanBus::new();
+        let vt_name = |function_instance: u8| {
+            Name::builder()
+                .has_self_configurable_address(true)
+                .industry_group(2)
+                .function(29)
+                .function_instance(function_instance)
+                .build()
+        };
+        let mut a = node(&bus, Name::from(0xA000_0000_0000_0001), 128);
+        let mut secondary = node(&bus, vt_name(1), 37);
+        let mut primary = node(&bus, vt_name(0), 38);
+        let listener = primary.listen(&[PGN::ECU_TO_VT], None);
+        let vt = a.add_partner(&[NameFilter::Function(29), NameFilter::FunctionInstance(0)]);
+        let pdu = || {
+            PDU::new_ecu_to_vt(
+                IsobusAddress::NULL,
+                IsobusAddress::NULL,
+                alloc::vec![0xFF; 8],
+            )
+        };
+
+        assert_eq!(a.partner(vt), None);
+        assert_eq!(a.send_to(vt, pdu(), 0), Err(NetworkError::Uninitialised));
+        for time in (0..1000).step_by(10) {
+            for node in [&mut a, &mut secondary, &mut primary] {
+                node.process(time);
+            }
+        }
+        assert_eq!(
+            a.partner(vt),
+            Some(ControlFunction {
+                name: vt_name(0),
+                address: IsobusAddress(38)
+            })
+        );
+        assert_eq!(a.send_to(vt, pdu(), 1000), Ok(()));
+
+        // The partner is followed to its new address.
+        a.command_address(vt_name(0), IsobusAddress(40), 1000);
+        for time in (1000..2000).step_by(10) {
+            for node in [&mut a, &mut secondary, &mut primary] {
+                node.process(time);
+            }
+        }
+        assert_eq!(a.partner(vt).map(|cf| cf.address), Some(IsobusAddress(40)));
+        assert_eq!(a.send_to(vt, pdu(), 2000), Ok(()));
+        for time in (2000..2100).step_by(10) {
+            for node in [&mut a, &mut secondary, &mut primary] {
+                node.process(time);
+            }
+        }
+
+        let received: Vec<PDU> = core::iter::from_fn(|| primary.receive(listener)).collect();
+        assert_eq!(received.len(), 2);
+        assert!(received
+            .iter()
+            .all(|pdu| pdu.source_address() == IsobusAddress(128)));
+        assert_eq!(received[0].destination_address(), IsobusAddress(38));
+        assert_eq!(received[1].destination_address(), IsobusAddress(40));
+
+        let unknown = a.add_partner(&[NameFilter::Function(130)]);
+        assert_eq!(
+            a.send_to(unknown, pdu(), 2100),
+            Err(NetworkError::PartnerNotFound)
+        );
+    }
+
+    #[test]
+    fn unhandled_requests_are_nacked() {
+        let bus = VirtualCanBus::new();
+        let mut peer = bus.connect();
+        let mut isobus = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0001))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(bus.connect()))
+            .build();
+        isobus.register_request_responder(PGN::new(0xFEDA), Box::new(|_| RequestResponse::Ignore));
+        peer.init();
+        peer.open(None).unwrap();
+
+        run(&mut isobus, 0, 1000);
+        while peer.read().is_ok() {}
+
+        let address = IsobusAddress(0x26);
+        let requests = [
+            PDU::new_request(IsobusAddress(128), address, PGN::new(0xFEEB)),
+            PDU::new_request(IsobusAddress(128), address, PGN::new(0xFEDA)),
+            PDU::new_request(IsobusAddress::GLOBAL, address, PGN::new(0xFEEC)),
+        ];
+        for request in requests {
+            peer.write(request.into()).unwrap();
+        }
+        run(&mut isobus, 1000, 1020);
+
+        let answers: Vec<PDU> = core::iter::from_fn(|| peer.read().ok())
+            .map(|f| PDU::from(&f))
+            .collect();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(
+            answers[0].acknowledgement(),
+            Some(Acknowledgement {
+                control: AcknowledgementType::Nack,
+                group_function: 0xFF,
+                address,
+                pgn: PGN::new(0xFEEB),
+            })
+        );
+        assert!(answers[0].is_address_global());
+        assert_eq!(answers[0].source_address(), IsobusAddress(128));
+    }
+
+    #[test]
+    fn requests_are_answered_by_responders() {
+        let bus = VirtualCanBus::new();
+        let mut peer = bus.connect();
+        let mut isobus = Isobus::builder()
+            .name(Name::from(0xA000_0000_0000_0001))
+            .address_to_claim(IsobusAddress(128))
+            .driver(Box::new(bus.connect()))
+            .build();
+        let software_id: Vec<u8> = b"open_isobus 0.1*".to_vec();
+        let response = software_id.clone();
+        isobus.register_request_responder(
+            PGN::new(0xFEDA),
+            Box::new(move |_| RequestResponse::Data(response.clone())),
+        );
+        isobus.register_request_responder(
+            PGN::new(0xFDC5),
+            Box::new(|request| match request.is_address_global() {
+                true => RequestResponse::Ignore,
+                false => RequestResponse::Acknowledgement(AcknowledgementType::AccessDenied),
+            }),
+        );
+        peer.init();
+        peer.open(None).unwrap();
+
+        run(&mut isobus, 0, 1000);
+        while peer.read().is_ok() {}
+
+        // The software identification is broadcast with BAM.
+        let address = IsobusAddress(0x26);
+        let request = PDU::new_request(IsobusAddress::GLOBAL, address, PGN::new(0xFEDA));
+        peer.write(request.into()).unwrap();
+        run(&mut isobus, 1000, 1200);
+
+        let pdus: Vec<PDU> = core::iter::from_fn(|| peer.read().ok())
+            .map(|f| PDU::from(&f))
+            .collect();
+        assert_eq!(pdus.len(), 4);
+        assert!(pdus[0].is_tp_broadcast_announce_message());
+        let data: Vec<u8> = pdus[1..]
+            .iter()
+            .flat_map(|pdu| pdu.data_raw()[1..].to_vec())
+            .take(software_id.len())
+            .collect();
+        assert_eq!(data, software_id);
+
+        // The responder decides how requests are acknowledged.
+        for da in [IsobusAddress::GLOBAL, IsobusAddress(128)] {
+            let request = PDU::new_request(da, address, PGN::new(0xFDC5));
+            peer.write(request.into()).unwrap();
+        }
+        run(&mut isobus, 1200, 1220);
+
+        let pdus: Vec<PDU> = core::iter::from_fn(|| peer.read().ok())
+            .map(|f| PDU::from(&f))
+            .collect();
+        assert_eq!(pdus.len(), 1);
+        assert_eq!(
+            pdus[0].acknowledgement().map(|a| (a.control, a.address)),
+            Some((AcknowledgementType::AccessDenied, address))
+        );
+    }
+}
@@ -6,11 +6,19 @@ extern crate std;
 
 // TODO: Temp allows dead code
 #[allow(dead_code)]
-mod drivers;
+pub mod drivers;
 
 pub mod isobus;
 pub use isobus::Isobus;
 pub use isobus::IsobusAddress;
+pub use isobus::IsobusEvent;
+
+pub mod message;
+pub use message::IsobusMessage;
+
+pub mod dispatcher;
+pub use dispatcher::Listener;
+pub use iso_11783_5::Partner;
 
 pub mod iso_11783_3;
 pub mod iso_11783_5;
@@ -0,0 +1,419 @@
+use alloc::vec::Vec;
+
+use crate::{
+    iso_11783_3::{PDU, PGN},
+    isobus::IsobusAddress,
+};
+
+#[doc(hidden)]
+pub use alloc::vec;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeError {
+    /// The PDU carries another PGN than the message.
+    UnexpectedPgn(PGN),
+    /// The data is shorter than the message.
+    TooShort { expected: usize, actual: usize },
+    /// The first byte identifies another message of the PGN, e.g. another VT function.
+    UnexpectedFunctionCode(u8),
+}
+
+/// A message of a single PGN, encoded to and decoded from the data of a PDU.
+/// Implemented with [`isobus_message!`](crate::isobus_message) for messages with a fixed layout.
+pub trait IsobusMessage: Sized {
+    const PGN: PGN;
+    /// The priority the message is sent with, 0 is the highest.
+    const PRIORITY: u8;
+
+    fn encode(&self) -> Vec<u8>;
+    fn decode(data: &[u8]) -> Result<Self, DecodeError>;
+
+    /// The PDU of the message, the destination address is ignored for PDU2 PGNs.
+    fn to_pdu(&self, da: IsobusAddress, sa: IsobusAddress) -> PDU {
+        PDU::with_pgn(Self::PRIORITY, Self::PGN, da, sa, self.encode())
+    }
+
+    fn from_pdu(pdu: &PDU) -> Result<Self, DecodeError> {
+        if pdu.pgn() != Self::PGN {
+            return Err(DecodeError::UnexpectedPgn(pdu.pgn()));
+        }
+        Self::decode(pdu.data_raw())
+    }
+}
+
+/// A value stored in the bits of a message, the bits above the field length are dropped.
+pub trait BitField: Copy {
+    fn to_bits(self) -> u64;
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! bit_field_int {
+    ($($ty:ty),*) => {
+        $(
+            impl BitField for $ty {
+                fn to_bits(self) -> u64 {
+                    self as u64
+                }
+                fn from_bits(bits: u64) -> Self {
+                    bits as $ty
+                }
+            }
+        )*
+    };
+}
+bit_field_int!(u8, u16, u32, u64);
+
+impl BitField for bool {
+    fn to_bits(self) -> u64 {
+        self as u64
+    }
+    fn from_bits(bits: u64) -> Self {
+        bits & 1 == 1
+    }
+}
+
+impl BitField for IsobusAddress {
+    fn to_bits(self) -> u64 {
+        self.0 as u64
+    }
+    fn from_bits(bits: u64) -> Self {
+        IsobusAddress(bits as u8)
+    }
+}
+
+impl BitField for PGN {
+    fn to_bits(self) -> u64 {
+        self.as_u32() as u64
+    }
+    fn from_bits(bits: u64) -> Self {
+        PGN::new(bits as u32)
+    }
+}
+
+/// Write `len` bits of the value at the bit offset, the least significant byte first.
+/// Bit 0 is the least significant bit of byte 0.
+pub fn write_bits(data: &mut [u8], offset: usize, len: usize, value: u64) {
+    for i in 0..len {
+        let position = offset + i;
+        let mask = 1 << (position % 8);
+        if (value >> i) & 1 == 1 {
+            data[position / 8] |= mask;
+        } else {
+            data[position / 8] &= !mask;
+        }
+    }
+}
+
+/// Read `len` bits at the bit offset, the least significant byte first.
+pub fn read_bits(data: &[u8], offset: usize, len: usize) -> u64 {
+    (0..len).fold(0, |value, i| {
+        let position = offset + i;
+        let bit = (data[position / 8] >> (position % 8)) & 1;
+        value | ((bit as u64) << i)
+    })
+}
+
+/// Define a message with a fixed length and implement [`IsobusMessage`] for it.
+/// Each field is stored at a bit offset with a length in bits, the unused bits are sent as 1.
+/// The field types implement [`BitField`].
+/// Messages sharing a PGN, like the VT functions of ISO 11783-6, set the `function_code`
+/// sent in the first byte, their fields start at bit 8.
+///
+/// ```
+/// use open_isobus::{isobus_message, iso_11783_3::PGN};
+///
+/// isobus_message! {
+///     /// Working set master message
+///     pub struct WorkingSetMaster {
+///         pgn: PGN::new(0xFE0D),
+///         priority: 7,
+///         length: 8,
+///         /// The number of members of the working set, including the master.
+///         pub number_of_members: u8 = [0, 8],
+///     }
+/// }
+/// ```
+///
+/// A field outside of the message does not compile.
+///
+/// ```compile_fail
+/// use open_isobus::{isobus_message, iso_11783_3::PGN};
+///
+/// isobus_message! {
+///     pub struct Overflow {
+///         pgn: PGN::new(0xFF00),
+///         priority: 6,
+///         length: 2,
+///         pub value: u16 = [8, 16],
+///     }
+/// }
+/// ```
+///
+/// Neither does a field overlapping the function code.
+///
+/// ```compile_fail
+/// use open_isobus::{isobus_message, iso_11783_3::PGN};
+///
+/// isobus_message! {
+///     pub struct Overlap {
+///         pgn: PGN::new(0xE600),
+///         function_code: 1,
+///         priority: 5,
+///         length: 8,
+///         pub value: u8 = [0, 8],
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! isobus_message {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            pgn: $pgn:expr,
+            $(function_code: $function_code:expr,)?
+            priority: $priority:expr,
+            length: $length:expr,
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident: $ty:ty = [$offset:expr, $bits:expr]
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::message::IsobusMessage for $name {
+            const PGN: $crate::iso_11783_3::PGN = $pgn;
+            const PRIORITY: u8 = $priority;
+
+            fn encode(&self) -> $crate::message::vec::Vec<u8> {
+                #[allow(unused_mut)]
+                let mut data = $crate::message::vec![0xFF; $length];
+                $(data[0] = $function_code;)?
+                $(
+                    $crate::message::write_bits(
+                        &mut data,
+                        $offset,
+                        $bits,
+                        $crate::message::BitField::to_bits(self.$field),
+                    );
+                )*
+                data
+            }
+
+            fn decode(data: &[u8]) -> Result<Self, $crate::message::DecodeError> {
+                if data.len() < $length {
+                    return Err($crate::message::DecodeError::TooShort {
+                        expected: $length,
+                        actual: data.len(),
+                    });
+                }
+                $(
+                    if data[0] != $function_code {
+                        return Err($crate::message::DecodeError::UnexpectedFunctionCode(data[0]));
+                    }
+                )?
+                Ok(Self {
+                    $(
+                        $field: $crate::message::BitField::from_bits(
+                            $crate::message::read_bits(data, $offset, $bits),
+                        ),
+                    )*
+                })
+            }
+        }
+
+        #[allow(clippy::int_plus_one, unused_variables)]
+        const _: () = {
+            // The function code is sent in the first byte.
+            let function_code: &[u8] = &[$($function_code)?];
+            let first_bit = if function_code.is_empty() { 0 } else { 8 };
+            $(
+                assert!(
+                    $offset + $bits <= $length * 8,
+                    concat!(
+                        "`",
+                        stringify!($name),
+                        "::",
+                        stringify!($field),
+                        "` does not fit in the message"
+                    ),
+                );
+                assert!(
+                    $offset >= first_bit,
+                    concat!(
+                        "`",
+                        stringify!($name),
+                        "::",
+                        stringify!($field),
+                        "` overlaps the function code"
+                    ),
+                );
+            )*
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        iso_11783_3::{Acknowledgement, AcknowledgementType, PGN},
+        iso_11783_5::{CommandedAddress, Name},
+        iso_11783_6::{
+            pdu::{
+                ChangeActiveMaskResponse, ChangeNumericValueResponse, GetHardwareMessage,
+                GetMemoryMessage, KeyActivationCode, SoftKeyActivationMessage, VTBusyCode,
+                VTStatusMessage,
+            },
+            MessageType,
+        },
+        iso_11783_7::WorkingSetMaster,
+        isobus::IsobusAddress,
+    };
+
+    use super::{DecodeError, IsobusMessage, Vec};
+
+    isobus_message! {
+        struct Packed {
+            pgn: PGN::new(0xFF00),
+            priority: 6,
+            length: 8,
+            state: u8 = [0, 2],
+            enabled: bool = [2, 1],
+            speed: u16 = [8, 16],
+            distance: u32 = [24, 32],
+        }
+    }
+
+    #[test]
+    fn packs_fields_at_bit_offsets() {
+        let message = Packed {
+            state: 2,
+            enabled: true,
+            speed: 0x1234,
+            distance: 0xAABBCCDD,
+        };
+        let data = message.encode();
+        assert_eq!(
+            data,
+            [0b1111_1110, 0x34, 0x12, 0xDD, 0xCC, 0xBB, 0xAA, 0xFF]
+        );
+        assert_eq!(Packed::decode(&data), Ok(message));
+
+        let pdu = message.to_pdu(IsobusAddress::GLOBAL, IsobusAddress(128));
+        assert_eq!(pdu.priority(), 6);
+        assert_eq!(Packed::from_pdu(&pdu), Ok(message));
+
+        assert_eq!(
+            Packed::decode(&data[..4]),
+            Err(DecodeError::TooShort {
+                expected: 8,
+                actual: 4
+            })
+        );
+    }
+
+    fn round_trip<M: IsobusMessage + PartialEq + core::fmt::Debug>(message: M) {
+        let pdu = message.to_pdu(IsobusAddress(38), IsobusAddress(128));
+        assert_eq!(pdu.pgn(), M::PGN);
+        assert_eq!(M::from_pdu(&pdu), Ok(message));
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        round_trip(Acknowledgement::new(
+            AcknowledgementType::Nack,
+            PGN::new(0xFEDA),
+            IsobusAddress(38),
+        ));
+        round_trip(WorkingSetMaster {
+            number_of_members: 3,
+        });
+        round_trip(CommandedAddress {
+            name: Name::from(0xA000_0000_0000_0002),
+            address: IsobusAddress(140),
+        });
+
+        let pdu = WorkingSetMaster {
+            number_of_members: 1,
+        }
+        .to_pdu(IsobusAddress::GLOBAL, IsobusAddress(128));
+        assert_eq!(
+            Acknowledgement::from_pdu(&pdu),
+            Err(DecodeError::UnexpectedPgn(PGN::WORKING_SET_MASTER))
+        );
+    }
+
+    #[test]
+    fn vt_functions_are_told_apart() {
+        round_trip(SoftKeyActivationMessage {
+            key_activation_code: KeyActivationCode::Held,
+            id: 1000.into(),
+            parent_id: 2000.into(),
+            key_number: 3,
+        });
+        round_trip(VTStatusMessage {
+            active_working_set: IsobusAddress(128),
+            data_alarm_mask: 1000.into(),
+            soft_key_mask: 2000.into(),
+            vt_busy_code: VTBusyCode::PARSING_OBJECT_POOL,
+            vt_function_code: 0xFF,
+        });
+        round_trip(GetMemoryMessage {
+            required_memory: 0x0012_3456,
+        });
+
+        let response = ChangeActiveMaskResponse {
+            mask_id: 1000.into(),
+            error_code: 0x10,
+        };
+        let pdu = response.to_pdu(IsobusAddress(128), IsobusAddress(38));
+        assert!(pdu.is_change_active_mask_response());
+        assert_eq!(
+            pdu.data::<8>(),
+            [173, 0xE8, 0x03, 0x10, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+        assert_eq!(ChangeActiveMaskResponse::from_pdu(&pdu), Ok(response));
+
+        let pdu = GetHardwareMessage {}.to_pdu(IsobusAddress(38), IsobusAddress(128));
+        assert_eq!(
+            GetMemoryMessage::from_pdu(&pdu),
+            Err(DecodeError::UnexpectedFunctionCode(
+                MessageType::GetHardware as u8
+            ))
+        );
+    }
+
+    #[test]
+    fn vt_messages_keep_their_byte_conversions() {
+        let status = VTStatusMessage {
+            active_working_set: IsobusAddress(128),
+            data_alarm_mask: 1000.into(),
+            soft_key_mask: 2000.into(),
+            vt_busy_code: VTBusyCode::empty(),
+            vt_function_code: 0xFF,
+        };
+        let data: Vec<u8> = status.into();
+        assert_eq!(data, status.encode());
+        assert_eq!(VTStatusMessage::from(data.as_slice()), status);
+        assert_eq!(
+            VTStatusMessage::from(&data[..4]),
+            VTStatusMessage::default()
+        );
+
+        // The error code of the response is not sent.
+        let data: Vec<u8> = ChangeNumericValueResponse {
+            id: 1000.into(),
+            error_code: 1,
+            value: 7,
+        }
+        .into();
+        assert_eq!(data, [168, 0xE8, 0x03, 0xFF, 7, 0, 0, 0]);
+    }
+}